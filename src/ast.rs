@@ -1,16 +1,34 @@
 use std::fmt::Formatter;
+use std::rc::Rc;
 
-#[derive(Debug, PartialEq)]
+use crate::ast_id::{ExprId, StmtId};
+use crate::token::Span;
+
+// Cloning copies the whole tree, except that function bodies, being behind
+// an Rc, are shared with the original rather than copied.
+#[derive(Debug, PartialEq, Clone)]
 pub struct AST {
     pub top: Vec<Stmt>,
 }
 
+impl AST {
+    // the innermost statement whose source includes the byte at offset
+    pub fn node_at(&self, offset: usize) -> Option<&Stmt> {
+        let mut found = self.top.iter().find(|stmt| stmt.span().contains(offset))?;
+        while let Some(inner) = found.statements().into_iter().find(|stmt| stmt.span().contains(offset)) {
+            found = inner;
+        }
+        Some(found)
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum Operator {
     OAdd,
     OSub,
     OMul,
     ODiv,
+    OMod,
     OLt,
     OLe,
     OGt,
@@ -24,153 +42,663 @@ pub enum Operator {
 
 impl std::fmt::Display for Operator {
     fn fmt(&self, formatter: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
-        use Operator::*;
-        formatter.write_str(
-            match self {
-                OAdd => "+",
-                OSub => "-",
-                OMul => "*",
-                ODiv => "/",
-                OLt => "<",
-                OLe => "<=",
-                OGt => ">",
-                OGe => ">=",
-                OEq => "==",
-                ONe => "!=",
-                ONot => "!",
-                OAnd => "&&",
-                OOr => "||",
-            }
-        )
+        formatter.write_str(self.symbol())
     }
 }
 
 use Operator::*;
 
-#[derive(Debug, PartialEq)]
+// everything the parser needs to know about an operator, in one place
+impl Operator {
+    // how the operator is written in Lox source
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            OAdd => "+",
+            OSub => "-",
+            OMul => "*",
+            ODiv => "/",
+            OMod => "%",
+            OLt => "<",
+            OLe => "<=",
+            OGt => ">",
+            OGe => ">=",
+            OEq => "==",
+            ONe => "!=",
+            OAnd => "and",
+            OOr => "or",
+            ONot => "!",
+        }
+    }
+
+    // how tightly the operator binds between two operands: higher first, so
+    // 1 + 2 * 3 is 1 + (2 * 3). Prefix operators bind tighter than all of
+    // these, so ONot, which is only ever prefix, has the highest.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            OOr => 1,
+            OAnd => 2,
+            OEq | ONe => 3,
+            OLt | OLe | OGt | OGe => 4,
+            OAdd | OSub => 5,
+            OMul | ODiv | OMod => 6,
+            ONot => 7,
+        }
+    }
+
+    // a - b - c is (a - b) - c; nothing groups the other way so far
+    pub fn is_right_associative(&self) -> bool {
+        false
+    }
+
+    // whether it can come before a single operand (-x, !x) and between two
+    pub fn is_unary(&self) -> bool {
+        matches!(self, OSub | ONot)
+    }
+
+    pub fn is_binary(&self) -> bool {
+        *self != ONot
+    }
+}
+
+// where a local variable lives: how many scopes out from the one using it,
+// and its position among that scope's declarations
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Slot {
+    pub depth: usize,
+    pub index: usize,
+}
+
+// Every node has the span of the source it was parsed from, and an id (see
+// ast_id).
+#[derive(Debug, PartialEq, Clone)]
 pub enum Expr {
     // Literal { value: Literal },
-    ENumber {value: String},
-    EString {value: String},
-    EBool {value: bool},
-    ENil,
-
-    EBinary {left: Box<Expr>, op: Operator, right: Box<Expr>},
-    EUnary { op: Operator, right: Box<Expr> },
-    EGrouping { expr: Box<Expr> },
-    EVariable {name: String},
-    EAssign {name: String, value: Box<Expr>}
+    ENumber {value: f64, id: ExprId, span: Span},
+    EString {value: String, id: ExprId, span: Span},
+    EBool {value: bool, id: ExprId, span: Span},
+    ENil {id: ExprId, span: Span},
+
+    // line is the operator's, for runtime errors
+    EBinary {left: Box<Expr>, op: Operator, right: Box<Expr>, line: usize, id: ExprId, span: Span},
+    EUnary { op: Operator, right: Box<Expr>, line: usize, id: ExprId, span: Span },
+    EGrouping { expr: Box<Expr>, id: ExprId, span: Span },
+    // slot is filled in by the resolver for variables in local scopes.
+    // Names are shared with the tokens they came from.
+    EVariable {name: Rc<str>, slot: Option<Slot>, id: ExprId, span: Span},
+    EAssign {name: Rc<str>, value: Box<Expr>, slot: Option<Slot>, id: ExprId, span: Span},
+    // line of the closing ')' so runtime errors can point at the call
+    ECall {callee: Box<Expr>, args: Vec<Expr>, line: usize, id: ExprId, span: Span},
+    EList {elements: Vec<Expr>, id: ExprId, span: Span},
+    EMap {pairs: Vec<(Expr, Expr)>, id: ExprId, span: Span},
+    // object.name, and object.name = value. line is the '.''s.
+    EGet {object: Box<Expr>, name: Rc<str>, line: usize, id: ExprId, span: Span},
+    ESet {object: Box<Expr>, name: Rc<str>, value: Box<Expr>, line: usize, id: ExprId, span: Span},
+    // the instance a method was called on, found like a variable named
+    // "this" (which the resolver places just outside the method's body)
+    EThis {slot: Option<Slot>, line: usize, id: ExprId, span: Span},
+    // super.method: the superclass's method, bound to this. slot is where
+    // "super" is, one scope further out than "this".
+    ESuper {method: Rc<str>, slot: Option<Slot>, line: usize, id: ExprId, span: Span},
 }
 
 use Expr::*;
 
 impl Expr {
-    pub fn number(value: impl Into<String>) -> Expr {
-        ENumber {value: value.into()}
+    pub fn number(value: f64) -> Expr {
+        ENumber {value, id: ExprId::default(), span: Span::default()}
     }
     pub fn string(value: impl Into<String>) -> Expr {
-        EString {value: value.into()}
+        EString {value: value.into(), id: ExprId::default(), span: Span::default()}
     }
 
     pub fn bool(value: bool) -> Expr {
-        EBool {value}
+        EBool {value, id: ExprId::default(), span: Span::default()}
     }
     pub fn nil() -> Expr {
-        ENil
+        ENil {id: ExprId::default(), span: Span::default()}
     }
 
     // the .into puts the value in the Box in this case
 
-    pub fn binary(left: Expr, op: Operator, right: Expr) -> Expr {
-        EBinary {left: left.into(), op, right: right.into()}
+    pub fn binary(left: Expr, op: Operator, right: Expr, line: usize) -> Expr {
+        EBinary {left: left.into(), op, right: right.into(), line, id: ExprId::default(), span: Span::default()}
     }
 
-    pub fn unary(op: Operator, right: Expr) -> Expr {
-        EUnary {op, right: right.into()}
+    pub fn unary(op: Operator, right: Expr, line: usize) -> Expr {
+        EUnary {op, right: right.into(), line, id: ExprId::default(), span: Span::default()}
     }
 
     pub fn grouping(expr: Expr) -> Expr {
-        EGrouping { expr: expr.into() }
+        EGrouping { expr: expr.into(), id: ExprId::default(), span: Span::default() }
+    }
+
+    pub fn variable(name: impl Into<Rc<str>>) -> Expr {
+        EVariable {name: name.into(), slot: None, id: ExprId::default(), span: Span::default()}
+    }
+
+    pub fn assign(name: impl Into<Rc<str>>, value: Expr) -> Expr {
+        EAssign {name: name.into(), value: value.into(), slot: None, id: ExprId::default(), span: Span::default()}
+    }
+
+    pub fn call(callee: Expr, args: Vec<Expr>, line: usize) -> Expr {
+        ECall {callee: callee.into(), args, line, id: ExprId::default(), span: Span::default()}
+    }
+
+    pub fn list(elements: Vec<Expr>) -> Expr {
+        EList {elements, id: ExprId::default(), span: Span::default()}
+    }
+
+    pub fn map(pairs: Vec<(Expr, Expr)>) -> Expr {
+        EMap {pairs, id: ExprId::default(), span: Span::default()}
     }
 
-    pub fn variable(name: impl Into<String>) -> Expr {
-        EVariable {name: name.into()}
+    pub fn get(object: Expr, name: impl Into<Rc<str>>, line: usize) -> Expr {
+        EGet {object: object.into(), name: name.into(), line, id: ExprId::default(), span: Span::default()}
     }
 
-    pub fn assign(name: impl Into<String>, value: Expr) -> Expr {
-        EAssign {name: name.into(), value: value.into()}
+    pub fn set(object: Expr, name: impl Into<Rc<str>>, value: Expr, line: usize) -> Expr {
+        ESet {object: object.into(), name: name.into(), value: value.into(), line, id: ExprId::default(), span: Span::default()}
+    }
+
+    pub fn this(line: usize) -> Expr {
+        EThis {slot: None, line, id: ExprId::default(), span: Span::default()}
+    }
+
+    pub fn super_(method: impl Into<Rc<str>>, line: usize) -> Expr {
+        ESuper {method: method.into(), slot: None, line, id: ExprId::default(), span: Span::default()}
+    }
+
+    // what the expression was parsed from. The constructors above leave it
+    // empty; the parser sets it.
+    pub fn span(&self) -> Span {
+        match self {
+            ENumber { span, .. }
+            | EString { span, .. }
+            | EBool { span, .. }
+            | ENil { span, .. }
+            | EBinary { span, .. }
+            | EUnary { span, .. }
+            | EGrouping { span, .. }
+            | EVariable { span, .. }
+            | EAssign { span, .. }
+            | ECall { span, .. }
+            | EList { span, .. }
+            | EMap { span, .. }
+            | EGet { span, .. }
+            | ESet { span, .. }
+            | EThis { span, .. }
+            | ESuper { span, .. } => *span,
+        }
+    }
+
+    pub fn with_span(mut self, new: Span) -> Expr {
+        match &mut self {
+            ENumber { span, .. }
+            | EString { span, .. }
+            | EBool { span, .. }
+            | ENil { span, .. }
+            | EBinary { span, .. }
+            | EUnary { span, .. }
+            | EGrouping { span, .. }
+            | EVariable { span, .. }
+            | EAssign { span, .. }
+            | ECall { span, .. }
+            | EList { span, .. }
+            | EMap { span, .. }
+            | EGet { span, .. }
+            | ESet { span, .. }
+            | EThis { span, .. }
+            | ESuper { span, .. } => *span = new,
+        }
+        self
+    }
+
+    pub fn id(&self) -> ExprId {
+        match self {
+            ENumber { id, .. }
+            | EString { id, .. }
+            | EBool { id, .. }
+            | ENil { id, .. }
+            | EBinary { id, .. }
+            | EUnary { id, .. }
+            | EGrouping { id, .. }
+            | EVariable { id, .. }
+            | EAssign { id, .. }
+            | ECall { id, .. }
+            | EList { id, .. }
+            | EMap { id, .. }
+            | EGet { id, .. }
+            | ESet { id, .. }
+            | EThis { id, .. }
+            | ESuper { id, .. } => *id,
+        }
+    }
+
+    pub fn set_id(&mut self, new: ExprId) {
+        match self {
+            ENumber { id, .. }
+            | EString { id, .. }
+            | EBool { id, .. }
+            | ENil { id, .. }
+            | EBinary { id, .. }
+            | EUnary { id, .. }
+            | EGrouping { id, .. }
+            | EVariable { id, .. }
+            | EAssign { id, .. }
+            | ECall { id, .. }
+            | EList { id, .. }
+            | EMap { id, .. }
+            | EGet { id, .. }
+            | ESet { id, .. }
+            | EThis { id, .. }
+            | ESuper { id, .. } => *id = new,
+        }
+    }
+
+    pub fn with_id(mut self, new: ExprId) -> Expr {
+        self.set_id(new);
+        self
+    }
+
+    // the expressions directly inside this one, in source order
+    pub fn subexpressions(&self) -> Vec<&Expr> {
+        match self {
+            ENumber { .. } | EString { .. } | EBool { .. } | ENil { .. } | EVariable { .. } | EThis { .. } | ESuper { .. } => vec![],
+            EBinary { left, right, .. } => vec![left, right],
+            EUnary { right, .. } => vec![right],
+            EGrouping { expr, .. } => vec![expr],
+            EAssign { value, .. } => vec![value],
+            ECall { callee, args, .. } => std::iter::once(&**callee).chain(args).collect(),
+            EList { elements, .. } => elements.iter().collect(),
+            EMap { pairs, .. } => pairs.iter().flat_map(|(key, value)| [key, value]).collect(),
+            EGet { object, .. } => vec![object],
+            ESet { object, value, .. } => vec![object, value],
+        }
+    }
+
+    // subexpressions, to change
+    pub fn subexpressions_mut(&mut self) -> Vec<&mut Expr> {
+        match self {
+            ENumber { .. } | EString { .. } | EBool { .. } | ENil { .. } | EVariable { .. } | EThis { .. } | ESuper { .. } => vec![],
+            EBinary { left, right, .. } => vec![left, right],
+            EUnary { right, .. } => vec![right],
+            EGrouping { expr, .. } => vec![expr],
+            EAssign { value, .. } => vec![value],
+            ECall { callee, args, .. } => std::iter::once(&mut **callee).chain(args).collect(),
+            EList { elements, .. } => elements.iter_mut().collect(),
+            EMap { pairs, .. } => pairs.iter_mut().flat_map(|(key, value)| [key, value]).collect(),
+            EGet { object, .. } => vec![object],
+            ESet { object, value, .. } => vec![object, value],
+        }
     }
 }
 
 // statements
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Stmt {
-    SPrint {expr: Expr},
-    SExpression{expr: Expr},
-    SVarDecl {name: String, initializer: Option<Expr>},
+    SPrint {expr: Expr, id: StmtId, span: Span},
+    SExpression{expr: Expr, id: StmtId, span: Span},
+    SVarDecl {name: Rc<str>, initializer: Option<Expr>, id: StmtId, span: Span},
+    SBlock {statements: Vec<Stmt>, id: StmtId, span: Span},
+    SIf {condition: Expr, then_branch: Box<Stmt>, else_branch: Option<Box<Stmt>>, id: StmtId, span: Span},
+    // the body is shared with every function value created from it.
+    // Arguments beyond params are collected into a list named rest. doc is
+    // the /// comment just above the declaration, if any, without the
+    // slashes; classes have one too.
+    SFunction {name: Rc<str>, params: Vec<Rc<str>>, rest: Option<Rc<str>>, body: Rc<Vec<Stmt>>, doc: Option<Rc<str>>, id: StmtId, span: Span},
+    SReturn {value: Option<Expr>, id: StmtId, span: Span},
+    // the first case equal to the subject runs; there is no fallthrough
+    SSwitch {subject: Expr, cases: Vec<(Expr, Vec<Stmt>)>, default: Option<Vec<Stmt>>, id: StmtId, span: Span},
+    STryCatch {try_body: Vec<Stmt>, catch_var: Option<Rc<str>>, catch_body: Vec<Stmt>, finally_body: Vec<Stmt>, id: StmtId, span: Span},
+    SThrow {value: Expr, id: StmtId, span: Span},
+    SImport {path: String, line: usize, id: StmtId, span: Span},
+    SForIn {var: Rc<str>, iterable: Expr, body: Box<Stmt>, id: StmtId, span: Span},
+    // a C-style for loop is one of these, in a block with its initializer
+    SWhile {condition: Expr, body: Box<Stmt>, id: StmtId, span: Span},
+    // methods are SFunctions; one named init is the initializer
+    // the superclass is a variable, if there is one
+    SClass {name: Rc<str>, superclass: Option<Expr>, methods: Vec<Stmt>, doc: Option<Rc<str>>, id: StmtId, span: Span},
 }
 
 
 // constructors
 impl Stmt {
     pub fn print(expr: Expr) -> Stmt {
-        Stmt::SPrint {expr}
+        Stmt::SPrint {expr, id: StmtId::default(), span: Span::default()}
     }
 
     pub fn expression(expr: Expr) -> Stmt {
-        Stmt::SExpression {expr}
+        Stmt::SExpression {expr, id: StmtId::default(), span: Span::default()}
     }
 
-    pub fn vardecl(name: impl Into<String>, initializer: Option<Expr>) -> Stmt {
-        Stmt::SVarDecl{name: name.into(), initializer}
+    pub fn vardecl(name: impl Into<Rc<str>>, initializer: Option<Expr>) -> Stmt {
+        Stmt::SVarDecl{name: name.into(), initializer, id: StmtId::default(), span: Span::default()}
+    }
+
+    pub fn block(statements: Vec<Stmt>) -> Stmt {
+        Stmt::SBlock {statements, id: StmtId::default(), span: Span::default()}
+    }
+
+    pub fn if_(condition: Expr, then_branch: Stmt, else_branch: Option<Stmt>) -> Stmt {
+        Stmt::SIf {condition, then_branch: then_branch.into(), else_branch: else_branch.map(Box::new), id: StmtId::default(), span: Span::default()}
+    }
+
+    pub fn function(name: impl Into<Rc<str>>, params: Vec<Rc<str>>, rest: Option<Rc<str>>, body: Vec<Stmt>) -> Stmt {
+        Stmt::SFunction {name: name.into(), params, rest, body: Rc::new(body), doc: None, id: StmtId::default(), span: Span::default()}
     }
-}
 
-pub fn format_op(o: &Operator) -> &'static str {
-    match o {
-        OAdd => "+",
-        OSub => "-",
-        OMul => "*",
-        ODiv => "/",
-        OLt => "<",
-        OLe => ">",
-        OGt => ">",
-        OGe => ">=",
-        OEq => "==",
-        ONe => "!=",
-        OAnd => "and",
-        OOr => "or",
-        ONot => "!",
+    pub fn return_(value: Option<Expr>) -> Stmt {
+        Stmt::SReturn {value, id: StmtId::default(), span: Span::default()}
+    }
+
+    pub fn switch(subject: Expr, cases: Vec<(Expr, Vec<Stmt>)>, default: Option<Vec<Stmt>>) -> Stmt {
+        Stmt::SSwitch {subject, cases, default, id: StmtId::default(), span: Span::default()}
+    }
+
+    pub fn try_catch(try_body: Vec<Stmt>, catch_var: Option<Rc<str>>, catch_body: Vec<Stmt>, finally_body: Vec<Stmt>) -> Stmt {
+        Stmt::STryCatch {try_body, catch_var, catch_body, finally_body, id: StmtId::default(), span: Span::default()}
+    }
+
+    pub fn throw(value: Expr) -> Stmt {
+        Stmt::SThrow {value, id: StmtId::default(), span: Span::default()}
+    }
+
+    pub fn import(path: impl Into<String>, line: usize) -> Stmt {
+        Stmt::SImport {path: path.into(), line, id: StmtId::default(), span: Span::default()}
+    }
+
+    pub fn for_in(var: impl Into<Rc<str>>, iterable: Expr, body: Stmt) -> Stmt {
+        Stmt::SForIn {var: var.into(), iterable, body: body.into(), id: StmtId::default(), span: Span::default()}
+    }
+
+    pub fn while_(condition: Expr, body: Stmt) -> Stmt {
+        Stmt::SWhile {condition, body: body.into(), id: StmtId::default(), span: Span::default()}
+    }
+
+    pub fn class(name: impl Into<Rc<str>>, superclass: Option<Expr>, methods: Vec<Stmt>) -> Stmt {
+        Stmt::SClass {name: name.into(), superclass, methods, doc: None, id: StmtId::default(), span: Span::default()}
+    }
+
+    // what the statement was parsed from, like Expr::span
+    pub fn span(&self) -> Span {
+        match self {
+            Stmt::SPrint { span, .. }
+            | Stmt::SExpression { span, .. }
+            | Stmt::SVarDecl { span, .. }
+            | Stmt::SBlock { span, .. }
+            | Stmt::SIf { span, .. }
+            | Stmt::SFunction { span, .. }
+            | Stmt::SReturn { span, .. }
+            | Stmt::SSwitch { span, .. }
+            | Stmt::STryCatch { span, .. }
+            | Stmt::SThrow { span, .. }
+            | Stmt::SImport { span, .. }
+            | Stmt::SForIn { span, .. }
+            | Stmt::SWhile { span, .. }
+            | Stmt::SClass { span, .. } => *span,
+        }
+    }
+
+    pub fn with_span(mut self, new: Span) -> Stmt {
+        match &mut self {
+            Stmt::SPrint { span, .. }
+            | Stmt::SExpression { span, .. }
+            | Stmt::SVarDecl { span, .. }
+            | Stmt::SBlock { span, .. }
+            | Stmt::SIf { span, .. }
+            | Stmt::SFunction { span, .. }
+            | Stmt::SReturn { span, .. }
+            | Stmt::SSwitch { span, .. }
+            | Stmt::STryCatch { span, .. }
+            | Stmt::SThrow { span, .. }
+            | Stmt::SImport { span, .. }
+            | Stmt::SForIn { span, .. }
+            | Stmt::SWhile { span, .. }
+            | Stmt::SClass { span, .. } => *span = new,
+        }
+        self
+    }
+
+    pub fn id(&self) -> StmtId {
+        match self {
+            Stmt::SPrint { id, .. }
+            | Stmt::SExpression { id, .. }
+            | Stmt::SVarDecl { id, .. }
+            | Stmt::SBlock { id, .. }
+            | Stmt::SIf { id, .. }
+            | Stmt::SFunction { id, .. }
+            | Stmt::SReturn { id, .. }
+            | Stmt::SSwitch { id, .. }
+            | Stmt::STryCatch { id, .. }
+            | Stmt::SThrow { id, .. }
+            | Stmt::SImport { id, .. }
+            | Stmt::SForIn { id, .. }
+            | Stmt::SWhile { id, .. }
+            | Stmt::SClass { id, .. } => *id,
+        }
+    }
+
+    pub fn set_id(&mut self, new: StmtId) {
+        match self {
+            Stmt::SPrint { id, .. }
+            | Stmt::SExpression { id, .. }
+            | Stmt::SVarDecl { id, .. }
+            | Stmt::SBlock { id, .. }
+            | Stmt::SIf { id, .. }
+            | Stmt::SFunction { id, .. }
+            | Stmt::SReturn { id, .. }
+            | Stmt::SSwitch { id, .. }
+            | Stmt::STryCatch { id, .. }
+            | Stmt::SThrow { id, .. }
+            | Stmt::SImport { id, .. }
+            | Stmt::SForIn { id, .. }
+            | Stmt::SWhile { id, .. }
+            | Stmt::SClass { id, .. } => *id = new,
+        }
+    }
+
+    pub fn with_id(mut self, new: StmtId) -> Stmt {
+        self.set_id(new);
+        self
+    }
+
+    // a function or class documented by new; anything else is unchanged
+    pub fn with_doc(mut self, new: Option<Rc<str>>) -> Stmt {
+        if let Stmt::SFunction { doc, .. } | Stmt::SClass { doc, .. } = &mut self {
+            *doc = new;
+        }
+        self
+    }
+
+    // the statements directly inside this one: bodies, branches and methods
+    pub fn statements(&self) -> Vec<&Stmt> {
+        match self {
+            Stmt::SBlock { statements, .. } => statements.iter().collect(),
+            Stmt::SIf { then_branch, else_branch, .. } => std::iter::once(&**then_branch).chain(else_branch.as_deref()).collect(),
+            Stmt::SFunction { body, .. } => body.iter().collect(),
+            Stmt::SSwitch { cases, default, .. } => {
+                cases.iter().flat_map(|(_, body)| body).chain(default.iter().flatten()).collect()
+            }
+            Stmt::STryCatch { try_body, catch_body, finally_body, .. } => {
+                try_body.iter().chain(catch_body).chain(finally_body).collect()
+            }
+            Stmt::SForIn { body, .. } | Stmt::SWhile { body, .. } => vec![body],
+            Stmt::SClass { methods, .. } => methods.iter().collect(),
+            _ => vec![],
+        }
+    }
+
+    // the expressions directly inside this one, not counting those in
+    // its statements
+    pub fn expressions(&self) -> Vec<&Expr> {
+        match self {
+            Stmt::SPrint { expr, .. } | Stmt::SExpression { expr, .. } => vec![expr],
+            Stmt::SVarDecl { initializer, .. } => initializer.iter().collect(),
+            Stmt::SIf { condition, .. } => vec![condition],
+            Stmt::SReturn { value, .. } => value.iter().collect(),
+            Stmt::SSwitch { subject, cases, .. } => std::iter::once(subject).chain(cases.iter().map(|(value, _)| value)).collect(),
+            Stmt::SThrow { value, .. } => vec![value],
+            Stmt::SForIn { iterable, .. } => vec![iterable],
+            Stmt::SWhile { condition, .. } => vec![condition],
+            Stmt::SClass { superclass, .. } => superclass.iter().collect(),
+            _ => vec![],
+        }
+    }
+
+    // statements, to change. A function body shared with a function value
+    // or another AST is copied first.
+    pub fn statements_mut(&mut self) -> Vec<&mut Stmt> {
+        match self {
+            Stmt::SBlock { statements, .. } => statements.iter_mut().collect(),
+            Stmt::SIf { then_branch, else_branch, .. } => {
+                std::iter::once(&mut **then_branch).chain(else_branch.as_deref_mut()).collect()
+            }
+            Stmt::SFunction { body, .. } => Rc::make_mut(body).iter_mut().collect(),
+            Stmt::SSwitch { cases, default, .. } => {
+                cases.iter_mut().flat_map(|(_, body)| body).chain(default.iter_mut().flatten()).collect()
+            }
+            Stmt::STryCatch { try_body, catch_body, finally_body, .. } => {
+                try_body.iter_mut().chain(catch_body).chain(finally_body).collect()
+            }
+            Stmt::SForIn { body, .. } | Stmt::SWhile { body, .. } => vec![body],
+            Stmt::SClass { methods, .. } => methods.iter_mut().collect(),
+            _ => vec![],
+        }
+    }
+
+    // expressions, to change
+    pub fn expressions_mut(&mut self) -> Vec<&mut Expr> {
+        match self {
+            Stmt::SPrint { expr, .. } | Stmt::SExpression { expr, .. } => vec![expr],
+            Stmt::SVarDecl { initializer, .. } => initializer.iter_mut().collect(),
+            Stmt::SIf { condition, .. } => vec![condition],
+            Stmt::SReturn { value, .. } => value.iter_mut().collect(),
+            Stmt::SSwitch { subject, cases, .. } => {
+                std::iter::once(subject).chain(cases.iter_mut().map(|(value, _)| value)).collect()
+            }
+            Stmt::SThrow { value, .. } => vec![value],
+            Stmt::SForIn { iterable, .. } => vec![iterable],
+            Stmt::SWhile { condition, .. } => vec![condition],
+            Stmt::SClass { superclass, .. } => superclass.iter_mut().collect(),
+            _ => vec![],
+        }
     }
 }
 
 pub fn format_expr(e: &Expr) -> String {
     match e {
-        ENumber { value } => format!("{}", value),
-        EString { value } => format!("\"{}\"", value),
-        EBool { value } => format!("{}", value),
-        ENil => "nil".to_string(),
-        EVariable { name } => format!("\"{}\"", name),
-        EBinary { left, op, right } => {
-            format!("({} {} {})", format_op(op), format_expr(left), format_expr(right))
+        ENumber { value, .. } => value.to_string(),
+        EString { value, .. } => format!("\"{}\"", value),
+        EBool { value, .. } => format!("{}", value),
+        ENil { .. } => "nil".to_string(),
+        EVariable { name, .. } => format!("\"{}\"", name),
+        EBinary { left, op, right, .. } => {
+            format!("({} {} {})", op.symbol(), format_expr(left), format_expr(right))
         },
-        EUnary { op, right } => {
-            format!("({}{})", format_op(op), format_expr(right))
+        EUnary { op, right, .. } => {
+            format!("({}{})", op.symbol(), format_expr(right))
         },
-        EGrouping { expr } => format!("group ({})", format_expr(expr) ),
-        EAssign { name, value } => {
+        EGrouping { expr, .. } => format!("group ({})", format_expr(expr) ),
+        EAssign { name, value, .. } => {
             format!("(assign {} {})", name, format_expr(value))
         }
+        ECall { callee, args, .. } => {
+            let args: Vec<String> = args.iter().map(format_expr).collect();
+            format!("(call {} {})", format_expr(callee), args.join(" "))
+        }
+        EList { elements, .. } => {
+            let elements: Vec<String> = elements.iter().map(format_expr).collect();
+            format!("[{}]", elements.join(", "))
+        }
+        EMap { pairs, .. } => {
+            let pairs: Vec<String> = pairs
+                .iter()
+                .map(|(key, value)| format!("{}: {}", format_expr(key), format_expr(value)))
+                .collect();
+            format!("{{{}}}", pairs.join(", "))
+        }
+        EGet { object, name, .. } => format!("(get {} {})", format_expr(object), name),
+        ESet { object, name, value, .. } => {
+            format!("(set {} {} {})", format_expr(object), name, format_expr(value))
+        }
+        EThis { .. } => "this".to_string(),
+        ESuper { method, .. } => format!("super.{method}"),
 
     }
 }
 
-pub fn main() {
-    let expression = Expr::binary(
-        Expr::unary(Operator::OSub, Expr::number("123")),
-        OMul,
-        Expr::grouping(Expr::number("45.67")),
-    );
-    println!("{}", format_expr(&expression));
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_expr() {
+        let expression = Expr::binary(
+            Expr::unary(Operator::OSub, Expr::number(123.0), 1),
+            OMul,
+            Expr::grouping(Expr::number(45.67)),
+            1,
+        );
+        assert_eq!(format_expr(&expression), "(* (-123) group (45.67))");
+    }
+
+    #[test]
+    fn test_symbol() {
+        let cases = [
+            (OAdd, "+"),
+            (OSub, "-"),
+            (OMul, "*"),
+            (ODiv, "/"),
+            (OMod, "%"),
+            (OLt, "<"),
+            (OLe, "<="),
+            (OGt, ">"),
+            (OGe, ">="),
+            (OEq, "=="),
+            (ONe, "!="),
+            (OAnd, "and"),
+            (OOr, "or"),
+            (ONot, "!"),
+        ];
+        for (op, text) in cases {
+            assert_eq!(op.symbol(), text);
+            assert_eq!(op.to_string(), text);
+        }
+    }
+
+    #[test]
+    fn test_precedence() {
+        // loosest first, as in the grammar
+        let levels = [vec![OOr], vec![OAnd], vec![OEq, ONe], vec![OLt, OLe, OGt, OGe], vec![OAdd, OSub], vec![OMul, ODiv, OMod]];
+        for (n, level) in levels.iter().enumerate() {
+            for op in level {
+                assert_eq!(op.precedence() as usize, n + 1, "{op}");
+                assert!(op.is_binary() && !op.is_right_associative(), "{op}");
+                assert!(op.precedence() < ONot.precedence());
+            }
+        }
+        assert!(ONot.is_unary() && !ONot.is_binary());
+        assert!(OSub.is_unary() && !OAdd.is_unary());
+    }
+
+    #[test]
+    fn test_clone() {
+        let sum = Expr::binary(Expr::number(1.0), OAdd, Expr::number(2.0), 1);
+        let body = Rc::new(vec![Stmt::print(Expr::grouping(sum.clone()))]);
+        let function = Stmt::SFunction { name: "f".into(), params: vec![], rest: None, body, doc: None, id: StmtId::default(), span: Span::default() };
+        let ast = AST { top: vec![function, Stmt::expression(sum)] };
+        let mut copy = ast.clone();
+        assert_eq!(copy, ast);
+        // changing the copy leaves the original be
+        let Stmt::SExpression { expr, .. } = &mut copy.top[1] else { unreachable!() };
+        *expr = Expr::number(3.0);
+        assert_ne!(copy, ast);
+        assert!(matches!(&ast.top[1], Stmt::SExpression { expr: EBinary { .. }, .. }));
+        // but function bodies are shared
+        let (Stmt::SFunction { body: original, .. }, Stmt::SFunction { body: copied, .. }) = (&ast.top[0], &copy.top[0]) else {
+            unreachable!()
+        };
+        assert!(Rc::ptr_eq(original, copied));
+    }
+}
@@ -0,0 +1,137 @@
+// Numbers that tell the nodes of one AST apart.
+//
+// Every Expr and Stmt has an id. The parser gives each node the next one as
+// it's made (see Parser::next_id), so no two in a parsed AST are the same;
+// the constructors in ast leave it at 0, meaning none yet. A tree built or
+// changed by hand can be numbered with assign_ids.
+//
+// Unlike a node's address, an id stays the same when the AST is moved or
+// cloned, so it's what to key tables about nodes by (see Coverage). Ids are
+// only unique within one AST: two programs both have a statement 1.
+use crate::ast::{Expr, Stmt, AST};
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Default, PartialOrd, Ord)]
+pub struct ExprId(pub u32);
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Default, PartialOrd, Ord)]
+pub struct StmtId(pub u32);
+
+// #n, short enough not to crowd the --ast output
+impl std::fmt::Debug for ExprId {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(formatter, "#{}", self.0)
+    }
+}
+
+impl std::fmt::Debug for StmtId {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(formatter, "#{}", self.0)
+    }
+}
+
+// number every node in ast from 1, statements and expressions separately,
+// in source order with each node before what's inside it
+pub fn assign_ids(ast: &mut AST) {
+    let mut numbering = Numbering { next_expr: 1, next_stmt: 1 };
+    for stmt in &mut ast.top {
+        numbering.statement(stmt);
+    }
+}
+
+struct Numbering {
+    next_expr: u32,
+    next_stmt: u32,
+}
+
+impl Numbering {
+    fn statement(&mut self, stmt: &mut Stmt) {
+        stmt.set_id(StmtId(self.next_stmt));
+        self.next_stmt += 1;
+        for expr in stmt.expressions_mut() {
+            self.expression(expr);
+        }
+        for inner in stmt.statements_mut() {
+            self.statement(inner);
+        }
+    }
+
+    fn expression(&mut self, expr: &mut Expr) {
+        expr.set_id(ExprId(self.next_expr));
+        self.next_expr += 1;
+        for inner in expr.subexpressions_mut() {
+            self.expression(inner);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use crate::reader::Source;
+    use crate::tokenize::tokenize;
+
+    const PROGRAM: &str = "
+        var x = [1, {\"a\": 2}];
+        fun f(n, ...rest) { if (n > 0) return f(n - 1); else { print -n; } }
+        class A < B { init() { this.y = super.z; } }
+        for (i = 0; i < 3; i = i + 1) switch (i) { case 1: print i; default: x = i; }
+        for (e in x) while (false) try { throw e; } catch (err) { print err; } finally { f(1); }
+        import \"m.lox\";";
+
+    fn ids(ast: &AST) -> (Vec<StmtId>, Vec<ExprId>) {
+        let (mut stmt_ids, mut expr_ids) = (Vec::new(), Vec::new());
+        let mut statements: Vec<&Stmt> = ast.top.iter().collect();
+        while let Some(stmt) = statements.pop() {
+            stmt_ids.push(stmt.id());
+            let mut exprs = stmt.expressions();
+            while let Some(expr) = exprs.pop() {
+                expr_ids.push(expr.id());
+                exprs.extend(expr.subexpressions());
+            }
+            statements.extend(stmt.statements());
+        }
+        (stmt_ids, expr_ids)
+    }
+
+    // every id set and none used twice
+    fn assert_distinct(mut ids: Vec<u32>) {
+        let count = ids.len();
+        assert!(!ids.contains(&0));
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), count);
+    }
+
+    #[test]
+    fn test_parser_ids() {
+        let ast = parse(&tokenize(Source::from(PROGRAM)).unwrap()).unwrap();
+        let (stmt_ids, expr_ids) = ids(&ast);
+        assert_distinct(stmt_ids.iter().map(|id| id.0).collect());
+        assert_distinct(expr_ids.iter().map(|id| id.0).collect());
+        // the same when moved or cloned
+        let moved = Box::new(ast.clone());
+        assert_eq!(ids(&moved), ids(&ast));
+    }
+
+    #[test]
+    fn test_assign_ids() {
+        let mut ast = AST {
+            top: vec![
+                Stmt::print(Expr::binary(Expr::number(1.0), crate::ast::Operator::OAdd, Expr::number(2.0), 1)),
+                Stmt::block(vec![Stmt::expression(Expr::variable("x"))]),
+            ],
+        };
+        assert_eq!(ast.top[0].id(), StmtId(0));
+        assign_ids(&mut ast);
+        let (stmt_ids, expr_ids) = ids(&ast);
+        assert_eq!(stmt_ids, [StmtId(2), StmtId(3), StmtId(1)]);
+        assert_eq!(expr_ids, [ExprId(4), ExprId(1), ExprId(3), ExprId(2)]);
+        // a parsed AST renumbered the same way
+        let mut ast = parse(&tokenize(Source::from(PROGRAM)).unwrap()).unwrap();
+        assign_ids(&mut ast);
+        let (stmt_ids, expr_ids) = ids(&ast);
+        assert_distinct(stmt_ids.iter().map(|id| id.0).collect());
+        assert_distinct(expr_ids.iter().map(|id| id.0).collect());
+    }
+}
@@ -0,0 +1,112 @@
+// Checking the syntax of many files at once, for linting a whole project.
+//
+// Each file is read, tokenized, parsed and resolved on one of a few worker threads.
+// Nothing is evaluated. Tokens and ASTs share their strings through Rc, so
+// they stay on the thread that made them; only the messages come back.
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::{parser, reader, resolver, tokenize};
+
+// what was wrong with one file, if anything
+#[derive(Debug, PartialEq)]
+pub struct FileReport {
+    pub path: PathBuf,
+    // one message per problem, like "Line 2: Syntax error: ...". An unreadable
+    // file has just the one.
+    pub errors: Vec<String>,
+}
+
+impl FileReport {
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+// parsing deeply nested code recurses deeply, so workers get more stack than
+// a thread's default
+const WORKER_STACK_SIZE: usize = 16 * 1024 * 1024;
+
+// a report for each path, in the same order as paths
+pub fn check_files(paths: &[PathBuf]) -> Vec<FileReport> {
+    let workers = std::thread::available_parallelism().map_or(1, |n| n.get()).min(paths.len());
+    let next = AtomicUsize::new(0);
+    let reports = Mutex::new(Vec::with_capacity(paths.len()));
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            std::thread::Builder::new()
+                .stack_size(WORKER_STACK_SIZE)
+                .spawn_scoped(scope, || {
+                    // each worker takes the next unchecked file until there are none
+                    loop {
+                        let n = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(path) = paths.get(n) else { break };
+                        let report = check_file(path);
+                        reports.lock().unwrap().push((n, report));
+                    }
+                })
+                .unwrap();
+        }
+    });
+    // put them back in input order, however the workers finished
+    let mut reports = reports.into_inner().unwrap();
+    reports.sort_by_key(|(n, _)| *n);
+    reports.into_iter().map(|(_, report)| report).collect()
+}
+
+fn check_file(path: &Path) -> FileReport {
+    let errors = match reader::read_source(&path.to_string_lossy()) {
+        Ok(source) => check_source(source),
+        Err(e) => vec![e.to_string()],
+    };
+    FileReport { path: path.to_path_buf(), errors }
+}
+
+fn check_source(source: reader::Source) -> Vec<String> {
+    let tokens = match tokenize::tokenize(source) {
+        Ok(tokens) => tokens,
+        Err(errors) => return errors.iter().map(|error| error.to_string()).collect(),
+    };
+    match parser::parse(&tokens).and_then(|mut ast| resolver::resolve(&mut ast)) {
+        Ok(()) => Vec::new(),
+        Err(parser::Error::Multiple(errors)) => errors.iter().map(|error| error.to_string()).collect(),
+        Err(error) => vec![error.to_string()],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_files() {
+        let dir = std::env::temp_dir().join(format!("lox-check-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut paths = Vec::new();
+        for n in 0..40 {
+            let path = dir.join(format!("{n}.lox"));
+            // every third file has two syntax errors
+            let contents = match n % 3 {
+                0 => format!("print {n};\nvar = 1;\nprint 2 2;\n"),
+                _ => format!("var x = {n};\nprint x;\n"),
+            };
+            std::fs::write(&path, contents).unwrap();
+            paths.push(path);
+        }
+        paths.push(dir.join("missing.lox"));
+        paths.push(dir.join("$.lox"));
+        std::fs::write(dir.join("$.lox"), "print $;").unwrap();
+
+        let reports = check_files(&paths);
+        let checked: Vec<&PathBuf> = reports.iter().map(|report| &report.path).collect();
+        assert_eq!(checked, paths.iter().collect::<Vec<_>>());
+        for (n, report) in reports[..40].iter().enumerate() {
+            assert_eq!(report.errors.len(), if n % 3 == 0 { 2 } else { 0 }, "{report:?}");
+        }
+        assert!(reports[0].errors[0].starts_with("Line 2: Syntax error"));
+        assert!(!reports[40].is_ok());
+        assert_eq!(reports[41].errors, ["Line 1: Unexpected character '$'"]);
+        assert_eq!(check_files(&[]), []);
+    }
+}
@@ -0,0 +1,275 @@
+// What the lox command line asked for.
+//
+// The binary turns its arguments into a Config and then does what it says;
+// anything else (a test, another front end) can build the same Config
+// from the same arguments.
+use crate::evaluate::InterpreterOptions;
+
+pub const USAGE: &str = "Usage: lox [options] [filename... | -e program]
+       lox [options] [--record file] [--replay file]
+       lox --check path...
+       lox --highlight filename
+       lox --help | --version";
+
+// USAGE, then every option
+pub const HELP: &str = "
+Run Lox programs, or start a prompt if there's nothing to run. The filename
+- means standard input, which is also run when a program is piped in.
+
+Options:
+  -e, --eval program    run program; an expression's value is printed
+  -c                    only check the syntax of the program
+  --check path...       check the syntax of files and of the .lox files in
+                        directories, in parallel
+  --highlight filename  print the file's syntax classes, one span per line
+  --lenient             allow arithmetic on mixed types
+  --strict              make warnings errors, and also declaring a name twice
+                        in a scope, using it there before it's declared, and
+                        assigning to a variable that was never declared
+  --trace-env           show the scopes at each statement
+  --profile             count statements and calls, reported to stderr
+  --stats               count what ran, reported to stderr
+  --coverage            list the lines of the file, marking those that ran,
+                        to stderr
+  --max-call-depth n    how deeply calls can nest before a stack overflow:
+                        200 unless given, up to 10000
+  --search-path dir     look in dir for imports not found beside the
+                        importing file; may be given more than once
+  --tokens              print the tokens to stderr before running
+  --ast                 print the syntax tree to stderr before running
+  --backend name        how to run programs: tree-walk (the only one)
+  --record file         append what worked at the prompt to file
+  --replay file         type file in at the prompt
+  --no-warn             don't print warnings about unused and shadowed
+                        variables
+  -Werror, --warn-error treat those warnings as errors
+  --no-color            don't color error messages
+  -h, --help            show this help
+  -V, --version         show the version";
+
+// the most --max-call-depth allows, since lox sets aside stack for every call
+// that's allowed
+pub const MAX_CALL_DEPTH: usize = 10_000;
+
+// the limit without --max-call-depth, lower than the interpreter's own to
+// keep the stack set aside for it small
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 200;
+
+// how programs are run. There's only the one way so far.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Backend {
+    #[default]
+    TreeWalk,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Config {
+    pub options: InterpreterOptions,
+    pub backend: Backend,
+    // DEFAULT_MAX_CALL_DEPTH if None (see Config::max_call_depth)
+    pub max_call_depth: Option<usize>,
+    // directories for imports to be looked for in, in order
+    pub search_path: Vec<String>,
+    // the program given with -e
+    pub eval: Option<String>,
+    pub filenames: Vec<String>,
+    pub no_color: bool,
+    // what to do with warnings: leave them out, or fail because of them
+    pub no_warn: bool,
+    pub warn_error: bool,
+    // --strict: warn_error, options.strict_scopes and strict_variables
+    pub strict: bool,
+    // print what the front end made of the program before running it
+    pub debug_tokens: bool,
+    pub debug_ast: bool,
+    // only check the syntax
+    pub check_only: bool,
+    // check the syntax of files and directories of them, in parallel
+    pub check: bool,
+    // list which lines of the file ran
+    pub coverage: bool,
+    // classify the file's text instead of running it
+    pub highlight: bool,
+    // the prompt's session: where to append it, and a file to read it from
+    pub record: Option<String>,
+    pub replay: Option<String>,
+    // print HELP or the version instead of doing anything
+    pub help: bool,
+    pub version: bool,
+}
+
+impl Config {
+    // how deeply calls can nest
+    pub fn max_call_depth(&self) -> usize {
+        self.max_call_depth.unwrap_or(DEFAULT_MAX_CALL_DEPTH)
+    }
+
+    // the arguments, without the program name. The error says what was wrong
+    // with them.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Config, String> {
+        let mut parsed = Config::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            // the value that has to follow arg
+            let mut value = |what: &str| args.next().ok_or_else(|| format!("{arg} needs {what}"));
+            match arg.as_str() {
+                "--lenient" => parsed.options.strict_arithmetic = false,
+                "--no-color" => parsed.no_color = true,
+                "--no-warn" => parsed.no_warn = true,
+                "-Werror" | "--warn-error" => parsed.warn_error = true,
+                "--strict" => {
+                    parsed.strict = true;
+                    parsed.warn_error = true;
+                    parsed.options.strict_variables = true;
+                    parsed.options.strict_scopes = true;
+                }
+                "--trace-env" => parsed.options.trace_env = true,
+                "--profile" => parsed.options.profile = true,
+                "--stats" => parsed.options.stats = true,
+                "--tokens" => parsed.debug_tokens = true,
+                "--ast" => parsed.debug_ast = true,
+                "-c" | "--compile-only" => parsed.check_only = true,
+                "--check" => parsed.check = true,
+                "--highlight" => parsed.highlight = true,
+                "--coverage" => parsed.coverage = true,
+                "-h" | "--help" => parsed.help = true,
+                "-V" | "--version" => parsed.version = true,
+                "-e" | "--eval" => parsed.eval = Some(value("a program")?),
+                "--record" => parsed.record = Some(value("a file")?),
+                "--replay" => parsed.replay = Some(value("a file")?),
+                "--search-path" => parsed.search_path.push(value("a directory")?),
+                "--max-call-depth" => {
+                    let depth = value("a depth")?;
+                    match depth.parse() {
+                        Ok(depth) if depth > MAX_CALL_DEPTH => {
+                            return Err(format!("--max-call-depth can be at most {MAX_CALL_DEPTH}, not {depth}"));
+                        }
+                        Ok(depth) if depth > 0 => parsed.max_call_depth = Some(depth),
+                        _ => return Err(format!("--max-call-depth needs a positive whole number, not {depth}")),
+                    }
+                }
+                "--backend" => {
+                    parsed.backend = match value("a backend")?.as_str() {
+                        "tree-walk" => Backend::TreeWalk,
+                        other => return Err(format!("Unknown backend {other}")),
+                    }
+                }
+                flag if flag.starts_with('-') && flag != "-" => return Err(format!("Unknown option {flag}")),
+                _ => parsed.filenames.push(arg),
+            }
+        }
+        if parsed.help || parsed.version {
+            return Ok(parsed);
+        }
+        if parsed.no_warn && parsed.strict {
+            return Err("--no-warn can't be combined with --strict".to_string());
+        }
+        if parsed.no_warn && parsed.warn_error {
+            return Err("--no-warn can't be combined with --warn-error".to_string());
+        }
+        if parsed.eval.is_some() && !parsed.filenames.is_empty() {
+            return Err("-e can't be combined with a filename".to_string());
+        }
+        if parsed.check_only && parsed.eval.is_none() && parsed.filenames.is_empty() {
+            return Err("-c needs something to check".to_string());
+        }
+        if parsed.check && (parsed.eval.is_some() || parsed.filenames.is_empty()) {
+            return Err("--check needs files or directories to check".to_string());
+        }
+        if parsed.highlight && (parsed.eval.is_some() || parsed.filenames.len() != 1) {
+            return Err("--highlight needs exactly one file".to_string());
+        }
+        if parsed.coverage && (parsed.eval.is_some() || parsed.filenames.len() != 1) {
+            return Err("--coverage needs exactly one file".to_string());
+        }
+        if (parsed.record.is_some() || parsed.replay.is_some()) && (parsed.eval.is_some() || !parsed.filenames.is_empty()) {
+            return Err("--record and --replay are only for the prompt".to_string());
+        }
+        Ok(parsed)
+    }
+}
+
+// what --version prints
+pub fn version() -> String {
+    format!("lox {}", env!("CARGO_PKG_VERSION"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<Config, String> {
+        Config::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse() {
+        let parsed = args(&["--lenient", "prog.lox"]).unwrap();
+        assert!(!parsed.options.strict_arithmetic);
+        assert!(!parsed.no_color);
+        assert!(!parsed.options.trace_env);
+        assert_eq!(args(&[]).unwrap(), Config::default());
+        assert!(args(&["--trace-env"]).unwrap().options.trace_env);
+        assert!(args(&["--profile", "prog.lox"]).unwrap().options.profile);
+        assert!(args(&["--stats", "prog.lox"]).unwrap().options.stats);
+        assert!(args(&["--no-color"]).unwrap().no_color);
+        assert!(args(&["-c", "prog.lox"]).unwrap().check_only);
+        assert!(args(&["--compile-only"]).is_err());
+        assert_eq!(parsed.filenames, ["prog.lox"]);
+        assert_eq!(args(&["-e", "print 1;"]).unwrap().eval.as_deref(), Some("print 1;"));
+        assert_eq!(args(&["-"]).unwrap().filenames, ["-"]);
+        assert_eq!(args(&["a.lox", "b.lox"]).unwrap().filenames, ["a.lox", "b.lox"]);
+        assert!(args(&["-e", "print 1;", "prog.lox"]).is_err());
+        assert_eq!(args(&["--eval"]), Err("--eval needs a program".to_string()));
+        assert_eq!(args(&["-x"]), Err("Unknown option -x".to_string()));
+        assert!(args(&["--check", "src", "prog.lox"]).unwrap().check);
+        assert!(args(&["--check"]).is_err());
+        assert!(args(&["--check", "-e", "print 1;"]).is_err());
+        assert!(args(&["--highlight", "prog.lox"]).unwrap().highlight);
+        assert!(args(&["--highlight"]).is_err());
+        assert!(args(&["--highlight", "a.lox", "b.lox"]).is_err());
+        let parsed = args(&["--record", "new.lox", "--replay", "old.lox"]).unwrap();
+        assert_eq!((parsed.record.as_deref(), parsed.replay.as_deref()), (Some("new.lox"), Some("old.lox")));
+        assert!(args(&["--record"]).is_err());
+        assert!(args(&["--replay", "old.lox", "prog.lox"]).is_err());
+        let parsed = args(&["--tokens", "--ast", "--backend", "tree-walk", "prog.lox"]).unwrap();
+        assert!(parsed.debug_tokens && parsed.debug_ast);
+        assert_eq!(parsed.backend, Backend::TreeWalk);
+        assert_eq!(args(&["--backend", "jit"]), Err("Unknown backend jit".to_string()));
+        assert_eq!(args(&["--max-call-depth", "50"]).unwrap().max_call_depth, Some(50));
+        assert_eq!(args(&[]).unwrap().max_call_depth, None);
+        assert_eq!(args(&[]).unwrap().max_call_depth(), 200);
+        assert_eq!(args(&["--max-call-depth", "50"]).unwrap().max_call_depth(), 50);
+        assert_eq!(args(&["--max-call-depth", "10000"]).unwrap().max_call_depth, Some(10000));
+        assert_eq!(
+            args(&["--max-call-depth", "10001"]),
+            Err("--max-call-depth can be at most 10000, not 10001".to_string())
+        );
+        for bad in ["0", "-5", "1.5", "many"] {
+            assert_eq!(
+                args(&["--max-call-depth", bad]),
+                Err(format!("--max-call-depth needs a positive whole number, not {bad}"))
+            );
+        }
+        assert!(args(&["--max-call-depth"]).is_err());
+        let parsed = args(&["--search-path", "lib", "--search-path", "/usr/share/lox", "prog.lox"]).unwrap();
+        assert_eq!(parsed.search_path, ["lib", "/usr/share/lox"]);
+        assert_eq!(args(&["--search-path"]), Err("--search-path needs a directory".to_string()));
+        assert!(args(&["--no-warn", "prog.lox"]).unwrap().no_warn);
+        assert!(args(&["-Werror", "prog.lox"]).unwrap().warn_error);
+        assert!(args(&["--warn-error", "prog.lox"]).unwrap().warn_error);
+        assert_eq!(
+            args(&["--no-warn", "-Werror", "prog.lox"]),
+            Err("--no-warn can't be combined with --warn-error".to_string())
+        );
+        let parsed = args(&["--strict", "prog.lox"]).unwrap();
+        assert!(parsed.strict && parsed.warn_error && parsed.options.strict_variables && parsed.options.strict_scopes);
+        assert_eq!(args(&["--strict", "--no-warn"]), Err("--no-warn can't be combined with --strict".to_string()));
+        assert!(args(&["--coverage", "prog.lox"]).unwrap().coverage);
+        assert_eq!(args(&["--coverage", "a.lox", "b.lox"]), Err("--coverage needs exactly one file".to_string()));
+        assert!(args(&["--coverage", "-e", "print 1;"]).is_err());
+        // asking for help is never a mistake
+        assert!(args(&["--check", "-h"]).unwrap().help);
+        assert!(args(&["-e", "1", "x.lox", "--version"]).unwrap().version);
+    }
+}
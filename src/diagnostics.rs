@@ -0,0 +1,133 @@
+// How errors look on stderr: ANSI colours when stderr is a terminal, plain
+// text when it isn't or when NO_COLOR or --no-color asks for that.
+use std::io::IsTerminal;
+
+use lox::lint::Warning;
+use lox::reader::Source;
+use lox::token::Span;
+
+use crate::Error;
+
+#[derive(Clone, Copy)]
+pub struct Style {
+    pub enabled: bool,
+}
+
+impl Style {
+    pub fn detect(no_color: bool) -> Style {
+        // https://no-color.org: set and not empty means no colour
+        let no_color_env = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+        Style { enabled: !no_color && !no_color_env && std::io::stderr().is_terminal() }
+    }
+
+    fn paint(&self, code: &str, text: &str) -> String {
+        if self.enabled {
+            format!("\x1b[{code}m{text}\x1b[0m")
+        } else {
+            text.to_string()
+        }
+    }
+
+    pub fn error(&self, text: &str) -> String {
+        self.paint("31", text)
+    }
+
+    pub fn warning(&self, text: &str) -> String {
+        self.paint("33", text)
+    }
+
+    pub fn bold(&self, text: &str) -> String {
+        self.paint("1", text)
+    }
+}
+
+pub fn render(err: &Error, style: Style) -> String {
+    match err {
+        Error::Read(e) => style.error(&e.to_string()),
+        Error::Tokenize(e) => style.error(&e.to_string()),
+        Error::Parse(e) => style.error(&e.to_string()),
+        Error::Evaluate(e) => style.error(&e.to_string()),
+        Error::Warnings(warnings) => {
+            let warnings: Vec<String> = warnings.iter().map(|warning| style.error(&warning.to_string())).collect();
+            warnings.join("\n")
+        }
+        Error::InFile { name, error } => format!("{}: {}", style.bold(name), render(error, style)),
+        Error::InSource { error, source } => match error.as_ref() {
+            Error::Evaluate(e) if let Some(span) = e.span() => {
+                format!("{}\n{}", render(error, style), underline(source, span, style))
+            }
+            _ => render(error, style),
+        },
+    }
+}
+
+// the line span starts on, numbered, with carets under the span (to the end
+// of the line, if it goes on past it):
+//
+//     3 | print (1 + "x") * 2;
+//       |        ^^^^^^^
+fn underline(source: &Source, span: Span, style: Style) -> String {
+    let (line, start) = source.line_col(span.start);
+    let text = source.line_text(line);
+    let end = match source.line_col(span.end) {
+        (end_line, end) if end_line == line => end,
+        _ => text.chars().count() + 1,
+    };
+    let number = line.to_string();
+    let carets = "^".repeat(end.saturating_sub(start).max(1));
+    format!(
+        "{number} | {text}\n{} | {}{}",
+        " ".repeat(number.len()),
+        " ".repeat(start - 1),
+        style.error(&carets)
+    )
+}
+
+pub fn report_errors(err: Error, style: Style) {
+    eprintln!("{}", render(&err, style));
+}
+
+// each warning on a line of its own, after the name of the source if it has one
+pub fn report_warnings(name: Option<&str>, warnings: &[Warning], style: Style) {
+    for warning in warnings {
+        match name {
+            Some(name) => eprintln!("{}: {}", style.bold(name), style.warning(&warning.to_string())),
+            None => eprintln!("{}", style.warning(&warning.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lox::evaluate;
+
+    #[test]
+    fn test_render() {
+        let err = Error::InFile {
+            name: "prog.lox".into(),
+            error: Box::new(Error::Evaluate(evaluate::Error::ZeroDivision { line: 2 })),
+        };
+        assert_eq!(render(&err, Style { enabled: false }), "prog.lox: Line 2: Division by zero");
+        assert_eq!(
+            render(&err, Style { enabled: true }),
+            "\x1b[1mprog.lox\x1b[0m: \x1b[31mLine 2: Division by zero\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn test_underline() {
+        let program = "var s = \"x\";\nprint (((1 + s) * 2) - 3) / 4;";
+        let error = evaluate::Interpreter::new().exec(program).unwrap_err();
+        let err = Error::InSource { error: Box::new(error.into()), source: Source::from(program) };
+        // the innermost of the three groupings, without its parentheses
+        assert_eq!(
+            render(&err, Style { enabled: false }),
+            "Line 2: Operands must be two numbers or two strings: 1 + \"x\" (number + string)\n\
+             2 | print (((1 + s) * 2) - 3) / 4;\n  |          ^^^^^"
+        );
+        // a span running onto the next line is underlined to the end of its first
+        let source = Source::from("print 1 +\n  nil;");
+        assert_eq!(underline(&source, Span::new(6, 15), Style { enabled: false }), "1 | print 1 +\n  |       ^^^");
+    }
+}
@@ -1,30 +1,286 @@
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
 use std::rc::Rc;
 
-pub struct Environment<V: Clone> {
+// Values are kept behind an Rc, so looking one up never copies it.
+// Assigning replaces the Rc rather than changing what it points to, so a
+// value someone has already looked up stays as it was.
+//
+// The global scope (the one without a parent) keeps its variables by name.
+// Other scopes keep them in the order they were declared, so that a
+// variable the resolver has found can be fetched by position (get_at) with
+// no name lookups at all.
+//
+// Names are Rc<str>s, normally the ones the tokenizer interned, so declaring
+// a variable shares its name rather than copying it.
+pub struct Environment<V> {
     parent: Option<Rc<Environment<V>>>,
-    vars: RefCell<HashMap<String, V>>,
+    vars: RefCell<HashMap<Rc<str>, Rc<V>>>,
+    slots: RefCell<Vec<(Rc<str>, Rc<V>)>>,
 }
 
-impl<V: Clone> Environment<V> {
+impl<V> Environment<V> {
 
     pub fn new(parent: Option<Rc<Environment<V>>>) -> Rc<Environment<V>> {
-        Rc::new(Environment{ parent, vars: HashMap::new().into()})
+        Rc::new(Environment{ parent, vars: HashMap::new().into(), slots: Vec::new().into()})
     }
 
-    pub fn declare(&self, name: &str, value: V) {
-        // declare a new variable
-        self.vars.borrow_mut().insert(name.into(), value);
+    pub fn declare(&self, name: impl Into<Rc<str>>, value: V) {
+        // declare a new variable. In a local scope, declaring a name again
+        // takes a new slot which hides the old one.
+        if self.parent.is_some() {
+            self.slots.borrow_mut().push((name.into(), Rc::new(value)));
+        } else {
+            self.vars.borrow_mut().insert(name.into(), Rc::new(value));
+        }
     }
 
-    pub fn lookup(&self, name: &str) -> Option<V> {
-        Some(self.vars.borrow().get(name)?.clone())
+    // the variables declared directly in this scope, sorted by name
+    pub fn entries(&self) -> Vec<(Rc<str>, Rc<V>)> {
+        let mut entries: Vec<(Rc<str>, Rc<V>)> = match &self.parent {
+            Some(_) => {
+                let slots = self.slots.borrow();
+                // only the latest of a name that was declared twice
+                slots
+                    .iter()
+                    .enumerate()
+                    .filter(|(n, (name, _))| !slots[n + 1..].iter().any(|(later, _)| later == name))
+                    .map(|(_, (name, value))| (name.clone(), value.clone()))
+                    .collect()
+            }
+            None => self.vars.borrow().iter().map(|(name, value)| (name.clone(), value.clone())).collect(),
+        };
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        entries
+    }
+
+    // how many scopes enclose this one: 0 for the global scope
+    pub fn depth(&self) -> usize {
+        match &self.parent {
+            Some(parent) => parent.depth() + 1,
+            None => 0,
+        }
+    }
+
+    // this scope, then the enclosing ones out to the global scope
+    pub fn chain(&self) -> impl Iterator<Item = &Environment<V>> {
+        std::iter::successors(Some(self), |environ| environ.parent.as_deref())
+    }
+
+    // the scope depth levels out from this one
+    fn ancestor(&self, depth: usize) -> Option<&Environment<V>> {
+        self.chain().nth(depth)
+    }
+
+    // position in this (local) scope of the latest declaration of name
+    fn slot_of(&self, name: &str) -> Option<usize> {
+        self.slots.borrow().iter().rposition(|(declared, _)| &**declared == name)
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<Rc<V>> {
+        // look in this scope first, then in the enclosing ones
+        match &self.parent {
+            Some(parent) => match self.slot_of(name) {
+                Some(slot) => Some(self.slots.borrow()[slot].1.clone()),
+                None => parent.lookup(name),
+            },
+            None => self.vars.borrow().get(name).cloned(),
+        }
+    }
+
+    // the variable in slot of the scope depth levels out, as the resolver
+    // worked out
+    pub fn get_at(&self, depth: usize, slot: usize) -> Option<Rc<V>> {
+        let scope = self.ancestor(depth)?;
+        let slots = scope.slots.borrow();
+        slots.get(slot).map(|(_, value)| value.clone())
     }
 
     pub fn assign(&self, name: &str, value: V) {
-        // change value of an *already declared* variable (name=value)
+        // change value of an *already declared* variable (name=value),
+        // searching outwards. Undeclared names end up as globals.
         // needs error checking
-        self.vars.borrow_mut().insert(name.into(), value);
+        match &self.parent {
+            Some(parent) => match self.slot_of(name) {
+                Some(slot) => self.slots.borrow_mut()[slot].1 = Rc::new(value),
+                None => parent.assign(name, value),
+            },
+            // only a new global needs its name copied
+            None => {
+                let mut vars = self.vars.borrow_mut();
+                match vars.get_mut(name) {
+                    Some(variable) => *variable = Rc::new(value),
+                    None => {
+                        vars.insert(name.into(), Rc::new(value));
+                    }
+                }
+            }
+        }
+    }
+
+    // assign to the variable get_at(depth, slot) would fetch, giving the
+    // value back if there's no such slot
+    pub fn assign_at(&self, depth: usize, slot: usize, value: V) -> Result<(), V> {
+        let Some(scope) = self.ancestor(depth) else { return Err(value) };
+        match scope.slots.borrow_mut().get_mut(slot) {
+            Some(variable) => {
+                variable.1 = Rc::new(value);
+                Ok(())
+            }
+            None => Err(value),
+        }
+    }
+}
+
+// every scope from this one out to the global one, each indented a little
+// further than the last:
+//
+//   scope 2: {"x": 3}
+//     scope 1: {"x": 2, "y": 1}
+//       scope 0: {"x": 1}
+impl<V: Debug> Debug for Environment<V> {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        for (n, environ) in self.chain().enumerate() {
+            if n > 0 {
+                writeln!(formatter)?;
+            }
+            let entries: Vec<String> =
+                environ.entries().iter().map(|(name, value)| format!("{name:?}: {value:?}")).collect();
+            write!(formatter, "{:indent$}scope {}: {{{}}}", "", environ.depth(), entries.join(", "), indent = 2 * n)?;
+        }
+        Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // global x, a; function scope x, y; block x
+    fn three_levels() -> Rc<Environment<i32>> {
+        let global = Environment::new(None);
+        global.declare("x", 1);
+        global.declare("a", 0);
+        let function = Environment::new(Some(global));
+        function.declare("y", 1);
+        function.declare("x", 2);
+        let block = Environment::new(Some(function));
+        block.declare("x", 3);
+        block
+    }
+
+    fn values(environ: &Environment<i32>) -> Vec<(String, i32)> {
+        environ.entries().into_iter().map(|(name, value)| (name.to_string(), *value)).collect()
+    }
+
+    #[test]
+    fn test_entries_and_depth() {
+        let block = three_levels();
+        let scopes: Vec<_> = block.chain().map(|environ| (environ.depth(), values(environ))).collect();
+        assert_eq!(
+            scopes,
+            vec![
+                (2, vec![("x".to_string(), 3)]),
+                (1, vec![("x".to_string(), 2), ("y".to_string(), 1)]),
+                (0, vec![("a".to_string(), 0), ("x".to_string(), 1)]),
+            ]
+        );
+        assert_eq!(block.lookup("x").as_deref(), Some(&3));
+        assert_eq!(block.lookup("a").as_deref(), Some(&0));
+    }
+
+    #[test]
+    fn test_slots() {
+        let block = three_levels();
+        // function scope: y in slot 0, x in slot 1
+        assert_eq!(block.get_at(1, 0).as_deref(), Some(&1));
+        assert_eq!(block.get_at(1, 1).as_deref(), Some(&2));
+        assert_eq!(block.get_at(0, 0).as_deref(), Some(&3));
+        assert_eq!(block.get_at(1, 2), None);
+        assert_eq!(block.get_at(5, 0), None);
+        assert_eq!(block.assign_at(1, 1, 20), Ok(()));
+        assert_eq!(block.assign_at(1, 7, 70), Err(70));
+        assert_eq!(block.chain().nth(1).unwrap().lookup("x").as_deref(), Some(&20));
+
+        // a second declaration hides the first, which is still in its slot
+        block.declare("x", 30);
+        assert_eq!(block.lookup("x").as_deref(), Some(&30));
+        assert_eq!(block.get_at(0, 0).as_deref(), Some(&3));
+        assert_eq!(values(&block), vec![("x".to_string(), 30)]);
+        block.assign("x", 31);
+        assert_eq!(block.get_at(0, 1).as_deref(), Some(&31));
+    }
+
+    #[test]
+    fn test_debug() {
+        assert_eq!(
+            format!("{:?}", three_levels()),
+            "scope 2: {\"x\": 3}\n  scope 1: {\"x\": 2, \"y\": 1}\n    scope 0: {\"a\": 0, \"x\": 1}"
+        );
+    }
+
+    #[test]
+    fn test_assign_shadowed() {
+        let block = three_levels();
+        block.assign("y", 10);
+        block.assign("x", 30);
+        let scopes: Vec<_> = block.chain().map(values).collect();
+        assert_eq!(scopes[0], vec![("x".to_string(), 30)]);
+        assert_eq!(scopes[1], vec![("x".to_string(), 2), ("y".to_string(), 10)]);
+    }
+
+    #[test]
+    fn test_assign_outer() {
+        let block = three_levels();
+        // from inside a block, to the nearest scope declaring the name
+        block.assign("a", 5);
+        block.assign("y", 6);
+        block.assign("new", 7);
+        let scopes: Vec<_> = block.chain().map(values).collect();
+        assert_eq!(scopes[0], vec![("x".to_string(), 3)]);
+        assert_eq!(scopes[1], vec![("x".to_string(), 2), ("y".to_string(), 6)]);
+        // an undeclared name becomes a global
+        assert_eq!(scopes[2], vec![("a".to_string(), 5), ("new".to_string(), 7), ("x".to_string(), 1)]);
+    }
+
+    #[test]
+    fn test_names_shared() {
+        let name: Rc<str> = "x".into();
+        let global = Environment::new(None);
+        let local = Environment::new(Some(global.clone()));
+        global.declare(name.clone(), 1);
+        local.declare(name.clone(), 2);
+        global.assign("x", 3);
+        assert_eq!(Rc::strong_count(&name), 3);
+        assert!(global.entries().iter().chain(local.entries().iter()).all(|(n, _)| Rc::ptr_eq(n, &name)));
+    }
+
+    // counts how often it's cloned
+    struct Counted(Rc<std::cell::Cell<usize>>);
+
+    impl Clone for Counted {
+        fn clone(&self) -> Counted {
+            self.0.set(self.0.get() + 1);
+            Counted(self.0.clone())
+        }
+    }
+
+    #[test]
+    fn test_lookup_doesnt_copy() {
+        let clones = Rc::new(std::cell::Cell::new(0));
+        let global = Environment::new(None);
+        global.declare("big", Counted(clones.clone()));
+        let inner = Environment::new(Some(global.clone()));
+        for _ in 0..1000 {
+            inner.lookup("big").unwrap();
+        }
+        assert_eq!(clones.get(), 0);
+
+        // assigning rebinds the name; what was looked up before is untouched
+        let before = global.lookup("big").unwrap();
+        inner.assign("big", Counted(Rc::new(std::cell::Cell::new(0))));
+        assert!(!Rc::ptr_eq(&before, &global.lookup("big").unwrap()));
+        assert_eq!(Rc::strong_count(&before), 1);
+    }
+}
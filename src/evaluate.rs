@@ -1,7 +1,14 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Formatter;
+use std::io::Write;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use crate::ast::{Expr, AST, Operator, Stmt};
-use crate::evaluate::LoxValue::LBoolean;
+use crate::profiler::{Coverage, ExecStats, Profiler};
+use crate::token::Span;
+use crate::{lint, natives, parser, reader, resolver, token, tokenize};
 
 // the goal of the evaluator is to convert the AST into a LoxValue.
 #[derive(Debug, PartialEq, Clone)]
@@ -9,169 +16,2649 @@ pub enum LoxValue{
     LNil,
     LBoolean(bool),
     LNumber(f64),
-    LString(String),
+    LString(Rc<str>),
+    LFunction(Rc<LoxFunction>),
+    LNative(Rc<NativeFunction>),
+    // a runtime error caught by try/catch, holding its message
+    LError(Rc<str>),
+    // lists are shared, not copied, when assigned or passed around
+    LList(Rc<RefCell<Vec<LoxValue>>>),
+    // maps are shared like lists. Keys must be hashable (see is_hashable).
+    LMap(Rc<RefCell<HashMap<LoxValue, LoxValue>>>),
+    LClass(Rc<LoxClass>),
+    LInstance(Rc<LoxInstance>),
+}
+
+// Values are cloned on every variable lookup and call, so every variant is
+// kept to one pointer (two for strings) and cloning a string only bumps a
+// reference count.
+const _: () = assert!(std::mem::size_of::<LoxValue>() <= 24);
+
+// HashMap keys need Eq. NaN breaks reflexivity, which only means a NaN key
+// can be stored but never found again.
+impl Eq for LoxValue {}
+
+impl std::hash::Hash for LoxValue {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            LoxValue::LBoolean(b) => b.hash(state),
+            // 0 == -0, so they must hash alike
+            LoxValue::LNumber(n) if *n == 0.0 => 0.0f64.to_bits().hash(state),
+            LoxValue::LNumber(n) => n.to_bits().hash(state),
+            LoxValue::LString(s) => s.hash(state),
+            // unhashable values only hash their type, which is consistent
+            // with any equality; they are kept out of maps anyway
+            _ => {}
+        }
+    }
 }
-pub type Output = ();
 type Environment = crate::environ::Environment<LoxValue>;
 
 impl LoxValue {
     pub fn is_truthy(&self) -> bool {
+        !matches!(self, LoxValue::LNil | LoxValue::LBoolean(false))
+    }
+
+    // the developer-facing form, echoed by the REPL and used inside lists.
+    // Unlike Display (what `print` shows), strings are quoted and escaped
+    // so "1" and 1 can be told apart. Anything else with a Lox literal
+    // (see to_lox_literal) shows as that.
+    pub fn repr(&self) -> String {
+        self.repr_inside(&NumberFormat::default(), &mut Vec::new())
+    }
+
+    // what `print` shows with numbers in format, those in lists and maps
+    // included
+    pub fn display_with(&self, format: &NumberFormat) -> String {
+        self.display_inside(format, &mut Vec::new())
+    }
+
+    // containing holds the lists and maps already being shown, which show as
+    // [...] or {...} inside themselves
+    fn repr_inside(&self, format: &NumberFormat, containing: &mut Vec<*const ()>) -> String {
+        match self {
+            LoxValue::LString(v) => {
+                let mut repr = String::with_capacity(v.len() + 2);
+                repr.push('"');
+                for c in v.chars() {
+                    match c {
+                        '"' => repr.push_str("\\\""),
+                        '\\' => repr.push_str("\\\\"),
+                        '\n' => repr.push_str("\\n"),
+                        '\r' => repr.push_str("\\r"),
+                        '\t' => repr.push_str("\\t"),
+                        c => repr.push(c),
+                    }
+                }
+                repr.push('"');
+                repr
+            }
+            LoxValue::LNumber(_) | LoxValue::LList(_) | LoxValue::LMap(_) => self.display_inside(format, containing),
+            other => to_lox_literal(other).unwrap_or_else(|| other.to_string()),
+        }
+    }
+
+    fn display_inside(&self, format: &NumberFormat, containing: &mut Vec<*const ()>) -> String {
+        let container = container_ptr(self);
+        match self {
+            LoxValue::LList(_) if containing.contains(&container) => return "[...]".to_string(),
+            LoxValue::LMap(_) if containing.contains(&container) => return "{...}".to_string(),
+            LoxValue::LList(_) | LoxValue::LMap(_) => {}
+            LoxValue::LNumber(n) => return format.format(*n),
+            other => return other.to_string(),
+        }
+        containing.push(container);
+        let shown = match self {
+            LoxValue::LList(elements) => {
+                let elements: Vec<String> = elements.borrow().iter().map(|element| element.repr_inside(format, containing)).collect();
+                format!("[{}]", elements.join(", "))
+            }
+            LoxValue::LMap(entries) => {
+                // sorted, since the HashMap's order isn't stable
+                let mut entries: Vec<String> = entries
+                    .borrow()
+                    .iter()
+                    .map(|(key, value)| format!("{}: {}", key.repr_inside(format, containing), value.repr_inside(format, containing)))
+                    .collect();
+                entries.sort();
+                format!("{{{}}}", entries.join(", "))
+            }
+            _ => unreachable!(),
+        };
+        containing.pop();
+        shown
+    }
+
+    // the doc comment of the function or class declaration that made this
+    pub fn doc(&self) -> Option<&Rc<str>> {
+        match self {
+            LoxValue::LFunction(function) => function.doc.as_ref(),
+            LoxValue::LClass(class) => class.doc.as_ref(),
+            _ => None,
+        }
+    }
+
+    // short, user-facing name of the value's type (for error messages)
+    pub fn type_name(&self) -> &'static str {
         match self {
-            LoxValue::LNil | LBoolean(false) => false,
-            _ => true,
+            LoxValue::LNil => "nil",
+            LoxValue::LBoolean(_) => "boolean",
+            LoxValue::LNumber(_) => "number",
+            LoxValue::LString(_) => "string",
+            LoxValue::LFunction(_) => "function",
+            LoxValue::LNative(_) => "native function",
+            LoxValue::LError(_) => "error",
+            LoxValue::LList(_) => "list",
+            LoxValue::LMap(_) => "map",
+            LoxValue::LClass(_) => "class",
+            LoxValue::LInstance(_) => "instance",
+        }
+    }
+
+    // whether the value can be a map key: mutable containers can't, as
+    // changing them would lose them in the map
+    pub fn is_hashable(&self) -> bool {
+        !matches!(self, LoxValue::LList(_) | LoxValue::LMap(_))
+    }
+}
+
+// a user-defined function together with the scope it was declared in
+pub struct LoxFunction {
+    pub name: Rc<str>,
+    pub params: Vec<Rc<str>>,
+    pub rest: Option<Rc<str>>,
+    pub body: Rc<Vec<Stmt>>,
+    pub closure: Rc<Environment>,
+    // a class's init method, which gives back the instance however it returns
+    pub is_initializer: bool,
+    // the declaration's doc comment, for doc()
+    pub doc: Option<Rc<str>>,
+    // which program the body was parsed from (see Interpreter::source)
+    pub source: usize,
+}
+
+impl LoxFunction {
+    // the method with `this` bound to instance, in a scope of its own
+    // between the closure and the body
+    fn bind(&self, instance: Rc<LoxInstance>) -> Rc<LoxFunction> {
+        let closure = Environment::new(Some(self.closure.clone()));
+        closure.declare("this", LoxValue::LInstance(instance));
+        Rc::new(LoxFunction {
+            name: self.name.clone(),
+            params: self.params.clone(),
+            rest: self.rest.clone(),
+            body: self.body.clone(),
+            closure,
+            is_initializer: self.is_initializer,
+            doc: self.doc.clone(),
+            source: self.source,
+        })
+    }
+
+    // line is the call's
+    fn check_arity(&self, got: usize, line: usize) -> Result<(), Error> {
+        let expected = self.params.len();
+        if got < expected || (got > expected && self.rest.is_none()) {
+            return Err(Error::ArityError { expected, got, name: self.name.to_string(), line });
+        }
+        Ok(())
+    }
+}
+
+// functions compare by identity, like in the book
+impl PartialEq for LoxFunction {
+    fn eq(&self, other: &LoxFunction) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl std::fmt::Debug for LoxFunction {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(formatter, "<fn {}>", self.name)
+    }
+}
+
+// Calling a class makes an instance of it and runs its init method, if it
+// has one, on the arguments.
+pub struct LoxClass {
+    pub name: Rc<str>,
+    pub superclass: Option<Rc<LoxClass>>,
+    pub methods: HashMap<Rc<str>, Rc<LoxFunction>>,
+    pub doc: Option<Rc<str>>,
+}
+
+impl LoxClass {
+    // the class's own method, or else the nearest superclass's
+    pub fn find_method(&self, name: &str) -> Option<&Rc<LoxFunction>> {
+        match self.methods.get(name) {
+            Some(method) => Some(method),
+            None => self.superclass.as_ref()?.find_method(name),
         }
     }
 }
 
+impl PartialEq for LoxClass {
+    fn eq(&self, other: &LoxClass) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl std::fmt::Debug for LoxClass {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(formatter, "<class {}>", self.name)
+    }
+}
+
+// An instance's fields are set by assigning to them. Looking up a property
+// finds a field first, then a method.
+pub struct LoxInstance {
+    pub class: Rc<LoxClass>,
+    pub fields: RefCell<HashMap<Rc<str>, LoxValue>>,
+}
+
+impl PartialEq for LoxInstance {
+    fn eq(&self, other: &LoxInstance) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl std::fmt::Debug for LoxInstance {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(formatter, "<{} instance>", self.class.name)
+    }
+}
+
+// a function implemented in Rust (see natives.rs)
+pub struct NativeFunction {
+    pub name: &'static str,
+    // how many arguments it accepts
+    pub arity: RangeInclusive<usize>,
+    pub function: NativeFn,
+}
+
+pub enum NativeFn {
+    // most natives only need their arguments
+    Plain(fn(&[LoxValue]) -> Result<LoxValue, Error>),
+    // natives like eval that run code in the interpreter calling them
+    WithInterpreter(fn(&mut Interpreter, &[LoxValue]) -> Result<LoxValue, Error>),
+}
+
+impl PartialEq for NativeFunction {
+    fn eq(&self, other: &NativeFunction) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl std::fmt::Debug for NativeFunction {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(formatter, "<native fn {}>", self.name)
+    }
+}
+
 impl std::fmt::Display for LoxValue {
     fn fmt(&self, formatter: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
-        use Operator::*;
         match self {
             LoxValue::LNil => formatter.write_str("nil"),
-            LoxValue::LBoolean(v) => formatter.write_str(&format!("{v}")),
-            LoxValue::LNumber(v) => formatter.write_str(&format!("{v}")),
+            LoxValue::LBoolean(v) => write!(formatter, "{v}"),
+            LoxValue::LNumber(v) => formatter.write_str(&lox_fmt_number(*v)),
             LoxValue::LString(v) => formatter.write_str(v),
+            LoxValue::LFunction(f) => write!(formatter, "<fn {}>", f.name),
+            LoxValue::LNative(f) => write!(formatter, "<native fn {}>", f.name),
+            LoxValue::LError(msg) => formatter.write_str(msg),
+            LoxValue::LList(_) | LoxValue::LMap(_) => formatter.write_str(&self.display_with(&NumberFormat::default())),
+            LoxValue::LClass(class) => formatter.write_str(&class.name),
+            LoxValue::LInstance(instance) => write!(formatter, "{} instance", instance.class.name),
         }?;
         Ok(())
     }
 }
 
+// Lox source that evaluates to an equal value, if there is any: nil,
+// booleans, finite numbers, strings without a '"' (Lox has no escapes, but a
+// string can span lines), and lists and maps of those. Functions, instances
+// and the like have none, and neither does a list or map that contains
+// itself.
+pub fn to_lox_literal(v: &LoxValue) -> Option<String> {
+    lox_literal_inside(v, &mut Vec::new())
+}
+
+// what a list or map is told apart by when looking for cycles, and null
+// for anything else
+fn container_ptr(v: &LoxValue) -> *const () {
+    match v {
+        LoxValue::LList(list) => Rc::as_ptr(list) as *const (),
+        LoxValue::LMap(map) => Rc::as_ptr(map) as *const (),
+        _ => std::ptr::null(),
+    }
+}
+
+// containing holds the lists and maps we're already inside
+fn lox_literal_inside(v: &LoxValue, containing: &mut Vec<*const ()>) -> Option<String> {
+    let container = container_ptr(v);
+    if containing.contains(&container) {
+        return None;
+    }
+    containing.push(container);
+    let literal = match v {
+        LoxValue::LNil => Some("nil".to_string()),
+        LoxValue::LBoolean(b) => Some(b.to_string()),
+        LoxValue::LNumber(n) if n.is_finite() => Some(lox_fmt_number(*n)),
+        LoxValue::LString(s) if !s.contains('"') => Some(format!("\"{s}\"")),
+        LoxValue::LList(list) => {
+            let elements: Option<Vec<String>> =
+                list.borrow().iter().map(|element| lox_literal_inside(element, containing)).collect();
+            elements.map(|elements| format!("[{}]", elements.join(", ")))
+        }
+        LoxValue::LMap(map) => {
+            let entries: Option<Vec<String>> = map
+                .borrow()
+                .iter()
+                .map(|(key, value)| {
+                    Some(format!("{}: {}", lox_literal_inside(key, containing)?, lox_literal_inside(value, containing)?))
+                })
+                .collect();
+            // sorted, as Display does
+            entries.map(|mut entries| {
+                entries.sort();
+                format!("{{{}}}", entries.join(", "))
+            })
+        }
+        _ => None,
+    };
+    containing.pop();
+    literal
+}
+
+// how numbers print. Whole numbers have no ".0"; numbers too big or too
+// small to write out sensibly use exponent notation (1e100, 1.5e-7), with
+// the same cut-offs as JavaScript. -0 keeps its sign; NaN and the
+// infinities print as NaN, inf, -inf. Otherwise this is the shortest text
+// that scans back to the same number, whatever the locale.
+pub fn lox_fmt_number(v: f64) -> String {
+    let magnitude = v.abs();
+    if magnitude.is_finite() && (magnitude >= 1e21 || (magnitude != 0.0 && magnitude < 1e-6)) {
+        format!("{v:e}")
+    } else {
+        format!("{v}")
+    }
+}
+
+// how `print` shows numbers. The default is lox_fmt_number's full form;
+// rounding gives at most max_precision digits after the point, keeping the
+// zeros at the end unless trim_trailing_zeros. Numbers in exponent form are
+// rounded the same way ("1.50e21").
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct NumberFormat {
+    pub max_precision: Option<usize>,
+    pub trim_trailing_zeros: bool,
+}
+
+impl NumberFormat {
+    pub fn format(&self, v: f64) -> String {
+        let Some(precision) = self.max_precision.filter(|_| v.is_finite()) else {
+            return lox_fmt_number(v);
+        };
+        let full = lox_fmt_number(v);
+        let rounded = if full.contains('e') { format!("{v:.precision$e}") } else { format!("{v:.precision$}") };
+        if !self.trim_trailing_zeros {
+            return rounded;
+        }
+        let (mantissa, exponent) = rounded.split_at(rounded.find('e').unwrap_or(rounded.len()));
+        let mantissa = if mantissa.contains('.') { mantissa.trim_end_matches('0').trim_end_matches('.') } else { mantissa };
+        format!("{mantissa}{exponent}")
+    }
+}
+
 #[derive(Debug)]
 pub enum Error {
-    ZeroDivision,
-    UnsupportedBinOp(LoxValue, Operator, LoxValue),
-    UnsupportedUnaryOp(Operator, LoxValue),
+    ZeroDivision { line: usize },
+    ModuloByZero { line: usize },
+    // span is the whole binary expression's, operands included, in the
+    // program given to evaluate(); None if the expression came from
+    // somewhere else, like an import or eval(). source is the program it
+    // came from.
+    UnsupportedBinOp { left: LoxValue, op: Operator, right: LoxValue, line: usize, span: Option<Span>, source: usize },
+    UnsupportedUnaryOp { op: Operator, value: LoxValue, line: usize },
+    NotCallable(LoxValue),
+    NotIterable(LoxValue),
+    UndefinedVariable(String),
+    // a property that's neither a field nor a method of the instance
+    UndefinedProperty { name: String, line: usize },
+    // only instances have properties to get or set
+    NoProperties { value: LoxValue, line: usize },
+    // `class B < A` where A isn't a class
+    BadSuperclass(LoxValue),
+    ArityError { expected: usize, got: usize, name: String, line: usize },
+    // a native was given an argument of the wrong type or value
+    TypeError(String),
+    ValueError(String),
+    IndexOutOfBounds(String),
+    StackOverflow { line: usize },
+    // a value thrown with `throw` that nothing caught
+    LoxThrow(LoxValue),
+    // `import "path";` could not load the file
+    Import { path: String, error: ImportError, line: usize },
+    // a file given to run_file couldn't be read
+    Read(reader::Error),
+    // source handed to the interpreter as a string (exec, eval_expr) or a
+    // file (run_file) didn't scan or parse
+    Tokenize(token::Error),
+    Parse(parser::Error),
+    // an error that escaped from inside Lox function calls, innermost frame first
+    Traced { error: Box<Error>, trace: Vec<Frame> },
+    // not real errors: `return` unwinds to the enclosing call with these
+    Return(LoxValue),
+    TailCall(Vec<LoxValue>),
 }
 
-pub struct Interpreter {
-    top_level: Rc<Environment>,
+impl std::fmt::Display for Error {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        use Error::*;
+        match self {
+            ZeroDivision { line } => write!(formatter, "Line {line}: Division by zero"),
+            ModuloByZero { line } => write!(formatter, "Line {line}: Modulo by zero"),
+            UnsupportedBinOp { left, op, right, line, .. } => {
+                let expected = match op {
+                    Operator::OAdd => "two numbers or two strings",
+                    _ => "numbers",
+                };
+                write!(
+                    formatter,
+                    "Line {line}: Operands must be {expected}: {} {op} {} ({} {op} {})",
+                    left.repr(),
+                    right.repr(),
+                    left.type_name(),
+                    right.type_name()
+                )
+            }
+            UnsupportedUnaryOp { op, value, line } => write!(
+                formatter,
+                "Line {line}: Operand must be a number: {op}{} ({})",
+                value.repr(),
+                value.type_name()
+            ),
+            NotCallable(value) => {
+                write!(formatter, "Can only call functions, not {}", value.type_name())
+            }
+            NotIterable(value) => {
+                write!(formatter, "Can only iterate over lists and strings, not {}", value.type_name())
+            }
+            UndefinedVariable(name) => write!(formatter, "Undefined variable {name:?}"),
+            UndefinedProperty { name, line } => write!(formatter, "Line {line}: Undefined property {name:?}"),
+            BadSuperclass(value) => write!(formatter, "Superclass must be a class, not {}", value.type_name()),
+            NoProperties { value, line } => {
+                write!(formatter, "Line {line}: Only instances have properties, not {}", value.type_name())
+            }
+            ArityError { expected, got, name, line } => {
+                let arguments = if *expected == 1 { "argument" } else { "arguments" };
+                write!(formatter, "Line {line}: {name}(): Expected {expected} {arguments} but got {got}.")
+            }
+            TypeError(msg) | ValueError(msg) | IndexOutOfBounds(msg) => write!(formatter, "{msg}"),
+            StackOverflow { line } => {
+                write!(formatter, "Line {line}: Stack overflow (too many nested calls)")
+            }
+            LoxThrow(value) => write!(formatter, "Uncaught exception: {value}"),
+            Import { path, error, line } => write!(formatter, "Line {line}: Can't import {path:?}: {error}"),
+            Read(e) => write!(formatter, "{e}"),
+            Tokenize(e) => write!(formatter, "{e}"),
+            Parse(e) => write!(formatter, "{e}"),
+            Traced { error, trace } => {
+                write!(formatter, "{error}")?;
+                for frame in trace.iter() {
+                    write!(formatter, "\n{frame}")?;
+                }
+                Ok(())
+            }
+            Return(_) | TailCall(_) => write!(formatter, "Can't return from top-level code"),
+        }
+    }
 }
 
-impl Interpreter {
-    pub fn new() -> Interpreter {
-        Interpreter{top_level: Environment::new(None)}
+#[derive(Debug)]
+pub enum ImportError {
+    Read(reader::Error),
+    Tokenize(token::Error),
+    Parse(parser::Error),
+    // the file is already being imported further up the chain
+    Circular,
+}
+
+impl std::fmt::Display for ImportError {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            ImportError::Read(e) => write!(formatter, "{e}"),
+            ImportError::Tokenize(e) => write!(formatter, "{e}"),
+            ImportError::Parse(e) => write!(formatter, "{e}"),
+            ImportError::Circular => write!(formatter, "circular import"),
+        }
     }
+}
 
-    pub fn evaluate(&mut self,  ast: AST) -> Result<Output, Error> {
-        execute_statements(&ast.top, &self.top_level)?;
-        Ok(())
+// one active Lox call, or the script that made the outermost one, and the
+// line it had got to: where it called the next frame in, or for the
+// innermost, where the error was (if the error knows)
+#[derive(Debug, PartialEq, Clone)]
+pub struct Frame {
+    // None for the script
+    pub function: Option<String>,
+    pub line: Option<usize>,
+}
+
+impl std::fmt::Display for Frame {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        if let Some(line) = self.line {
+            write!(formatter, "[line {line}] ")?;
+        }
+        match &self.function {
+            Some(function) => write!(formatter, "in {function}()"),
+            None => formatter.write_str("in script"),
+        }
     }
 }
 
-pub fn evaluate(ast: AST) -> Result<Output, Error> {
-    println!("Evaluating");
-    let mut environ = Environment::new(None);
-    execute_statements(&ast.top, &environ)?;
-    Ok(())
+impl Error {
+    // the underlying error, without any stack trace attached
+    pub fn untraced(&self) -> &Error {
+        match self {
+            Error::Traced { error, .. } => error,
+            error => error,
+        }
+    }
+
+    // the line the error happened on, for errors that know it
+    pub fn line(&self) -> Option<usize> {
+        use Error::*;
+        match self.untraced() {
+            ZeroDivision { line }
+            | ModuloByZero { line }
+            | UnsupportedBinOp { line, .. }
+            | UnsupportedUnaryOp { line, .. }
+            | UndefinedProperty { line, .. }
+            | NoProperties { line, .. }
+            | StackOverflow { line }
+            | ArityError { line, .. }
+            | Import { line, .. } => Some(*line),
+            _ => None,
+        }
+    }
+
+    // the part of the source the error is about, for errors that know it
+    pub fn span(&self) -> Option<Span> {
+        match self.untraced() {
+            Error::UnsupportedBinOp { span, .. } => *span,
+            _ => None,
+        }
+    }
+
+    // forget spans that aren't in source, the program with that number
+    fn keep_spans_in(&mut self, program: usize) {
+        match self {
+            Error::Traced { error, .. } => error.keep_spans_in(program),
+            Error::UnsupportedBinOp { span, source, .. } if *source != program => *span = None,
+            _ => {}
+        }
+    }
+
+    // the value a catch clause sees for this error, if it can be caught at all
+    fn caught_value(self) -> Result<LoxValue, Error> {
+        match self {
+            Error::Traced { error, .. } => error.caught_value(),
+            Error::LoxThrow(value) => Ok(value),
+            Error::Return(_) | Error::TailCall(_) => Err(self),
+            error => Ok(LoxValue::LError(error.to_string().into())),
+        }
+    }
 }
 
-pub fn execute_statements(statements: &Vec<Stmt>, environ: &Rc<Environment>) -> Result<(), Error> {
-    // execute zero or more statements
-    for stmt in statements.iter() {
-        execute_statement(stmt, environ)?;
-    };
-    Ok(())
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InterpreterOptions {
+    // strict: dividing by zero and mixing strings with other values in `+`
+    // are runtime errors. Lenient: division follows IEEE (inf, NaN) and `+`
+    // converts the non-string side to a string.
+    pub strict_arithmetic: bool,
+    // write the variables in scope to stderr after every statement
+    pub trace_env: bool,
+    // count statements and calls (see Interpreter::profiler)
+    pub profile: bool,
+    // keep totals of what was evaluated (see Interpreter::stats)
+    pub stats: bool,
+    // whether the eval() native exists. A program that can build and run
+    // code from strings can do anything the interpreter can, so hosts
+    // running untrusted code may want it gone.
+    pub allow_eval: bool,
+    // how print shows numbers
+    pub number_format: NumberFormat,
+    // assigning to a variable that was never declared is an error, rather
+    // than a way of declaring a global
+    pub strict_variables: bool,
+    // what lint::strict_errors finds is a syntax error, in imported files as
+    // well as the program
+    pub strict_scopes: bool,
 }
 
-pub fn execute_statement(stmt: &Stmt, environ: &Rc<Environment>) -> Result<(), Error> {
-    // execute a single statement
-    match stmt {
-        Stmt::SPrint{expr} => {
-            let value = evaluate_expression(expr, environ)?;
-            println!("{value:?}");
-        },
-        Stmt::SExpression{expr} => {
-
-            evaluate_expression(expr, environ)?;
-        },
-        Stmt::SVarDecl {name, initializer} => {
-            let iv = match initializer {
-                Some(v) => evaluate_expression(v, environ)?,
-                None => LoxValue::LNil
-            };
-            environ.declare(name, iv)
-        }
-    }
-    Ok(()) // statements don't produce values
-}
-
-pub fn evaluate_expression(expr: &Expr, environ: &Rc<Environment>) -> Result<LoxValue, Error> {
-    Ok(match expr {
-        Expr::ENumber {value} => {
-            LoxValue::LNumber(value.parse().unwrap())
-        },
-        Expr::EString {value} => {
-            LoxValue::LString(value.clone())
-        }
-        Expr::EBool {value} => {
-            LoxValue::LBoolean(*value)
-        }
-        Expr::ENil =>{
-            LoxValue::LNil
-        },
-        Expr::EVariable {name} => {
-            environ.lookup(name).unwrap().clone()
-        }
-        Expr::EBinary {left, op, right} => {
-            use LoxValue::*;
-            use Operator::*;
-            let lv = evaluate_expression(left, environ)?;
-            let rv = evaluate_expression(right, environ)?;
-            match (lv, op, rv) {
-                (LNumber(x), OAdd, LNumber(y))=> LNumber(x + y),
-                (LNumber(x), OSub, LNumber(y))=> LNumber(x - y),
-                (LNumber(x), OMul, LNumber(y))=> LNumber(x * y),
-                (LNumber(x), ODiv, LNumber(y))=> {
-                    if y == 0.0 {
-                        return Err(Error::ZeroDivision)
-                    }else{
-                        LNumber(x / y)
-                    }
-                },
-                (LNumber(x), OLt, LNumber(y))=> LBoolean(x < y),
-                (LNumber(x), OLe, LNumber(y))=> LBoolean(x <= y),
-                (LNumber(x), OGt, LNumber(y))=> LBoolean(x > y),
-                (LNumber(x), OGe, LNumber(y))=> LBoolean(x >= y),
-                // string
-                (LString(x), OAdd, LString(y))=> LString(format!("{}{}", x, y)),
+impl Default for InterpreterOptions {
+    fn default() -> InterpreterOptions {
+        InterpreterOptions {
+            strict_arithmetic: true,
+            trace_env: false,
+            profile: false,
+            stats: false,
+            allow_eval: true,
+            number_format: NumberFormat::default(),
+            strict_variables: false,
+            strict_scopes: false,
+        }
+    }
+}
 
-                // equality works with any combination of values
-                (x, OEq, y)=> LBoolean(x == y),
-                (x, ONe, y)=> LBoolean(x != y),
-                (lv, op, rv) => {
-                    return Err(Error::UnsupportedBinOp(lv, *op, rv))
-                }
+// the variables visible from environ as `name = repr` lines: its own first,
+// then each enclosing scope's, indented two more spaces per level. Natives
+// are left out, since they're always there.
+fn dump_scopes(environ: &Environment, out: &mut dyn Write) -> std::io::Result<()> {
+    for (n, scope) in environ.chain().enumerate() {
+        for (name, value) in scope.entries() {
+            if !matches!(*value, LoxValue::LNative(_)) {
+                writeln!(out, "{:indent$}{name} = {}", "", value.repr(), indent = 2 * n)?;
             }
         }
-        Expr::EUnary {op, right} => {
-            use LoxValue::*;
-            use Operator::*;
-            let rv = evaluate_expression(right, environ)?;
-            match (op, rv) {
-                (OSub, LNumber(x)) => LNumber(-x),
-                (ONot, x) => LBoolean(!x.is_truthy()),
-                (op, rv) => {
-                    return Err(Error::UnsupportedUnaryOp(*op, rv));
-                }
+    }
+    Ok(())
+}
+
+// somewhere for set_output to send `print` output that can still be read
+// afterwards: clones share one buffer
+#[derive(Clone, Default)]
+pub struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+impl SharedOutput {
+    // what's been written since last time, as text
+    pub fn take(&self) -> String {
+        let bytes = std::mem::take(&mut *self.0.borrow_mut());
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+}
+
+impl Write for SharedOutput {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+// how many Lox calls may be nested before giving up with StackOverflow
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1000;
+
+pub struct Interpreter {
+    top_level: Rc<Environment>,
+    // functions currently being executed (with their call-site line), innermost last
+    call_stack: Vec<(Rc<LoxFunction>, usize)>,
+    max_call_depth: usize,
+    options: InterpreterOptions,
+    // files in the middle of being imported, to detect import cycles
+    importing: HashSet<PathBuf>,
+    // files that have been imported, which later imports skip
+    imported: HashSet<PathBuf>,
+    // directories that relative imports are resolved against, innermost last
+    directories: Vec<PathBuf>,
+    // where else to look for an import not found beside the importing file,
+    // in order
+    search_path: Vec<PathBuf>,
+    // where `print` writes (stdout unless set_output says otherwise)
+    output: Box<dyn Write>,
+    // try statements (and eval() calls) the running function is inside; a
+    // `return f()` in one isn't a tail call, since the try still has to
+    // see what f() throws
+    try_depth: usize,
+    profiler: Option<Profiler>,
+    stats: Option<ExecStats>,
+    coverage: Option<Coverage>,
+    // each AST given to evaluate() is a program with a number of its own,
+    // so spans in errors can be told apart from those in other programs
+    // (imports, eval() code, earlier files). source is the number of the
+    // one running; functions run in the program they were declared in.
+    source: usize,
+    programs: usize,
+}
+
+impl Interpreter {
+    pub fn new() -> Interpreter {
+        Interpreter::new_with_options(InterpreterOptions::default())
+    }
+
+    pub fn new_with_options(options: InterpreterOptions) -> Interpreter {
+        Interpreter{
+            top_level: Interpreter::globals_with_natives(&options),
+            call_stack: Vec::new(),
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            options,
+            importing: HashSet::new(),
+            imported: HashSet::new(),
+            directories: Vec::new(),
+            search_path: Vec::new(),
+            output: Box::new(std::io::stdout()),
+            try_depth: 0,
+            profiler: options.profile.then(Profiler::new),
+            stats: options.stats.then(ExecStats::new),
+            coverage: None,
+            source: 0,
+            programs: 0,
+        }
+    }
+
+    fn globals_with_natives(options: &InterpreterOptions) -> Rc<Environment> {
+        let top_level = Environment::new(None);
+        for native in natives::natives() {
+            if native.name == "eval" && !options.allow_eval {
+                continue;
             }
+            top_level.declare(native.name, LoxValue::LNative(Rc::new(native)));
+        }
+        top_level
+    }
+
+    // forget every global and import, as if freshly created (but keeping
+    // the options and call depth limit)
+    pub fn reset(&mut self) {
+        self.top_level = Interpreter::globals_with_natives(&self.options);
+        self.call_stack.clear();
+        self.try_depth = 0;
+        self.importing.clear();
+        self.imported.clear();
+    }
+
+    // send the output of `print` somewhere other than stdout, giving back
+    // where it was going
+    pub fn set_output(&mut self, output: Box<dyn Write>) -> Box<dyn Write> {
+        std::mem::replace(&mut self.output, output)
+    }
+
+    // the options it was created with
+    pub fn options(&self) -> &InterpreterOptions {
+        &self.options
+    }
+
+    // what has run so far, if the interpreter was created with
+    // options.profile
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    // what has been evaluated so far, if the interpreter was created with
+    // options.stats
+    pub fn stats(&self) -> Option<&ExecStats> {
+        self.stats.as_ref()
+    }
+
+    // keep track of which of the program's lines run (see Coverage::new).
+    // The program is the next one evaluated.
+    pub fn set_coverage(&mut self, mut coverage: Coverage) {
+        coverage.program = self.programs + 1;
+        self.coverage = Some(coverage);
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.max_call_depth = depth;
+    }
+
+    // relative imports in the main program are resolved against dir (by
+    // default, the current directory)
+    pub fn set_directory(&mut self, dir: impl Into<PathBuf>) {
+        self.directories = vec![dir.into()];
+    }
+
+    // look in dir for imports, after the importing file's own directory and
+    // any directories added before
+    pub fn add_search_path(&mut self, dir: impl Into<PathBuf>) {
+        self.search_path.push(dir.into());
+    }
+
+    // the global variables, sorted by name
+    pub fn globals(&self) -> Vec<(String, LoxValue)> {
+        self.top_level.entries().into_iter().map(|(name, value)| (name.to_string(), LoxValue::clone(&value))).collect()
+    }
+
+    // the global variables, as the REPL's :env shows them
+    pub fn dump_env(&self, out: &mut dyn Write) -> std::io::Result<()> {
+        dump_scopes(&self.top_level, out)
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<LoxValue> {
+        self.top_level.lookup(name).map(|value| LoxValue::clone(&value))
+    }
+
+    // declare (or redeclare) a global, as `var name = value;` would
+    pub fn set_global(&mut self, name: &str, value: LoxValue) {
+        self.top_level.declare(name, value);
+    }
+
+    // run a whole program in the global scope. Its value is that of its last
+    // statement, if that's an expression, or else nil.
+    pub fn evaluate(&mut self, mut ast: AST) -> Result<LoxValue, Error> {
+        resolver::resolve(&mut ast).map_err(Error::Parse)?;
+        self.programs += 1;
+        let program = self.programs;
+        let outer = std::mem::replace(&mut self.source, program);
+        let result = self.execute_program(&ast.top);
+        self.source = outer;
+        result.map_err(|mut error| {
+            error.keep_spans_in(program);
+            error
+        })
+    }
+
+    // run the source of a whole program. Globals it declares stay around for
+    // later exec and eval_expr calls.
+    pub fn exec(&mut self, source: &str) -> Result<(), Error> {
+        let tokens = tokenize::tokenize_with_comments(reader::Source::from(source)).map_err(Error::Tokenize)?;
+        let ast = parser::parse(&tokens).map_err(Error::Parse)?;
+        self.evaluate(ast).map(|_| ())
+    }
+
+    // run the file at path in the global scope, so that what it declares is
+    // there for whatever runs next, as with a library loaded before a
+    // session. Imports in it are relative to its own directory, and count
+    // it as imported, so an import of it later does nothing.
+    pub fn run_file(&mut self, path: &str) -> Result<(), Error> {
+        let source = reader::read_source(path).map_err(Error::Read)?;
+        let directory = source.directory.clone().unwrap_or_default();
+        let tokens = tokenize::tokenize_with_comments(source).map_err(Error::Tokenize)?;
+        let ast = parser::parse(&tokens).map_err(Error::Parse)?;
+        let Ok(canonical) = std::fs::canonicalize(path) else { return self.evaluate_in(ast, directory) };
+        self.importing.insert(canonical.clone());
+        let result = self.evaluate_in(ast, directory);
+        self.importing.remove(&canonical);
+        if result.is_ok() {
+            self.imported.insert(canonical);
         }
-        Expr::EGrouping { expr} => {
-            evaluate_expression(expr, environ)?
-        },
-        Expr::EAssign { name, value } => {
-            let v = evaluate_expression(value, environ)?;
-            environ.assign(name, v.clone());
-            v
+        result
+    }
+
+    // evaluate the source of a single expression, like "x + 1", in the
+    // global scope
+    pub fn eval_expr(&mut self, source: &str) -> Result<LoxValue, Error> {
+        let tokens = tokenize::tokenize(reader::Source::from(source)).map_err(Error::Tokenize)?;
+        let expr = parser::parse_expr(&tokens).map_err(Error::Parse)?;
+        self.evaluate_global(&expr)
+    }
+
+    // run source in the global scope and give back the value of its last
+    // statement, if that's an expression, or else nil. A `return` ends it
+    // early with the value returned. This is what eval() does.
+    pub fn eval_source(&mut self, source: &str) -> Result<LoxValue, Error> {
+        let tokens = tokenize::tokenize_with_comments(reader::Source::from(source)).map_err(Error::Tokenize)?;
+        let ast = parser::parse(&tokens).map_err(Error::Parse)?;
+        // the code isn't part of whichever function called eval(), so a
+        // `return f()` in it mustn't be taken as that function's tail call
+        self.try_depth += 1;
+        let result = self.evaluate(ast);
+        self.try_depth -= 1;
+        match result {
+            Err(Error::Return(value)) => Ok(value),
+            result => result,
         }
-    })
-}
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    // evaluate a single expression in the global scope
+    pub fn evaluate_global(&mut self, expr: &Expr) -> Result<LoxValue, Error> {
+        let top_level = self.top_level.clone();
+        self.evaluate_expression(expr, &top_level)
+    }
 
-    #[test]
-    fn its_alive() {
-        assert_eq!(true, true);
+    // the top-level statements of a program, keeping the value of the last
+    // one if it's an expression
+    fn execute_program(&mut self, top: &[Stmt]) -> Result<LoxValue, Error> {
+        let top_level = self.top_level.clone();
+        let Some((last @ Stmt::SExpression { expr, .. }, rest)) = top.split_last() else {
+            return self.execute_statements(top, &top_level).map(|()| LoxValue::LNil);
+        };
+        self.execute_statements(rest, &top_level)?;
+        self.record_statement(last, &top_level);
+        let value = self.evaluate_expression(expr, &top_level)?;
+        self.trace_scopes(&top_level);
+        Ok(value)
+    }
+
+    pub fn execute_statements(&mut self, statements: &[Stmt], environ: &Rc<Environment>) -> Result<(), Error> {
+        // execute zero or more statements
+        for stmt in statements.iter() {
+            self.execute_statement(stmt, environ)?;
+            self.trace_scopes(environ);
+        };
+        Ok(())
+    }
+
+    fn trace_scopes(&self, environ: &Rc<Environment>) {
+        if self.options.trace_env {
+            let mut stderr = std::io::stderr().lock();
+            let _ = dump_scopes(environ, &mut stderr).and_then(|()| writeln!(stderr, "--"));
+        }
+    }
+
+    fn record_statement(&mut self, stmt: &Stmt, environ: &Rc<Environment>) {
+        if let Some(profiler) = &mut self.profiler {
+            profiler.record_statement(stmt);
+        }
+        if let Some(stats) = &mut self.stats {
+            stats.record_statement(environ.depth());
+        }
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record_statement(self.source, stmt);
+        }
+    }
+
+    pub fn execute_statement(&mut self, stmt: &Stmt, environ: &Rc<Environment>) -> Result<(), Error> {
+        // execute a single statement
+        self.record_statement(stmt, environ);
+        match stmt {
+            Stmt::SPrint{expr, ..} => {
+                let value = self.evaluate_expression(expr, environ)?.display_with(&self.options.number_format);
+                // a closed output isn't the program's fault, so carry on
+                let _ = writeln!(self.output, "{value}");
+            },
+            Stmt::SExpression{expr, ..} => {
+
+                self.evaluate_expression(expr, environ)?;
+            },
+            Stmt::SVarDecl {name, initializer, ..} => {
+                let iv = match initializer {
+                    Some(v) => self.evaluate_expression(v, environ)?,
+                    None => LoxValue::LNil
+                };
+                environ.declare(name.clone(), iv)
+            }
+            Stmt::SBlock {statements, ..} => {
+                let inner = Environment::new(Some(environ.clone()));
+                self.execute_statements(statements, &inner)?;
+            }
+            Stmt::SIf {condition, then_branch, else_branch, ..} => {
+                if self.evaluate_expression(condition, environ)?.is_truthy() {
+                    self.execute_statement(then_branch, environ)?;
+                } else if let Some(else_branch) = else_branch {
+                    self.execute_statement(else_branch, environ)?;
+                }
+            }
+            Stmt::SFunction {name, params, rest, body, doc, ..} => {
+                let function = LoxFunction {
+                    name: name.clone(),
+                    params: params.clone(),
+                    rest: rest.clone(),
+                    body: body.clone(),
+                    closure: environ.clone(),
+                    is_initializer: false,
+                    doc: doc.clone(),
+                    source: self.source,
+                };
+                environ.declare(name.clone(), LoxValue::LFunction(Rc::new(function)));
+            }
+            Stmt::SClass {name: class_name, superclass, methods, doc, ..} => {
+                let superclass = match superclass {
+                    Some(superclass) => match self.evaluate_expression(superclass, environ)? {
+                        LoxValue::LClass(class) => Some(class),
+                        other => return Err(Error::BadSuperclass(other)),
+                    },
+                    None => None,
+                };
+                // methods of a subclass close over a scope holding "super"
+                let closure = match &superclass {
+                    Some(class) => {
+                        let closure = Environment::new(Some(environ.clone()));
+                        closure.declare("super", LoxValue::LClass(class.clone()));
+                        closure
+                    }
+                    None => environ.clone(),
+                };
+                let methods = methods
+                    .iter()
+                    .filter_map(|method| match method {
+                        Stmt::SFunction {name, params, rest, body, doc, ..} => Some((name.clone(), Rc::new(LoxFunction {
+                            name: name.clone(),
+                            params: params.clone(),
+                            rest: rest.clone(),
+                            body: body.clone(),
+                            closure: closure.clone(),
+                            is_initializer: &**name == "init",
+                            doc: doc.clone(),
+                            source: self.source,
+                        }))),
+                        _ => None,
+                    })
+                    .collect();
+                let class = LoxClass {name: class_name.clone(), superclass, methods, doc: doc.clone()};
+                environ.declare(class_name.clone(), LoxValue::LClass(Rc::new(class)));
+            }
+            Stmt::SReturn {value, ..} => {
+                let value = match value {
+                    // `return f(...)` where f is the running function: rather than
+                    // recursing, hand the arguments back to call_function's loop
+                    Some(call @ Expr::ECall {callee, args, line, ..}) => {
+                        // counted as evaluate_expression would have
+                        if let Some(stats) = &mut self.stats {
+                            stats.record_expression(call);
+                        }
+                        let callee = self.evaluate_expression(callee, environ)?;
+                        let args = self.evaluate_arguments(args, environ)?;
+                        if let LoxValue::LFunction(function) = &callee
+                            && self.is_current_function(&callee)
+                            && self.try_depth == 0
+                        {
+                            function.check_arity(args.len(), *line)?;
+                            return Err(Error::TailCall(args));
+                        }
+                        self.call(callee, args, *line)?
+                    }
+                    Some(expr) => self.evaluate_expression(expr, environ)?,
+                    None => LoxValue::LNil,
+                };
+                return Err(Error::Return(value));
+            }
+            Stmt::STryCatch {try_body, catch_var, catch_body, finally_body, ..} => {
+                let inner = Environment::new(Some(environ.clone()));
+                self.try_depth += 1;
+                let mut result = self.execute_statements(try_body, &inner);
+                if let Err(error) = result {
+                    result = match error.caught_value() {
+                        Ok(value) => {
+                            let inner = Environment::new(Some(environ.clone()));
+                            if let Some(name) = catch_var {
+                                inner.declare(name.clone(), value);
+                            }
+                            self.execute_statements(catch_body, &inner)
+                        }
+                        Err(error) => Err(error),
+                    };
+                }
+                self.try_depth -= 1;
+                // finally always runs; an error raised in it wins
+                let inner = Environment::new(Some(environ.clone()));
+                self.execute_statements(finally_body, &inner)?;
+                result?;
+            }
+            Stmt::SThrow {value, ..} => {
+                let value = self.evaluate_expression(value, environ)?;
+                return Err(Error::LoxThrow(value));
+            }
+            Stmt::SForIn {var, iterable, body, ..} => {
+                let items = match self.evaluate_expression(iterable, environ)? {
+                    // iterate over a snapshot so the body may modify the list
+                    LoxValue::LList(elements) => elements.borrow().clone(),
+                    LoxValue::LString(s) => s.chars().map(|c| LoxValue::LString(c.to_string().into())).collect(),
+                    other => return Err(Error::NotIterable(other)),
+                };
+                for item in items {
+                    let inner = Environment::new(Some(environ.clone()));
+                    inner.declare(var.clone(), item);
+                    self.execute_statement(body, &inner)?;
+                }
+            }
+            Stmt::SWhile {condition, body, ..} => {
+                while self.evaluate_expression(condition, environ)?.is_truthy() {
+                    self.execute_statement(body, environ)?;
+                }
+            }
+            Stmt::SImport {path, line, ..} => {
+                self.import(path, *line)?;
+            }
+            Stmt::SSwitch {subject, cases, default, ..} => {
+                let subject = self.evaluate_expression(subject, environ)?;
+                let mut arm = default.as_ref();
+                for (value, body) in cases.iter() {
+                    if self.evaluate_expression(value, environ)? == subject {
+                        arm = Some(body);
+                        break;
+                    }
+                }
+                if let Some(body) = arm {
+                    let inner = Environment::new(Some(environ.clone()));
+                    self.execute_statements(body, &inner)?;
+                }
+            }
+        }
+        Ok(()) // statements don't produce values
+    }
+
+    // run a file in the global scope, unless it has been imported already.
+    // Relative paths are relative to the importing file or, failing that, to
+    // a directory on the search path.
+    fn import(&mut self, path: &str, line: usize) -> Result<(), Error> {
+        let fail = |error| Error::Import { path: path.to_string(), error, line };
+        let here = self.directories.last().map_or(Path::new(""), PathBuf::as_path);
+        let mut found = std::iter::once(here)
+            .chain(self.search_path.iter().map(PathBuf::as_path))
+            .map(|dir| std::fs::canonicalize(dir.join(path)));
+        // if it's nowhere, the error is about the importing file's directory
+        let canonical = match found.next().unwrap() {
+            Ok(canonical) => canonical,
+            Err(e) => found.find_map(Result::ok).ok_or_else(|| fail(ImportError::Read(e.into())))?,
+        };
+        if self.imported.contains(&canonical) {
+            return Ok(());
+        }
+        if !self.importing.insert(canonical.clone()) {
+            return Err(fail(ImportError::Circular));
+        }
+        let result = self.run_module(&canonical, fail);
+        self.importing.remove(&canonical);
+        if result.is_ok() {
+            self.imported.insert(canonical);
+        }
+        result
+    }
+
+    // imports in the module are relative to its own directory
+    fn run_module(&mut self, path: &Path, fail: impl Fn(ImportError) -> Error) -> Result<(), Error> {
+        let source = reader::read_source(&path.to_string_lossy()).map_err(|e| fail(ImportError::Read(e)))?;
+        let directory = source.directory.clone().unwrap_or_default();
+        let tokens = tokenize::tokenize_with_comments(source).map_err(|e| fail(ImportError::Tokenize(e)))?;
+        let ast = parser::parse(&tokens).map_err(|e| fail(ImportError::Parse(e)))?;
+        if self.options.strict_scopes {
+            lint::check_strict(&ast, &tokens).map_err(|e| fail(ImportError::Parse(e)))?;
+        }
+        self.evaluate_in(ast, directory)
+    }
+
+    // evaluate ast with imports relative to directory
+    fn evaluate_in(&mut self, ast: AST, directory: PathBuf) -> Result<(), Error> {
+        self.directories.push(directory);
+        let result = self.evaluate(ast);
+        self.directories.pop();
+        result.map(|_| ())
+    }
+
+    fn is_current_function(&self, callee: &LoxValue) -> bool {
+        match (callee, self.call_stack.last()) {
+            (LoxValue::LFunction(f), Some((current, _))) => Rc::ptr_eq(f, current),
+            _ => false,
+        }
+    }
+
+    fn evaluate_arguments(&mut self, args: &[Expr], environ: &Rc<Environment>) -> Result<Vec<LoxValue>, Error> {
+        args.iter().map(|arg| self.evaluate_expression(arg, environ)).collect()
+    }
+
+    fn call(&mut self, callee: LoxValue, args: Vec<LoxValue>, line: usize) -> Result<LoxValue, Error> {
+        if let Some(profiler) = &mut self.profiler {
+            match &callee {
+                LoxValue::LFunction(function) => profiler.record_call(&function.name),
+                LoxValue::LNative(native) => profiler.record_call(native.name),
+                LoxValue::LClass(class) => profiler.record_call(&class.name),
+                _ => {}
+            }
+        }
+        match callee {
+            LoxValue::LFunction(function) => {
+                // tail calls loop inside call_function and don't count here
+                if self.call_stack.len() >= self.max_call_depth {
+                    return Err(Error::StackOverflow { line });
+                }
+                function.check_arity(args.len(), line)?;
+                self.call_function(&function, args, line)
+            }
+            LoxValue::LNative(native) => {
+                if !native.arity.contains(&args.len()) {
+                    // report the bound that was missed
+                    let expected = args.len().clamp(*native.arity.start(), *native.arity.end());
+                    let name = native.name.to_string();
+                    return Err(Error::ArityError { expected, got: args.len(), name, line });
+                }
+                let result = match native.function {
+                    NativeFn::Plain(function) => function(&args),
+                    NativeFn::WithInterpreter(function) => function(self, &args),
+                };
+                // natives don't know the line they were called from
+                result.map_err(|error| match error {
+                    Error::ZeroDivision { .. } => Error::ZeroDivision { line },
+                    error => error,
+                })
+            }
+            LoxValue::LClass(class) => {
+                let instance = Rc::new(LoxInstance {class: class.clone(), fields: RefCell::new(HashMap::new())});
+                match class.find_method("init") {
+                    Some(init) => {
+                        if self.call_stack.len() >= self.max_call_depth {
+                            return Err(Error::StackOverflow { line });
+                        }
+                        init.check_arity(args.len(), line)?;
+                        self.call_function(&init.bind(instance.clone()), args, line)?;
+                    }
+                    None if !args.is_empty() => {
+                        return Err(Error::ArityError { expected: 0, got: args.len(), name: class.name.to_string(), line });
+                    }
+                    None => {}
+                }
+                Ok(LoxValue::LInstance(instance))
+            }
+            other => Err(Error::NotCallable(other)),
+        }
+    }
+
+    fn call_function(&mut self, function: &Rc<LoxFunction>, args: Vec<LoxValue>, line: usize) -> Result<LoxValue, Error> {
+        self.call_stack.push((function.clone(), line));
+        if let Some(stats) = &mut self.stats {
+            stats.record_call_depth(self.call_stack.len());
+        }
+        // the caller's try statements don't stop tail calls in here
+        let try_depth = std::mem::take(&mut self.try_depth);
+        let caller_source = std::mem::replace(&mut self.source, function.source);
+        let mut args = args;
+        let result = loop {
+            let environ = Environment::new(Some(function.closure.clone()));
+            let mut args_iter = args.into_iter();
+            for param in function.params.iter() {
+                environ.declare(param.clone(), args_iter.next().unwrap_or(LoxValue::LNil));
+            }
+            if let Some(rest) = &function.rest {
+                let rest_args = LoxValue::LList(Rc::new(RefCell::new(args_iter.collect())));
+                environ.declare(rest.clone(), rest_args);
+            }
+            match self.execute_statements(&function.body, &environ) {
+                Ok(()) => break Ok(LoxValue::LNil),
+                Err(Error::Return(value)) => break Ok(value),
+                // self tail call: rebind the parameters and run the body again
+                Err(Error::TailCall(next_args)) => {
+                    if let Some(profiler) = &mut self.profiler {
+                        profiler.record_call(&function.name);
+                    }
+                    args = next_args;
+                }
+                Err(e @ Error::Traced { .. }) => break Err(e),
+                // the innermost call the error escapes from records the trace
+                Err(e) => break Err(Error::Traced { trace: self.trace(e.line()), error: e.into() }),
+            }
+        };
+        self.try_depth = try_depth;
+        self.source = caller_source;
+        self.call_stack.pop();
+        // init gives back the instance, even when called again by name
+        if function.is_initializer && result.is_ok() {
+            return Ok(function.closure.get_at(0, 0).map_or(LoxValue::LNil, |this| LoxValue::clone(&this)));
+        }
+        result
+    }
+
+    // object.name: a field, or else a method bound to the instance
+    fn get_property(&self, object: LoxValue, name: &str, line: usize) -> Result<LoxValue, Error> {
+        let LoxValue::LInstance(instance) = object else {
+            return Err(Error::NoProperties { value: object, line });
+        };
+        if let Some(value) = instance.fields.borrow().get(name) {
+            return Ok(value.clone());
+        }
+        match instance.class.find_method(name) {
+            Some(method) => Ok(LoxValue::LFunction(method.bind(instance.clone()))),
+            None => Err(Error::UndefinedProperty { name: name.to_string(), line }),
+        }
+    }
+
+    // snapshot of the active calls, innermost first and then the script.
+    // Each frame is at the line it called the next one in from; the
+    // innermost is at line, where the error was.
+    fn trace(&self, line: Option<usize>) -> Vec<Frame> {
+        let names = self.call_stack.iter().rev().map(|(function, _)| Some(function.name.to_string()));
+        let lines = self.call_stack.iter().rev().map(|(_, called_from)| Some(*called_from));
+        names
+            .chain([None])
+            .zip(std::iter::once(line).chain(lines))
+            .map(|(function, line)| Frame { function, line })
+            .collect()
+    }
+
+    // Evaluation is strictly left to right, each subexpression finished
+    // (side effects and all) before the next starts: left operand before
+    // right, callee before arguments, arguments and list elements in order,
+    // each map key before its value, an object before the value set on it.
+    // tests/evaluation_order.rs pins this down, and anything that rewrites
+    // expressions has to keep it.
+    pub fn evaluate_expression(&mut self, expr: &Expr, environ: &Rc<Environment>) -> Result<LoxValue, Error> {
+        if let Some(stats) = &mut self.stats {
+            stats.record_expression(expr);
+        }
+        Ok(match expr {
+            Expr::ENumber {value, ..} => {
+                LoxValue::LNumber(*value)
+            },
+            Expr::EString {value, ..} => {
+                LoxValue::LString(value.as_str().into())
+            }
+            Expr::EBool {value, ..} => {
+                LoxValue::LBoolean(*value)
+            }
+            Expr::ENil { .. } =>{
+                LoxValue::LNil
+            },
+            Expr::EVariable {name, slot, ..} => {
+                let value = match slot {
+                    Some(slot) => environ.get_at(slot.depth, slot.index),
+                    // the resolver found no local, so it's a global
+                    None => self.top_level.lookup(name),
+                };
+                let value = value.ok_or_else(|| Error::UndefinedVariable(name.to_string()))?;
+                LoxValue::clone(&value)
+            }
+            // `and` and `or` give back whichever operand decided the result,
+            // not a boolean, and only evaluate the right one if they need it
+            Expr::EBinary {left, op: op @ (Operator::OAnd | Operator::OOr), right, ..} => {
+                let lv = self.evaluate_expression(left, environ)?;
+                if lv.is_truthy() == matches!(op, Operator::OOr) {
+                    lv
+                } else {
+                    self.evaluate_expression(right, environ)?
+                }
+            }
+            Expr::EBinary {left, op, right, line, span, ..} => {
+                use LoxValue::*;
+                use Operator::*;
+                let lv = self.evaluate_expression(left, environ)?;
+                let rv = self.evaluate_expression(right, environ)?;
+                let strict = self.options.strict_arithmetic;
+                match (lv, op, rv) {
+                    (LNumber(x), OAdd, LNumber(y))=> LNumber(x + y),
+                    (LNumber(x), OSub, LNumber(y))=> LNumber(x - y),
+                    (LNumber(x), OMul, LNumber(y))=> LNumber(x * y),
+                    (LNumber(x), ODiv, LNumber(y))=> {
+                        // in strict mode any zero divisor (0 or -0, including
+                        // 0/0) is an error rather than producing inf or NaN
+                        if y == 0.0 && strict {
+                            return Err(Error::ZeroDivision { line: *line })
+                        }else{
+                            LNumber(x / y)
+                        }
+                    },
+                    (LNumber(x), OMod, LNumber(y))=> {
+                        if y == 0.0 && strict {
+                            return Err(Error::ModuloByZero { line: *line })
+                        }else{
+                            LNumber(floor_mod(x, y))
+                        }
+                    },
+                    (LNumber(x), OLt, LNumber(y))=> LBoolean(x < y),
+                    (LNumber(x), OLe, LNumber(y))=> LBoolean(x <= y),
+                    (LNumber(x), OGt, LNumber(y))=> LBoolean(x > y),
+                    (LNumber(x), OGe, LNumber(y))=> LBoolean(x >= y),
+                    // string
+                    (LString(x), OAdd, LString(y))=> LString(format!("{}{}", x, y).into()),
+                    (LString(x), OAdd, y) if !strict => LString(format!("{}{}", x, y).into()),
+                    (x, OAdd, LString(y)) if !strict => LString(format!("{}{}", x, y).into()),
+
+                    // equality works with any combination of values. Numbers
+                    // compare as IEEE doubles: NaN is unequal to everything
+                    // (itself included, so nan != nan is true) and 0 == -0.
+                    (x, OEq, y)=> LBoolean(x == y),
+                    (x, ONe, y)=> LBoolean(x != y),
+                    (lv, op, rv) => {
+                        let (span, source) = (Some(*span), self.source);
+                        return Err(Error::UnsupportedBinOp { left: lv, op: *op, right: rv, line: *line, span, source })
+                    }
+                }
+            }
+            Expr::EUnary {op, right, line, ..} => {
+                use LoxValue::*;
+                use Operator::*;
+                let rv = self.evaluate_expression(right, environ)?;
+                match (op, rv) {
+                    (OSub, LNumber(x)) => LNumber(-x),
+                    (ONot, x) => LBoolean(!x.is_truthy()),
+                    (op, rv) => {
+                        return Err(Error::UnsupportedUnaryOp { op: *op, value: rv, line: *line });
+                    }
+                }
+            }
+            Expr::EGrouping { expr, ..} => {
+                self.evaluate_expression(expr, environ)?
+            },
+            Expr::EAssign { name, value, slot, .. } => {
+                let v = self.evaluate_expression(value, environ)?;
+                let unresolved = match slot {
+                    Some(slot) => environ.assign_at(slot.depth, slot.index, v.clone()).err(),
+                    None => Some(v.clone()),
+                };
+                if let Some(v) = unresolved {
+                    if self.options.strict_variables && self.top_level.lookup(name).is_none() {
+                        return Err(Error::UndefinedVariable(name.to_string()));
+                    }
+                    self.top_level.assign(name, v);
+                }
+                v
+            }
+            Expr::EList { elements, .. } => {
+                let elements = self.evaluate_arguments(elements, environ)?;
+                LoxValue::LList(Rc::new(RefCell::new(elements)))
+            }
+            Expr::EMap { pairs, .. } => {
+                let mut entries = HashMap::new();
+                for (key, value) in pairs {
+                    let key = self.evaluate_expression(key, environ)?;
+                    if !key.is_hashable() {
+                        return Err(Error::TypeError(format!("a {} can't be a map key", key.type_name())));
+                    }
+                    entries.insert(key, self.evaluate_expression(value, environ)?);
+                }
+                LoxValue::LMap(Rc::new(RefCell::new(entries)))
+            }
+            Expr::ECall { callee, args, line, .. } => {
+                let callee = self.evaluate_expression(callee, environ)?;
+                let args = self.evaluate_arguments(args, environ)?;
+                self.call(callee, args, *line)?
+            }
+            Expr::EGet { object, name, line, .. } => {
+                let object = self.evaluate_expression(object, environ)?;
+                self.get_property(object, name, *line)?
+            }
+            Expr::ESet { object, name, value, line, .. } => {
+                let instance = match self.evaluate_expression(object, environ)? {
+                    LoxValue::LInstance(instance) => instance,
+                    object => return Err(Error::NoProperties { value: object, line: *line }),
+                };
+                let value = self.evaluate_expression(value, environ)?;
+                instance.fields.borrow_mut().insert(name.clone(), value.clone());
+                value
+            }
+            Expr::ESuper { method, slot, line, .. } => {
+                // "this" is in the scope just inside "super"'s
+                let (superclass, this) = match slot {
+                    Some(slot) => (environ.get_at(slot.depth, slot.index), environ.get_at(slot.depth - 1, 0)),
+                    None => (environ.lookup("super"), environ.lookup("this")),
+                };
+                let superclass = superclass.ok_or_else(|| Error::UndefinedVariable("super".to_string()))?;
+                let this = this.ok_or_else(|| Error::UndefinedVariable("this".to_string()))?;
+                let (LoxValue::LClass(superclass), LoxValue::LInstance(instance)) = (&*superclass, &*this) else {
+                    return Err(Error::UndefinedVariable("super".to_string()));
+                };
+                match superclass.find_method(method) {
+                    Some(method) => LoxValue::LFunction(method.bind(instance.clone())),
+                    None => return Err(Error::UndefinedProperty { name: method.to_string(), line: *line }),
+                }
+            }
+            Expr::EThis { slot, .. } => {
+                let value = match slot {
+                    Some(slot) => environ.get_at(slot.depth, slot.index),
+                    None => environ.lookup("this"),
+                };
+                let value = value.ok_or_else(|| Error::UndefinedVariable("this".to_string()))?;
+                LoxValue::clone(&value)
+            }
+        })
+    }
+}
+
+// x % y rounding the quotient towards negative infinity, so the result takes
+// the sign of y: -7 % 3 == 2, 7 % -3 == -2. Computed from the truncating
+// remainder (which is exact) rather than x - y * floor(x / y), which loses
+// precision for large operands.
+pub fn floor_mod(x: f64, y: f64) -> f64 {
+    let r = x % y;
+    if r != 0.0 && (r < 0.0) != (y < 0.0) {
+        r + y
+    } else {
+        r
+    }
+}
+
+// x / y rounded towards negative infinity; floor_div(x, y) * y + floor_mod(x, y) == x
+pub fn floor_div(x: f64, y: f64) -> f64 {
+    ((x - floor_mod(x, y)) / y).round()
+}
+
+impl Default for Interpreter {
+    fn default() -> Interpreter {
+        Interpreter::new()
+    }
+}
+
+// an Interpreter set up step by step, for hosts embedding Lox:
+//     LoxBuilder::new().max_call_depth(100).disable_eval().build()
+pub struct LoxBuilder {
+    options: InterpreterOptions,
+    max_call_depth: usize,
+    search_path: Vec<PathBuf>,
+}
+
+impl LoxBuilder {
+    pub fn new() -> LoxBuilder {
+        LoxBuilder { options: InterpreterOptions::default(), max_call_depth: DEFAULT_MAX_CALL_DEPTH, search_path: Vec::new() }
+    }
+
+    pub fn options(mut self, options: InterpreterOptions) -> LoxBuilder {
+        self.options = options;
+        self
+    }
+
+    pub fn max_call_depth(mut self, depth: usize) -> LoxBuilder {
+        self.max_call_depth = depth;
+        self
+    }
+
+    pub fn number_format(mut self, format: NumberFormat) -> LoxBuilder {
+        self.options.number_format = format;
+        self
+    }
+
+    pub fn search_path(mut self, dir: impl Into<PathBuf>) -> LoxBuilder {
+        self.search_path.push(dir.into());
+        self
+    }
+
+    // leave out the eval() native (see InterpreterOptions::allow_eval)
+    pub fn disable_eval(mut self) -> LoxBuilder {
+        self.options.allow_eval = false;
+        self
+    }
+
+    pub fn build(self) -> Interpreter {
+        let mut interpreter = Interpreter::new_with_options(self.options);
+        interpreter.set_max_call_depth(self.max_call_depth);
+        interpreter.search_path = self.search_path;
+        interpreter
+    }
+}
+
+impl Default for LoxBuilder {
+    fn default() -> LoxBuilder {
+        LoxBuilder::new()
+    }
+}
+
+// run a program on an interpreter of its own, for when nothing else needs
+// to see its globals
+pub fn evaluate(ast: AST, options: InterpreterOptions) -> Result<LoxValue, Error> {
+    Interpreter::new_with_options(options).evaluate(ast)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::Source;
+
+    #[test]
+    fn its_alive() {
+        assert_eq!(true, true);
+    }
+
+    // helper: run a program and return the interpreter for inspection
+    fn run_string(s: &str) -> Result<Interpreter, Error> {
+        let tokens = crate::tokenize::tokenize(Source::from(s)).unwrap();
+        let ast = crate::parser::parse(&tokens).unwrap();
+        let mut interp = Interpreter::new();
+        interp.evaluate(ast)?;
+        Ok(interp)
+    }
+
+    fn global(interp: &Interpreter, name: &str) -> LoxValue {
+        interp.get_global(name).unwrap()
+    }
+
+    #[test]
+    fn test_function_call() {
+        let interp = run_string("fun add(a, b) { return a + b; } var x = add(1, 2);").unwrap();
+        assert_eq!(global(&interp, "x"), LoxValue::LNumber(3.0));
+    }
+
+    #[test]
+    fn test_eval_expr() {
+        let mut interp = Interpreter::new();
+        interp.top_level.declare("x", LoxValue::LNumber(41.0));
+        assert_eq!(interp.eval_expr("x + 1").unwrap(), LoxValue::LNumber(42.0));
+        assert_eq!(interp.eval_expr("len([1, 2])").unwrap(), LoxValue::LNumber(2.0));
+        assert!(matches!(interp.eval_expr("x +"), Err(Error::Parse(_))));
+        assert!(matches!(interp.eval_expr("x 1"), Err(Error::Parse(_))));
+        assert!(matches!(interp.eval_expr("x @"), Err(Error::Tokenize(_))));
+    }
+
+    #[test]
+    fn test_evaluate_value() {
+        let parse = |s: &str| crate::parser::parse(&crate::tokenize::tokenize(Source::from(s)).unwrap()).unwrap();
+        let mut interp = Interpreter::new();
+        assert_eq!(interp.evaluate(parse("var x = 1; x + 2;")).unwrap(), LoxValue::LNumber(3.0));
+        assert_eq!(interp.evaluate(parse("x + 2; print x;")).unwrap(), LoxValue::LNil);
+        assert_eq!(interp.evaluate(parse("")).unwrap(), LoxValue::LNil);
+        // the last expression can still fail
+        assert!(interp.evaluate(parse("1; nothing;")).is_err());
+        let options = InterpreterOptions { strict_arithmetic: false, ..InterpreterOptions::default() };
+        assert_eq!(evaluate(parse("\"a\" + 1;"), options).unwrap(), LoxValue::LString("a1".into()));
+        assert!(evaluate(parse("\"a\" + 1;"), InterpreterOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_exec() {
+        let mut interp = Interpreter::new();
+        interp.exec("var count = 1; fun bump() { count = count + 1; return count; }").unwrap();
+        interp.exec("bump();").unwrap();
+        assert_eq!(interp.eval_expr("count").unwrap(), LoxValue::LNumber(2.0));
+        assert_eq!(interp.eval_expr("bump()").unwrap(), LoxValue::LNumber(3.0));
+        assert!(matches!(interp.exec("var;"), Err(Error::Parse(_))));
+        // statements before a runtime error have still run
+        assert!(interp.exec("count = 10; 1 / 0;").is_err());
+        assert_eq!(interp.eval_expr("count").unwrap(), LoxValue::LNumber(10.0));
+        interp.set_global("count", LoxValue::LNumber(20.0));
+        interp.exec("bump();").unwrap();
+        assert_eq!(interp.get_global("count"), Some(LoxValue::LNumber(21.0)));
+        assert_eq!(interp.get_global("nothing"), None);
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut interp = run_string("var x = 1;").unwrap();
+        interp.set_max_call_depth(5);
+        interp.reset();
+        assert!(interp.top_level.lookup("x").is_none());
+        assert!(interp.top_level.lookup("len").is_some());
+        assert_eq!(interp.max_call_depth, 5);
+    }
+
+    #[test]
+    fn test_set_output() {
+        let output = SharedOutput::default();
+        let mut interp = Interpreter::new();
+        interp.set_output(Box::new(output.clone()));
+        interp.exec("print 1 + 2; print \"x\";").unwrap();
+        assert_eq!(output.take(), "3\nx\n");
+        assert_eq!(output.take(), "");
+        // handing back the old output
+        interp.set_output(Box::new(std::io::sink())).write_all(b"y\n").unwrap();
+        assert_eq!(output.take(), "y\n");
+    }
+
+    #[test]
+    fn test_variadic_function() {
+        let program = "
+            fun count(first, ...rest) { return len(rest); }
+            var none = count(1);
+            var two = count(1, 2, 3);
+            fun sum(...numbers) {
+                var total = 0;
+                for (n in numbers) total = total + n;
+                return total;
+            }
+            var total = sum(1, 2, 3, 4);
+        ";
+        let interp = run_string(program).unwrap();
+        assert_eq!(global(&interp, "none"), LoxValue::LNumber(0.0));
+        assert_eq!(global(&interp, "two"), LoxValue::LNumber(2.0));
+        assert_eq!(global(&interp, "total"), LoxValue::LNumber(10.0));
+        assert_eq!(error_message("fun f(a, b, ...rest) {}\nf(1);"), "Line 2: f(): Expected 2 arguments but got 1.");
+    }
+
+    #[test]
+    fn test_arity() {
+        assert_eq!(error_message("fun f(a) {}\nf(1, 2);"), "Line 2: f(): Expected 1 argument but got 2.");
+        assert_eq!(error_message("fun f(a, b) {}\nf();"), "Line 2: f(): Expected 2 arguments but got 0.");
+        // a function called wrongly doesn't run at all
+        let mut interp = Interpreter::new();
+        assert!(interp.exec("var ran = false; fun f(a) { ran = true; } f(1, 2);").is_err());
+        assert_eq!(global(&interp, "ran"), LoxValue::LBoolean(false));
+        // the tail call path checks too
+        assert_eq!(
+            error_message("fun f(n) { return f(n, n); }\nf(1);"),
+            "Line 1: f(): Expected 1 argument but got 2.\n[line 1] in f()\n[line 2] in script"
+        );
+    }
+
+    #[test]
+    fn test_doc() {
+        let mut interp = Interpreter::new();
+        let program = "/// Twice n.\nfun double(n) { return n * 2; }\nfun bare() {}\n/// A shape.\nclass Shape {}\n\
+                       var a = doc(double); var b = doc(bare); var c = doc(Shape); var d = doc(1);";
+        interp.exec(program).unwrap();
+        assert_eq!(global(&interp, "a"), LoxValue::LString("Twice n.".into()));
+        assert_eq!(global(&interp, "b"), LoxValue::LNil);
+        assert_eq!(global(&interp, "c"), LoxValue::LString("A shape.".into()));
+        assert_eq!(global(&interp, "d"), LoxValue::LNil);
+    }
+
+    #[test]
+    fn test_classes() {
+        let program = "
+            class Point {
+                init(x, y) { this.x = x; this.y = y; }
+                sum() { return this.x + this.y; }
+            }
+            var p = Point(1, 2);
+            p.y = 10;
+            var sum = p.sum();
+            var method = p.sum;
+            p.x = 5;
+            var later = method();
+        ";
+        let interp = run_string(program).unwrap();
+        assert_eq!(global(&interp, "sum"), LoxValue::LNumber(11.0));
+        // a method taken off an instance stays bound to it
+        assert_eq!(global(&interp, "later"), LoxValue::LNumber(15.0));
+        assert_eq!(global(&interp, "p").to_string(), "Point instance");
+        assert_eq!(global(&interp, "Point").to_string(), "Point");
+        assert_eq!(error_message("class A {}\nA().x;"), "Line 2: Undefined property \"x\"");
+        assert_eq!(error_message("var n = 1;\nn.x = 2;"), "Line 2: Only instances have properties, not number");
+    }
+
+    #[test]
+    fn test_init() {
+        assert_eq!(error_message("class A { init(a) {} }\nA();"), "Line 2: init(): Expected 1 argument but got 0.");
+        assert_eq!(error_message("class A {}\nA(1);"), "Line 2: A(): Expected 0 arguments but got 1.");
+        // a bare return ends init early, and the instance still comes back,
+        // as it does from calling init again
+        let program = "
+            class A {
+                init(n) { this.n = n; if (n > 1) { return; } this.n = 0; }
+            }
+            var a = A(2);
+            var n = a.n;
+            var again = a.init(1);
+            var same = again == a;
+        ";
+        let interp = run_string(program).unwrap();
+        assert_eq!(global(&interp, "n"), LoxValue::LNumber(2.0));
+        assert_eq!(global(&interp, "same"), LoxValue::LBoolean(true));
+        let LoxValue::LInstance(again) = global(&interp, "again") else { panic!("not an instance") };
+        assert_eq!(again.fields.borrow().get("n"), Some(&LoxValue::LNumber(0.0)));
+        // returning anything else is a syntax error
+        let tokens = crate::tokenize::tokenize(crate::reader::Source::from("class A { init() { return 1; } }")).unwrap();
+        assert!(crate::parser::parse(&tokens).is_err());
+    }
+
+    #[test]
+    fn test_super() {
+        let program = "
+            class Shape {
+                init(name) { this.name = name; }
+                describe() { return (this.name + \" of area \") + this.units(); }
+                units() { return \"one\"; }
+                area() { return 0; }
+            }
+            class Square < Shape {
+                init(side) { super.init(\"square\"); this.side = side; }
+                area() { return this.side * this.side; }
+                units() { return \"many\"; }
+                describe() { return \"a \" + super.describe(); }
+            }
+            class Unit < Square {
+                init() { super.init(1); }
+            }
+            var square = Square(3).describe();
+            var area = Square(3).area();
+            var inherited = Unit().side;
+        ";
+        let interp = run_string(program).unwrap();
+        // super.describe() runs Shape's method, but this.units() is still Square's
+        assert_eq!(global(&interp, "square"), LoxValue::LString("a square of area many".into()));
+        assert_eq!(global(&interp, "area"), LoxValue::LNumber(9.0));
+        assert_eq!(global(&interp, "inherited"), LoxValue::LNumber(1.0));
+        assert_eq!(error_message("var A = 1;\nclass B < A {}"), "Superclass must be a class, not number");
+        assert_eq!(
+            error_message("class A {}\nclass B < A { m() { return super.m(); } }\nB().m();"),
+            "Line 2: Undefined property \"m\"\n[line 2] in m()\n[line 3] in script"
+        );
+    }
+
+    #[test]
+    fn test_tail_call() {
+        // would overflow the Rust stack without the tail call loop
+        let interp = run_string(
+            "fun count(n) { if (n == 0) return \"done\"; return count(n - 1); }
+             var result = count(1000000);",
+        ).unwrap();
+        assert_eq!(global(&interp, "result"), LoxValue::LString("done".into()));
+    }
+
+    #[test]
+    fn test_tail_call_keeps_closure() {
+        let interp = run_string(
+            "var step = 2;
+             fun down(n) { if (n < 1) return n; return down(n - step); }
+             var result = down(9);",
+        ).unwrap();
+        assert_eq!(global(&interp, "result"), LoxValue::LNumber(-1.0));
+    }
+
+    #[test]
+    fn test_tail_call_in_try() {
+        // the recursive call has to happen inside the try for the catch to
+        // see what it throws, so it can't be a tail call
+        let interp = run_string(
+            "fun f(n) {
+                 if (n == 0) throw \"bottom\";
+                 try { return f(n - 1); } catch (e) { return \"caught \" + e; }
+             }
+             fun g(n) {
+                 try { n = n - 1; } catch (e) {} finally { n = n - 1; }
+                 if (n <= 0) return n;
+                 return g(n);
+             }
+             var result = f(2);
+             var deep = g(200000);",
+        ).unwrap();
+        assert_eq!(global(&interp, "result"), LoxValue::LString("caught bottom".into()));
+        // after the try, tail calls work again
+        assert_eq!(global(&interp, "deep"), LoxValue::LNumber(0.0));
+    }
+
+    #[test]
+    fn test_switch_number() {
+        let program = "
+            fun name(n) {
+                switch (n) {
+                    case 1: return \"one\";
+                    case 2: var s = \"two\"; return s;
+                    default: return \"many\";
+                }
+            }
+            var a = name(1); var b = name(2); var c = name(3);";
+        let interp = run_string(program).unwrap();
+        assert_eq!(global(&interp, "a"), LoxValue::LString("one".into()));
+        assert_eq!(global(&interp, "b"), LoxValue::LString("two".into()));
+        assert_eq!(global(&interp, "c"), LoxValue::LString("many".into()));
+    }
+
+    #[test]
+    fn test_switch_string() {
+        let program = "
+            var x = 0;
+            switch (\"b\") {
+                case \"a\": x = 1;
+                case \"b\": x = 2;
+                case \"b\": x = 3;
+                default: x = 4;
+            }
+            var y = 0;
+            switch (\"z\") { case \"a\": y = 1; }";
+        let interp = run_string(program).unwrap();
+        // first matching arm only, no fallthrough into the default
+        assert_eq!(global(&interp, "x"), LoxValue::LNumber(2.0));
+        assert_eq!(global(&interp, "y"), LoxValue::LNumber(0.0));
+    }
+
+    #[test]
+    fn test_assign_outer_from_case() {
+        // a case body is a scope of its own, but assigning there changes the
+        // variable outside it rather than declaring a new one
+        let program = "
+            fun pick(n) {
+                var picked = \"none\";
+                switch (n) {
+                    case 1: picked = \"one\";
+                    default: { picked = \"other\"; }
+                }
+                return picked;
+            }
+            var total = 0;
+            { switch (2) { case 2: total = total + 2; } }
+            var a = pick(1); var b = pick(3);";
+        let interp = run_string(program).unwrap();
+        assert_eq!(global(&interp, "a"), LoxValue::LString("one".into()));
+        assert_eq!(global(&interp, "b"), LoxValue::LString("other".into()));
+        assert_eq!(global(&interp, "total"), LoxValue::LNumber(2.0));
+    }
+
+    #[test]
+    fn test_closure_binds_statically() {
+        // f sees the x that was in scope where it was written, not one
+        // declared later in the same block
+        let program = "
+            var result;
+            {
+                var x = \"outer\";
+                { fun f() { return x; } var x = \"inner\"; result = f(); }
+            }";
+        let interp = run_string(program).unwrap();
+        assert_eq!(global(&interp, "result"), LoxValue::LString("outer".into()));
+    }
+
+    #[test]
+    fn test_unresolved_names_are_global() {
+        // a local declared after the function doesn't take over its x, even
+        // once it's there
+        let output = SharedOutput::default();
+        let mut interp = Interpreter::new();
+        interp.set_output(Box::new(output.clone()));
+        interp.exec("var x = \"global\"; { fun show() { print x; } show(); var x = \"local\"; show(); }").unwrap();
+        assert_eq!(output.take(), "global\nglobal\n");
+        // nor can a function reach one by name from its call
+        let error = interp.exec("fun mk() { fun get() { return y; } var y = 5; return get; } print mk()();").unwrap_err();
+        assert_eq!(error.untraced().to_string(), "Undefined variable \"y\"");
+        // assigning one sets the global
+        interp.exec("{ fun set() { x = \"set\"; } var x = \"local\"; set(); }").unwrap();
+        assert_eq!(global(&interp, "x"), LoxValue::LString("set".into()));
+    }
+
+    // A timing comparison rather than a check of behaviour, so it only runs
+    // when asked for: cargo test --release -- --ignored local_slots
+    #[test]
+    #[ignore]
+    fn test_local_slots_faster() {
+        let program = "
+            fun count() {
+                var n = 0;
+                for (i in range(0, 1000)) for (j in range(0, 10000)) n = n + 1;
+                return n;
+            }
+            var total = count();";
+        let parse = || crate::parser::parse(&crate::tokenize::tokenize(Source::from(program)).unwrap()).unwrap();
+        let by_name = {
+            let mut interp = Interpreter::new();
+            let top_level = interp.top_level.clone();
+            let start = std::time::Instant::now();
+            interp.execute_statements(&parse().top, &top_level).unwrap();
+            assert_eq!(global(&interp, "total"), LoxValue::LNumber(1e7));
+            start.elapsed()
+        };
+        let by_slot = {
+            let mut interp = Interpreter::new();
+            let start = std::time::Instant::now();
+            interp.evaluate(parse()).unwrap();
+            assert_eq!(global(&interp, "total"), LoxValue::LNumber(1e7));
+            start.elapsed()
+        };
+        println!("by name: {by_name:?}, by slot: {by_slot:?}");
+        assert!(by_slot < by_name);
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        let mut interp = Interpreter::new();
+        interp.set_max_call_depth(10);
+        let program = "fun forever(n) { return 1 + forever(n); }\nforever(1);";
+        let ast = crate::parser::parse(&crate::tokenize::tokenize(Source::from(program)).unwrap()).unwrap();
+        let error = interp.evaluate(ast).unwrap_err();
+        assert!(matches!(error.untraced(), Error::StackOverflow { line: 1 }));
+        // the interpreter is still usable afterwards
+        assert!(interp.call_stack.is_empty());
+        let ast = crate::parser::parse(&crate::tokenize::tokenize(Source::from("var x = 1;")).unwrap()).unwrap();
+        assert!(interp.evaluate(ast).is_ok());
+        assert_eq!(global(&interp, "x"), LoxValue::LNumber(1.0));
+    }
+
+    #[test]
+    fn test_tail_calls_not_limited() {
+        let mut interp = Interpreter::new();
+        interp.set_max_call_depth(10);
+        let program = "fun count(n) { if (n == 0) return 0; return count(n - 1); } count(100);";
+        let ast = crate::parser::parse(&crate::tokenize::tokenize(Source::from(program)).unwrap()).unwrap();
+        assert!(interp.evaluate(ast).is_ok());
+    }
+
+    #[test]
+    fn test_try_catch() {
+        let program = "
+            var caught; var after = false;
+            try { throw \"boom\"; after = true; } catch (e) { caught = e; }";
+        let interp = run_string(program).unwrap();
+        assert_eq!(global(&interp, "caught"), LoxValue::LString("boom".into()));
+        assert_eq!(global(&interp, "after"), LoxValue::LBoolean(false));
+    }
+
+    #[test]
+    fn test_precedence_table() {
+        // every pair of binary operators groups as Operator::precedence says
+        use Operator::*;
+        let binary = [OOr, OAnd, OEq, ONe, OLt, OLe, OGt, OGe, OAdd, OSub, OMul, ODiv, OMod];
+        let mut interp = Interpreter::new();
+        let mut value = |source: &str| interp.eval_expr(source).map(|value| value.repr()).map_err(|e| e.to_string());
+        for first in binary {
+            for second in binary {
+                let grouped = if first.precedence() >= second.precedence() {
+                    format!("(7 {first} 3) {second} 2")
+                } else {
+                    format!("7 {first} (3 {second} 2)")
+                };
+                assert_eq!(value(&format!("7 {first} 3 {second} 2")), value(&grouped), "{grouped}");
+            }
+            // prefix operators bind tighter still
+            assert_eq!(value(&format!("-7 {first} 3")), value(&format!("(-7) {first} 3")), "-7 {first} 3");
+            assert_eq!(value(&format!("!nil {first} 3")), value(&format!("(!nil) {first} 3")), "!nil {first} 3");
+        }
+        assert_eq!(value("1 + 2 * 3 - 8 / 4 / 2"), Ok("6".to_string()));
+        assert_eq!(value("10 - 4 - 3"), Ok("3".to_string()));
+        assert_eq!(value("1 < 2 == 3 > 4"), Ok("false".to_string()));
+    }
+
+    #[test]
+    fn test_and_or() {
+        let program = "
+            var a = \"hi\" or nil;
+            var b = false or 42;
+            var c = nil and missing;
+            var d = 1 and \"one\";
+            var e = 0 or missing;
+            var name = nil;
+            var greeting = name or \"stranger\";";
+        let interp = run_string(program).unwrap();
+        assert_eq!(global(&interp, "a"), LoxValue::LString("hi".into()));
+        assert_eq!(global(&interp, "b"), LoxValue::LNumber(42.0));
+        // the right side of these is never evaluated
+        assert_eq!(global(&interp, "c"), LoxValue::LNil);
+        assert_eq!(global(&interp, "e"), LoxValue::LNumber(0.0));
+        assert_eq!(global(&interp, "d"), LoxValue::LString("one".into()));
+        assert_eq!(global(&interp, "greeting"), LoxValue::LString("stranger".into()));
+        assert_eq!(error_message("false or missing;"), "Undefined variable \"missing\"");
+    }
+
+    #[test]
+    fn test_catch_failed_assert() {
+        let program = "
+            var caught;
+            try { assert_eq(1 + 1, 3, \"arithmetic\"); } catch (e) { caught = e; }";
+        let interp = run_string(program).unwrap();
+        assert_eq!(global(&interp, "caught"), LoxValue::LString("arithmetic: 2 != 3".into()));
+        assert_eq!(error_message("assert(false, \"nope\");"), "Uncaught exception: nope");
+    }
+
+    #[test]
+    fn test_catch_runtime_error() {
+        let interp = run_string("var caught; try { 1 / 0; } catch (e) { caught = e; }").unwrap();
+        assert_eq!(global(&interp, "caught"), LoxValue::LError("Line 1: Division by zero".into()));
+    }
+
+    #[test]
+    fn test_finally() {
+        let program = "
+            var log = \"\";
+            fun f() {
+                try { return 1; } catch { log = log + \"catch \"; } finally { log = log + \"finally\"; }
+            }
+            var result = f();
+            try { try { throw 2; } catch { throw 3; } finally { log = log + \"!\"; } } catch (e) { result = e; }";
+        let interp = run_string(program).unwrap();
+        assert_eq!(global(&interp, "log"), LoxValue::LString("finally!".into()));
+        assert_eq!(global(&interp, "result"), LoxValue::LNumber(3.0));
+    }
+
+    #[test]
+    fn test_uncaught_throw() {
+        let result = run_string("fun f() { throw \"up\"; } f();");
+        let Err(error) = result else { panic!("expected an error") };
+        assert!(matches!(error.untraced(), Error::LoxThrow(LoxValue::LString(s)) if &**s == "up"));
+    }
+
+    #[test]
+    fn test_stack_trace() {
+        let program = "fun average(n) {\n  return n / 0;\n}\nfun middle(n) {\n  return 1 + average(n);\n}\nfun outer() {\n  middle(1);\n}\nouter();";
+        let Err(error) = run_string(program) else { panic!("expected an error") };
+        let Error::Traced { error, trace } = error else { panic!("expected a trace") };
+        assert!(matches!(*error, Error::ZeroDivision { line: 2 }));
+        let frames: Vec<(Option<&str>, Option<usize>)> =
+            trace.iter().map(|f| (f.function.as_deref(), f.line)).collect();
+        assert_eq!(
+            frames,
+            vec![(Some("average"), Some(2)), (Some("middle"), Some(5)), (Some("outer"), Some(8)), (None, Some(10))]
+        );
+        assert_eq!(
+            Error::Traced { error, trace }.to_string(),
+            "Line 2: Division by zero\n[line 2] in average()\n[line 5] in middle()\n[line 8] in outer()\n[line 10] in script"
+        );
+        // an error that doesn't know its line leaves it out
+        let Err(error) = run_string("fun f() {\n  return nope;\n}\nf();") else { panic!("expected an error") };
+        assert_eq!(error.to_string(), "Undefined variable \"nope\"\nin f()\n[line 4] in script");
+    }
+
+    #[test]
+    fn test_caught_errors_have_no_trace() {
+        let interp = run_string("fun f() { return 1 / 0; } var e; try { f(); } catch (x) { e = x; }").unwrap();
+        assert_eq!(global(&interp, "e"), LoxValue::LError("Line 1: Division by zero".into()));
+    }
+
+    // write a scratch file for the import tests, returning its path
+    fn write_module(name: &str, contents: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("lox-import-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, contents).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_import() {
+        let path = write_module("helpers.lox", "var greeting = \"hi\"; fun twice(x) { return x + x; }");
+        let interp = run_string(&format!("import {path:?}; var result = twice(greeting);")).unwrap();
+        assert_eq!(global(&interp, "result"), LoxValue::LString("hihi".into()));
+    }
+
+    #[test]
+    fn test_import_relative_and_once() {
+        write_module("counted.lox", "count = count + 1; var counted = true;");
+        let main = write_module("imports_twice.lox", "import \"counted.lox\";\nimport \"counted.lox\";");
+        let interp = run_string(&format!("var count = 0; import {main:?}; import {main:?};")).unwrap();
+        assert_eq!(global(&interp, "count"), LoxValue::LNumber(1.0));
+        assert_eq!(global(&interp, "counted"), LoxValue::LBoolean(true));
+    }
+
+    #[test]
+    fn test_search_path() {
+        let lib = std::env::temp_dir().join(format!("lox-search-{}", std::process::id()));
+        for (dir, value) in [("first", 1), ("second", 2)] {
+            std::fs::create_dir_all(lib.join(dir)).unwrap();
+            std::fs::write(lib.join(dir).join("found.lox"), format!("var found = {value};")).unwrap();
+        }
+        std::fs::write(lib.join("second").join("only.lox"), "var only = true;").unwrap();
+        let tokens = crate::tokenize::tokenize(Source::from("import \"found.lox\"; import \"only.lox\";")).unwrap();
+        let mut interp = LoxBuilder::new().search_path(lib.join("first")).search_path(lib.join("second")).build();
+        interp.evaluate(crate::parser::parse(&tokens).unwrap()).unwrap();
+        // the first directory with the file wins
+        assert_eq!(global(&interp, "found"), LoxValue::LNumber(1.0));
+        assert_eq!(global(&interp, "only"), LoxValue::LBoolean(true));
+        // a file beside the importer comes before the search path
+        let main = write_module("found.lox", "var found = 0;");
+        let mut interp = Interpreter::new();
+        interp.add_search_path(lib.join("first"));
+        interp.set_directory(Path::new(&main).parent().unwrap());
+        interp.exec("import \"found.lox\";").unwrap();
+        assert_eq!(global(&interp, "found"), LoxValue::LNumber(0.0));
+        assert!(matches!(interp.exec("import \"nowhere.lox\";"), Err(Error::Import { .. })));
+    }
+
+    #[test]
+    fn test_run_file() {
+        write_module("stdlib_helpers.lox", "fun twice(x) { return x * 2; }");
+        let stdlib = write_module("stdlib.lox", "import \"stdlib_helpers.lox\"; fun quad(x) { return twice(twice(x)); }");
+        let mut interp = Interpreter::new();
+        interp.run_file(&stdlib).unwrap();
+        // what it declared stays, for later input to use
+        assert_eq!(interp.eval_expr("quad(3)").unwrap(), LoxValue::LNumber(12.0));
+        interp.exec("var sixteen = quad(4);").unwrap();
+        assert_eq!(global(&interp, "sixteen"), LoxValue::LNumber(16.0));
+        assert!(matches!(interp.run_file("no/such/file.lox"), Err(Error::Read(_))));
+        // importing it afterwards doesn't run it again
+        let counted = write_module("run_then_imported.lox", "runs = runs + 1;");
+        interp.exec("var runs = 0;").unwrap();
+        interp.run_file(&counted).unwrap();
+        interp.exec(&format!("import {counted:?};")).unwrap();
+        assert_eq!(global(&interp, "runs"), LoxValue::LNumber(1.0));
+        // nor can it import itself
+        let looped = write_module("run_imports_itself.lox", "import \"run_imports_itself.lox\";");
+        assert!(matches!(interp.run_file(&looped), Err(Error::Import { error: ImportError::Circular, .. })));
+        let broken = write_module("broken.lox", "var = 1;");
+        assert!(matches!(interp.run_file(&broken), Err(Error::Parse(_))));
+    }
+
+    #[test]
+    fn test_strict_scopes_import() {
+        let path = write_module("redeclares.lox", "var twice = 1;\nvar twice = 2;");
+        let program = format!("import {path:?};");
+        assert!(run_string(&program).is_ok());
+        let options = InterpreterOptions { strict_scopes: true, ..InterpreterOptions::default() };
+        let mut interp = LoxBuilder::new().options(options).build();
+        let error = interp.exec(&program).unwrap_err();
+        assert!(matches!(error, Error::Import { error: ImportError::Parse(parser::Error::SyntaxError { line: 2, .. }), .. }));
+    }
+
+    #[test]
+    fn test_circular_import() {
+        let a = write_module("cycle_a.lox", "");
+        let b = write_module("cycle_b.lox", &format!("import {a:?};"));
+        std::fs::write(&a, format!("import {b:?};")).unwrap();
+        let Err(error) = run_string(&format!("import {a:?};")) else { panic!("expected an error") };
+        // reported against the innermost import that closes the cycle
+        assert!(matches!(error, Error::Import { error: ImportError::Circular, .. }));
+    }
+
+    #[test]
+    fn test_import_missing_file() {
+        let Err(error) = run_string("var x = 1;\nimport \"no/such/module.lox\";") else { panic!("expected an error") };
+        assert!(error.to_string().starts_with("Line 2: Can't import \"no/such/module.lox\""));
+        assert!(matches!(error, Error::Import { error: ImportError::Read(reader::Error::NotFound(_)), .. }));
+    }
+
+    fn error_message(program: &str) -> String {
+        match run_string(program) {
+            Err(e) => e.to_string(),
+            Ok(_) => panic!("expected an error"),
+        }
+    }
+
+    #[test]
+    fn test_operator_messages() {
+        assert_eq!(
+            error_message("\"a\" * 3;"),
+            "Line 1: Operands must be numbers: \"a\" * 3 (string * number)"
+        );
+        assert_eq!(
+            error_message("\"a\" + 3;"),
+            "Line 1: Operands must be two numbers or two strings: \"a\" + 3 (string + number)"
+        );
+        assert_eq!(
+            error_message("nil < true;"),
+            "Line 1: Operands must be numbers: nil < true (nil < boolean)"
+        );
+        assert_eq!(
+            error_message("-\"x\";"),
+            "Line 1: Operand must be a number: -\"x\" (string)"
+        );
+        assert_eq!(error_message("print nope;"), "Undefined variable \"nope\"");
+    }
+
+    #[test]
+    fn test_for_in() {
+        let program = "
+            var total = 0;
+            for (x in [1, 2, 3]) total = total + x;
+            var letters = \"\";
+            for (c in \"abc\") { letters = c + letters; }";
+        let interp = run_string(program).unwrap();
+        assert_eq!(global(&interp, "total"), LoxValue::LNumber(6.0));
+        assert_eq!(global(&interp, "letters"), LoxValue::LString("cba".into()));
+        // the loop variable lives in the loop's own scope
+        assert!(interp.top_level.lookup("x").is_none());
+    }
+
+    #[test]
+    fn test_c_style_for() {
+        let program = "
+            var total = 0;
+            for (var i = 1; i <= 4; i = i + 1) total = total + i;
+            var n = 0;
+            for (; n < 3;) n = n + 1;
+            var steps = 0;
+            while (steps < 5) steps = steps + 2;
+            for (var x in [1, 2]) steps = steps + x;";
+        let interp = run_string(program).unwrap();
+        assert_eq!(global(&interp, "total"), LoxValue::LNumber(10.0));
+        assert_eq!(global(&interp, "n"), LoxValue::LNumber(3.0));
+        assert_eq!(global(&interp, "steps"), LoxValue::LNumber(9.0));
+        // as with for-in, the loop variable is the loop's own
+        assert!(interp.top_level.lookup("i").is_none());
+        let mut interp = Interpreter::new();
+        let program = "fun count() { var n = 0; for (;;) { n = n + 1; if (n == 7) return n; } } var seven = count();";
+        interp.exec(program).unwrap();
+        assert_eq!(interp.get_global("seven"), Some(LoxValue::LNumber(7.0)));
+    }
+
+    #[test]
+    fn test_assignment_value() {
+        let program = "
+            var a; var b;
+            a = b = 3;
+            var c = (a = 4) + b;
+            var d = nil or (b = 5);
+            class Box {}
+            var box = Box();
+            var e = box.x = a = 6;
+            var i = 0; var item; var total = 0;
+            fun next() { i = i + 1; return i; }
+            while ((item = next()) != 3) total = total + item;";
+        let interp = run_string(program).unwrap();
+        for (name, value) in [("a", 6.0), ("b", 5.0), ("c", 7.0), ("d", 5.0), ("e", 6.0), ("total", 3.0), ("item", 3.0)] {
+            assert_eq!(global(&interp, name), LoxValue::LNumber(value), "{name}");
+        }
+        let output = SharedOutput::default();
+        let mut interp = Interpreter::new();
+        interp.set_output(Box::new(output.clone()));
+        interp.exec("var a; print a = 3;").unwrap();
+        assert_eq!(output.take(), "3\n");
+    }
+
+    #[test]
+    fn test_strict_variables() {
+        let program = "var declared; declared = 1; fun f() { undeclared = 2; } f();";
+        let mut interp = Interpreter::new();
+        interp.exec(program).unwrap();
+        assert_eq!(interp.get_global("undeclared"), Some(LoxValue::LNumber(2.0)));
+        let options = InterpreterOptions { strict_variables: true, ..InterpreterOptions::default() };
+        let mut interp = LoxBuilder::new().options(options).build();
+        let error = interp.exec(program).unwrap_err();
+        assert_eq!(error.untraced().to_string(), "Undefined variable \"undeclared\"");
+        assert_eq!(interp.get_global("declared"), Some(LoxValue::LNumber(1.0)));
+        assert_eq!(interp.get_global("undeclared"), None);
+    }
+
+    #[test]
+    fn test_for_in_not_iterable() {
+        assert_eq!(
+            error_message("for (x in 5) print x;"),
+            "Can only iterate over lists and strings, not number"
+        );
+    }
+
+    #[test]
+    fn test_repr_and_display() {
+        use LoxValue::*;
+        let cases = [
+            (LString("abc".into()), "abc", "\"abc\""),
+            (LString("say \"hi\"\n".into()), "say \"hi\"\n", "\"say \\\"hi\\\"\\n\""),
+            (LNumber(1.5), "1.5", "1.5"),
+            (LNil, "nil", "nil"),
+            (LBoolean(true), "true", "true"),
+            (LList(Rc::new(RefCell::new(vec![LNumber(1.0), LString("a".into())]))), "[1, \"a\"]", "[1, \"a\"]"),
+        ];
+        for (value, display, repr) in cases {
+            assert_eq!(value.to_string(), display);
+            assert_eq!(value.repr(), repr);
+        }
+    }
+
+    #[test]
+    fn test_display_cycles() {
+        let mut interp = Interpreter::new();
+        interp.exec("var m = {}; set(m, \"self\", m); var l = {}; set(l, 1, [l]); var both = [m, m];").unwrap();
+        let m = interp.get_global("m").unwrap();
+        assert_eq!(m.to_string(), "{\"self\": {...}}");
+        assert_eq!(m.repr(), "{\"self\": {...}}");
+        assert_eq!(interp.get_global("l").unwrap().repr(), "{1: [{...}]}");
+        // the same map twice, but not inside itself
+        assert_eq!(interp.get_global("both").unwrap().to_string(), "[{\"self\": {...}}, {\"self\": {...}}]");
+    }
+
+    #[test]
+    fn test_to_lox_literal() {
+        let mut interp = Interpreter::new();
+        interp.exec("fun f() {} var cycle = {}; set(cycle, \"again\", [cycle]);").unwrap();
+        let programs = ["nil", "true", "-2.5", "0.000001", "\"two\nlines\"", "[1, [\"a\"], {}]", "{\"k\": [nil]}"];
+        for program in programs {
+            let value = interp.eval_expr(program).unwrap();
+            // what it writes reads back as the same value
+            let literal = to_lox_literal(&value).unwrap();
+            assert_eq!(interp.eval_expr(&literal).unwrap().repr(), value.repr(), "{program}");
+        }
+        for name in ["f", "len", "cycle"] {
+            assert_eq!(to_lox_literal(&interp.get_global(name).unwrap()), None, "{name}");
+        }
+        // which repr shows some other way
+        assert_eq!(interp.get_global("cycle").unwrap().repr(), "{\"again\": [{...}]}");
+        assert_eq!(to_lox_literal(&LoxValue::LString("say \"hi\"".into())), None);
+        assert_eq!(to_lox_literal(&LoxValue::LNumber(f64::NAN)), None);
+        assert_eq!(to_lox_literal(&LoxValue::LNumber(1e100)).as_deref(), Some("1e100"));
+    }
+
+    #[test]
+    fn test_value_size() {
+        // a String inline made this 32
+        assert_eq!(std::mem::size_of::<LoxValue>(), 24);
+    }
+
+    #[test]
+    fn test_strings() {
+        let interp = run_string(
+            "var a = \"ab\"; var b = a; var c = \"a\" + \"b\"; var d = c + \"!\";
+             var same = a == c; var different = a == \"abc\";",
+        )
+        .unwrap();
+        assert_eq!(global(&interp, "c"), LoxValue::LString("ab".into()));
+        assert_eq!(global(&interp, "d"), LoxValue::LString("ab!".into()));
+        assert_eq!(global(&interp, "same"), LoxValue::LBoolean(true));
+        assert_eq!(global(&interp, "different"), LoxValue::LBoolean(false));
+        // assigning a string shares it rather than copying it
+        let (LoxValue::LString(a), LoxValue::LString(b)) = (global(&interp, "a"), global(&interp, "b")) else {
+            panic!("expected strings")
+        };
+        assert!(Rc::ptr_eq(&a, &b));
+    }
+
+    #[test]
+    fn test_eval() {
+        let interp = run_string(
+            "var sum = eval(\"1 + 2;\");
+             var declared = eval(\"var y = 5; fun twice(n) { return 2 * n; }\");
+             var called = twice(y);
+             var returned = eval(\"return 4; print 1;\");
+             fun f() { var local = 1; try { eval(\"local;\"); } catch (e) { return e; } }
+             var hidden = f();
+             var broken = nil;
+             try { eval(\"1 +;\"); } catch (e) { broken = e; }",
+        )
+        .unwrap();
+        assert_eq!(global(&interp, "sum"), LoxValue::LNumber(3.0));
+        assert_eq!(global(&interp, "declared"), LoxValue::LNil);
+        assert_eq!(global(&interp, "called"), LoxValue::LNumber(10.0));
+        assert_eq!(global(&interp, "returned"), LoxValue::LNumber(4.0));
+        // only globals are visible to the evaluated code
+        assert_eq!(global(&interp, "hidden"), LoxValue::LError("Undefined variable \"local\"".into()));
+        assert!(matches!(global(&interp, "broken"), LoxValue::LError(message) if message.contains("Syntax error")));
+
+        // a `return f()` in evaluated code isn't a tail call of the function calling eval
+        let interp = run_string("fun f(n) { if (n > 0) { return eval(\"f(0);\") + 1; } return 0; } var x = f(1);").unwrap();
+        assert_eq!(global(&interp, "x"), LoxValue::LNumber(1.0));
+        let interp = run_string("fun g(n) { if (n > 0) { return eval(\"return g(0);\"); } return 7; } var x = g(1);").unwrap();
+        assert_eq!(global(&interp, "x"), LoxValue::LNumber(7.0));
+    }
+
+    #[test]
+    fn test_profiler() {
+        let mut interp = Interpreter::new_with_options(InterpreterOptions { profile: true, ..Default::default() });
+        interp.exec("fun fib(n) { if (n < 2) { return n; } return fib(n - 1) + fib(n - 2); } var x = fib(5);").unwrap();
+        let profiler = interp.profiler().unwrap();
+        assert_eq!(profiler.function_calls["fib"], 15);
+        assert_eq!(profiler.statement_counts["if"], 15);
+        assert_eq!(profiler.statement_counts["var"], 1);
+        assert!(Interpreter::new().profiler().is_none());
+    }
+
+    #[test]
+    fn test_stats() {
+        let stats = |program: &str| {
+            let mut interp = Interpreter::new_with_options(InterpreterOptions { stats: true, ..Default::default() });
+            interp.exec(program).unwrap();
+            interp.stats().unwrap().clone()
+        };
+        // var and for, then each time round a block holding one statement
+        // that evaluates `total = total + i`: four expressions, one binary
+        assert_eq!(
+            stats("var total = 0; for (i in range(0, 3)) { total = total + i; }"),
+            ExecStats { statements: 8, expressions: 17, binary: 3, unary: 0, calls: 1, max_env_depth: 2, max_call_depth: 0 }
+        );
+        let recursive = stats("fun f(n) { if (n > 0) { f(n - 1); } } f(-(-2));");
+        assert_eq!((recursive.unary, recursive.calls, recursive.max_call_depth), (2, 3, 3));
+        // tail calls count too, though they don't nest
+        let tail = stats("fun f(n) { if (n > 0) return f(n - 1); return 0; } f(3);");
+        assert_eq!((tail.statements, tail.expressions, tail.binary, tail.calls, tail.max_call_depth), (10, 31, 7, 4, 1));
+        assert!(Interpreter::new().stats().is_none());
+    }
+
+    #[test]
+    fn test_disable_eval() {
+        let mut interp = LoxBuilder::new().max_call_depth(3).disable_eval().build();
+        assert!(matches!(interp.exec("eval(\"1\");"), Err(Error::UndefinedVariable(name)) if name == "eval"));
+        interp.reset();
+        assert_eq!(interp.get_global("eval"), None);
+        assert!(matches!(
+            interp.exec("fun f() { return 1 + f(); } f();").unwrap_err().untraced(),
+            Error::StackOverflow { .. }
+        ));
+        assert!(LoxBuilder::new().build().get_global("eval").is_some());
+    }
+
+    #[test]
+    fn test_natives() {
+        let program = "var total = 0; for (i in range(0, 5)) total = total + i; var n = len(range(1, 5));";
+        let interp = run_string(program).unwrap();
+        assert_eq!(global(&interp, "total"), LoxValue::LNumber(10.0));
+        assert_eq!(global(&interp, "n"), LoxValue::LNumber(4.0));
+        assert_eq!(error_message("range(1);"), "Line 1: range(): Expected 2 arguments but got 1.");
+        assert_eq!(error_message("range(1, 2, 3, 4);"), "Line 1: range(): Expected 3 arguments but got 4.");
+    }
+
+    // run a program with `nan` and `inf` predeclared, returning `result`
+    fn float_result(program: &str) -> LoxValue {
+        let mut interp = Interpreter::new();
+        interp.top_level.declare("nan", LoxValue::LNumber(f64::NAN));
+        interp.top_level.declare("inf", LoxValue::LNumber(f64::INFINITY));
+        let ast = crate::parser::parse(&crate::tokenize::tokenize(Source::from(program)).unwrap()).unwrap();
+        interp.evaluate(ast).unwrap();
+        global(&interp, "result")
+    }
+
+    #[test]
+    fn test_number_format() {
+        for (n, formatted) in [
+            (1.0, "1"),
+            (-3.0, "-3"),
+            (0.0, "0"),
+            (2.5, "2.5"),
+            (0.1 + 0.2, "0.30000000000000004"),
+            (1e20, "100000000000000000000"),
+            (1e21, "1e21"),
+            (1e100, "1e100"),
+            (-1.5e300, "-1.5e300"),
+            (0.000001, "0.000001"),
+            (1.5e-7, "1.5e-7"),
+            (f64::MAX, "1.7976931348623157e308"),
+            (f64::MIN_POSITIVE, "2.2250738585072014e-308"),
+        ] {
+            assert_eq!(lox_fmt_number(n), formatted);
+        }
+        let interp = run_string("var big = [1000000000000000000000, 2.0];").unwrap();
+        assert_eq!(global(&interp, "big").to_string(), "[1e21, 2]");
+    }
+
+    #[test]
+    fn test_numbers_round_trip() {
+        // printed, scanned, parsed and evaluated again, every finite number
+        // comes back with the same bits
+        let mut interp = Interpreter::new();
+        let mut round_trip = |n: f64| {
+            let printed = lox_fmt_number(n);
+            let LoxValue::LNumber(back) = interp.eval_expr(&printed).unwrap() else { panic!("{printed}") };
+            assert_eq!(back.to_bits(), n.to_bits(), "{printed}");
+        };
+        for n in [0.0, -0.0, 0.1 + 0.2, f64::MAX, f64::MIN, f64::MIN_POSITIVE, f64::EPSILON, 5e-324, 1e21, 1e-7, 123456789.125] {
+            round_trip(n);
+        }
+        // random bit patterns, from a fixed xorshift so failures repeat
+        let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+        for _ in 0..20_000 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let n = f64::from_bits(state);
+            if n.is_finite() {
+                round_trip(n);
+            }
+        }
+    }
+
+    #[test]
+    fn test_number_precision() {
+        let rounded = NumberFormat { max_precision: Some(3), trim_trailing_zeros: false };
+        let trimmed = NumberFormat { trim_trailing_zeros: true, ..rounded };
+        for (n, by_rounded, by_trimmed) in [
+            (0.1 + 0.2, "0.300", "0.3"),
+            (2.0 / 3.0, "0.667", "0.667"),
+            (-2.0 / 3.0, "-0.667", "-0.667"),
+            (-0.0001, "-0.000", "-0"),
+            (42.0, "42.000", "42"),
+            (1200.5, "1200.500", "1200.5"),
+            (1.5e21, "1.500e21", "1.5e21"),
+            (-1e100, "-1.000e100", "-1e100"),
+            (1.23456e-7, "1.235e-7", "1.235e-7"),
+            (f64::NAN, "NaN", "NaN"),
+        ] {
+            assert_eq!(rounded.format(n), by_rounded);
+            assert_eq!(trimmed.format(n), by_trimmed);
+        }
+        // the default changes nothing
+        assert_eq!(NumberFormat::default().format(0.1 + 0.2), "0.30000000000000004");
+
+        let output = SharedOutput::default();
+        let mut interp = LoxBuilder::new().number_format(trimmed).build();
+        interp.set_output(Box::new(output.clone()));
+        interp.exec("print 0.1 + 0.2; print 10; print \"0.30000\"; print to_fixed(0.5, 2);").unwrap();
+        assert_eq!(output.take(), "0.3\n10\n0.30000\n0.50\n");
+        // numbers in lists and maps too
+        interp.exec("print [0.1 + 0.2, \"0.30000\", {\"third\": 1 / 3}];").unwrap();
+        assert_eq!(output.take(), "[0.3, \"0.30000\", {\"third\": 0.333}]\n");
+    }
+
+    #[test]
+    fn test_nan_and_negative_zero() {
+        use LoxValue::*;
+        assert_eq!(float_result("var result = nan == nan;"), LBoolean(false));
+        assert_eq!(float_result("var result = nan != nan;"), LBoolean(true));
+        assert_eq!(float_result("var result = nan < 1;"), LBoolean(false));
+        assert_eq!(float_result("var result = 0 == -0;"), LBoolean(true));
+        assert_eq!(float_result("var result = inf == inf;"), LBoolean(true));
+        assert_eq!(float_result("var result = -0;").to_string(), "-0");
+        assert_eq!(LNumber(f64::NAN).to_string(), "NaN");
+        assert_eq!(LNumber(f64::INFINITY).to_string(), "inf");
+        assert_eq!(LNumber(f64::NEG_INFINITY).to_string(), "-inf");
+    }
+
+    #[test]
+    fn test_division_by_zero() {
+        assert!(matches!(run_string("0 / 0;"), Err(Error::ZeroDivision { line: 1 })));
+        assert!(matches!(run_string("1 / -0;"), Err(Error::ZeroDivision { line: 1 })));
+        assert_eq!(error_message("var x = 1;\nprint floor_div(x, 0);"), "Line 2: Division by zero");
+        let caught = run_string("var e; try { floor_div(1, 0); } catch (x) { e = x; }").unwrap();
+        assert_eq!(global(&caught, "e"), LoxValue::LError("Line 1: Division by zero".into()));
+        assert_eq!(float_result("var result = 1 / inf;"), LoxValue::LNumber(0.0));
+    }
+
+    #[test]
+    fn test_strict_and_lenient_arithmetic() {
+        let fixture = "var inf = 1 / 0; var nan = 0 / 0; var s = \"n=\" + 1; var t = nil + \"!\";";
+        let run_with = |strict_arithmetic| {
+            let mut interp = Interpreter::new_with_options(InterpreterOptions { strict_arithmetic, ..Default::default() });
+            let ast = crate::parser::parse(&crate::tokenize::tokenize(Source::from(fixture)).unwrap()).unwrap();
+            interp.evaluate(ast).map(|_| interp)
+        };
+
+        let Err(error) = run_with(true) else { panic!("expected an error") };
+        assert!(matches!(error, Error::ZeroDivision { line: 1 }));
+
+        let Ok(interp) = run_with(false) else { panic!("expected success") };
+        assert_eq!(global(&interp, "inf"), LoxValue::LNumber(f64::INFINITY));
+        assert!(matches!(global(&interp, "nan"), LoxValue::LNumber(n) if n.is_nan()));
+        assert_eq!(global(&interp, "s"), LoxValue::LString("n=1".into()));
+        assert_eq!(global(&interp, "t"), LoxValue::LString("nil!".into()));
+    }
+
+    #[test]
+    fn test_modulo() {
+        let interp = run_string("var m = -7; var n = -3;\nvar a = m % 3; var b = 7 % n; var c = 7 % 3; var d = m % n;").unwrap();
+        assert_eq!(global(&interp, "a"), LoxValue::LNumber(2.0));
+        assert_eq!(global(&interp, "b"), LoxValue::LNumber(-2.0));
+        assert_eq!(global(&interp, "c"), LoxValue::LNumber(1.0));
+        assert_eq!(global(&interp, "d"), LoxValue::LNumber(-1.0));
+        assert_eq!(floor_mod(1e300, 7.0), 1e300 % 7.0);
+        assert_eq!(error_message("var x = 1;\nx % 0;"), "Line 2: Modulo by zero");
+    }
+
+    #[test]
+    fn test_floor_div() {
+        assert_eq!(floor_div(7.0, 2.0), 3.0);
+        assert_eq!(floor_div(-7.0, 2.0), -4.0);
+        assert_eq!(floor_div(7.0, -2.0), -4.0);
+        assert_eq!(floor_div(-7.0, -2.0), 3.0);
+        for (x, y) in [(-7.0, 3.0), (7.0, -3.0), (-1e17, 3.0)] {
+            assert_eq!(floor_div(x, y) * y + floor_mod(x, y), x);
+        }
+    }
+
+    #[test]
+    fn test_maps() {
+        let program = "
+            var ages = {\"ann\": 31, \"bob\": 4 * 10};
+            var bob = get(ages, \"bob\");
+            set(ages, \"cy\", 2);
+            var empty = {};
+            var missing = get(empty, 1);
+        ";
+        let interp = run_string(program).unwrap();
+        assert_eq!(global(&interp, "bob"), LoxValue::LNumber(40.0));
+        assert_eq!(global(&interp, "missing"), LoxValue::LNil);
+        assert_eq!(global(&interp, "ages").to_string(), "{\"ann\": 31, \"bob\": 40, \"cy\": 2}");
+        assert_eq!(global(&interp, "empty").to_string(), "{}");
+        // a block is still a block
+        assert!(run_string("{ var x = 1; }").is_ok());
+        assert_eq!(error_message("var m = {[1]: 2};"), "a list can't be a map key");
+    }
+
+    #[test]
+    fn test_map_keys() {
+        let mut map = HashMap::new();
+        map.insert(LoxValue::LNumber(0.0), 1);
+        assert_eq!(map.get(&LoxValue::LNumber(-0.0)), Some(&1));
+        assert_eq!(map.get(&LoxValue::LString("0".into())), None);
+    }
+
+    #[test]
+    fn type_names() {
+        assert_eq!(LoxValue::LNil.type_name(), "nil");
+        assert_eq!(LoxValue::LBoolean(true).type_name(), "boolean");
+        assert_eq!(LoxValue::LNumber(1.0).type_name(), "number");
+        assert_eq!(LoxValue::LString("a".into()).type_name(), "string");
+        assert_eq!(LoxValue::LList(Rc::new(RefCell::new(vec![]))).type_name(), "list");
     }
 }
@@ -0,0 +1,50 @@
+// One shared copy of each distinct string, for names that come up over and
+// over. The first time a string is interned it's allocated; after that the
+// same Rc comes back, and cloning that costs nothing.
+use std::collections::HashSet;
+use std::rc::Rc;
+
+#[derive(Debug, Default)]
+pub struct StringInterner {
+    strings: HashSet<Rc<str>>,
+}
+
+impl StringInterner {
+    pub fn new() -> StringInterner {
+        StringInterner::default()
+    }
+
+    pub fn intern(&mut self, s: &str) -> Rc<str> {
+        if let Some(interned) = self.strings.get(s) {
+            return interned.clone();
+        }
+        let interned: Rc<str> = s.into();
+        self.strings.insert(interned.clone());
+        interned
+    }
+
+    // how many distinct strings there are
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intern() {
+        let mut interner = StringInterner::new();
+        let first = interner.intern("count");
+        let again = interner.intern(&String::from("count"));
+        assert!(Rc::ptr_eq(&first, &again));
+        assert!(!Rc::ptr_eq(&first, &interner.intern("total")));
+        assert_eq!(&*again, "count");
+        assert_eq!(interner.len(), 2);
+    }
+}
@@ -0,0 +1,306 @@
+// Converting between Lox values and serde_json::Value, for hosts that pass
+// structured data into a program and read results back out.
+//
+// JSON objects become maps with string keys and arrays become lists.
+// Going the other way, anything JSON has no way to write (functions, NaN,
+// maps with non-string keys, a list or map that contains itself) is an error.
+//
+// Parsed programs can be written out too, for tools that want to look at
+// the syntax tree: each node is an object whose "type" says what it is.
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use serde_json::{json, Map, Number, Value};
+
+use crate::ast::{Expr, Stmt, AST};
+use crate::evaluate::{Error, Interpreter, LoxValue};
+
+// 2^53: beyond this an f64 can't hold every integer
+const MAX_EXACT_INTEGER: f64 = 9007199254740992.0;
+
+impl TryFrom<&Value> for LoxValue {
+    type Error = Error;
+
+    fn try_from(value: &Value) -> Result<LoxValue, Error> {
+        Ok(match value {
+            Value::Null => LoxValue::LNil,
+            Value::Bool(b) => LoxValue::LBoolean(*b),
+            // without serde_json's arbitrary_precision every number has an
+            // f64 form, if only an approximate one
+            Value::Number(n) => match n.as_f64() {
+                Some(n) => LoxValue::LNumber(n),
+                None => return Err(Error::ValueError(format!("{n} can't be a Lox number"))),
+            },
+            Value::String(s) => LoxValue::LString(s.as_str().into()),
+            Value::Array(elements) => {
+                let elements = elements.iter().map(LoxValue::try_from).collect::<Result<_, _>>()?;
+                LoxValue::LList(Rc::new(RefCell::new(elements)))
+            }
+            Value::Object(entries) => {
+                let mut map = HashMap::new();
+                for (key, value) in entries {
+                    map.insert(LoxValue::LString(key.as_str().into()), LoxValue::try_from(value)?);
+                }
+                LoxValue::LMap(Rc::new(RefCell::new(map)))
+            }
+        })
+    }
+}
+
+impl LoxValue {
+    pub fn to_json(&self) -> Result<Value, Error> {
+        self.to_json_inside(&mut Vec::new())
+    }
+
+    // containing holds the lists and maps we're already inside, so that a
+    // cycle is an error rather than endless recursion
+    fn to_json_inside(&self, containing: &mut Vec<*const ()>) -> Result<Value, Error> {
+        let not_json = |what: &str| Error::ValueError(format!("{what} can't be converted to JSON"));
+        let container = match self {
+            LoxValue::LList(list) => Rc::as_ptr(list) as *const (),
+            LoxValue::LMap(map) => Rc::as_ptr(map) as *const (),
+            _ => std::ptr::null(),
+        };
+        if !container.is_null() {
+            if containing.contains(&container) {
+                return Err(not_json(&format!("a {} that contains itself", self.type_name())));
+            }
+            containing.push(container);
+        }
+        let json = match self {
+            LoxValue::LNil => Value::Null,
+            LoxValue::LBoolean(b) => Value::Bool(*b),
+            // whole numbers go out as JSON integers, as they most likely came in
+            LoxValue::LNumber(n) if n.fract() == 0.0 && n.abs() < MAX_EXACT_INTEGER => Value::from(*n as i64),
+            LoxValue::LNumber(n) => match Number::from_f64(*n) {
+                Some(n) => Value::Number(n),
+                None => return Err(not_json(&self.to_string())),
+            },
+            LoxValue::LString(s) => Value::String(s.to_string()),
+            LoxValue::LList(list) => Value::Array(
+                list.borrow().iter().map(|element| element.to_json_inside(containing)).collect::<Result<_, _>>()?,
+            ),
+            LoxValue::LMap(map) => {
+                let mut object = Map::new();
+                for (key, value) in map.borrow().iter() {
+                    let LoxValue::LString(key) = key else {
+                        return Err(not_json(&format!("a map with a {} key", key.type_name())));
+                    };
+                    object.insert(key.to_string(), value.to_json_inside(containing)?);
+                }
+                Value::Object(object)
+            }
+            LoxValue::LFunction(_) | LoxValue::LNative(_) | LoxValue::LError(_) | LoxValue::LClass(_) => {
+                return Err(not_json(&format!("a {}", self.type_name())));
+            }
+            LoxValue::LInstance(instance) => return Err(not_json(&format!("a {} instance", instance.class.name))),
+        };
+        if !container.is_null() {
+            containing.pop();
+        }
+        Ok(json)
+    }
+}
+
+impl Interpreter {
+    // declare a global holding the Lox form of value
+    pub fn set_global_json(&mut self, name: &str, value: &Value) -> Result<(), Error> {
+        let value = LoxValue::try_from(value)?;
+        self.set_global(name, value);
+        Ok(())
+    }
+
+    pub fn get_global_json(&self, name: &str) -> Result<Value, Error> {
+        match self.get_global(name) {
+            Some(value) => value.to_json(),
+            None => Err(Error::UndefinedVariable(name.to_string())),
+        }
+    }
+}
+
+impl AST {
+    // the top-level statements, as a JSON array
+    pub fn to_json(&self) -> Value {
+        statements_json(&self.top)
+    }
+}
+
+fn statements_json(statements: &[Stmt]) -> Value {
+    Value::Array(statements.iter().map(Stmt::to_json).collect())
+}
+
+impl Stmt {
+    pub fn to_json(&self) -> Value {
+        let optional = |expr: &Option<Expr>| expr.as_ref().map_or(Value::Null, Expr::to_json);
+        match self {
+            Stmt::SPrint { expr, .. } => json!({"type": "print", "expr": expr.to_json()}),
+            Stmt::SExpression { expr, .. } => json!({"type": "expression", "expr": expr.to_json()}),
+            Stmt::SVarDecl { name, initializer, .. } => {
+                json!({"type": "var", "name": &**name, "initializer": optional(initializer)})
+            }
+            Stmt::SBlock { statements, .. } => json!({"type": "block", "statements": statements_json(statements)}),
+            Stmt::SIf { condition, then_branch, else_branch, .. } => json!({
+                "type": "if",
+                "condition": condition.to_json(),
+                "then": then_branch.to_json(),
+                "else": else_branch.as_ref().map_or(Value::Null, |branch| branch.to_json()),
+            }),
+            Stmt::SFunction { name, params, rest, body, .. } => json!({
+                "type": "fun",
+                "name": &**name,
+                "params": params.iter().map(|param| &**param).collect::<Vec<_>>(),
+                "rest": rest.as_deref(),
+                "body": statements_json(body),
+            }),
+            Stmt::SReturn { value, .. } => json!({"type": "return", "value": optional(value)}),
+            Stmt::SSwitch { subject, cases, default, .. } => json!({
+                "type": "switch",
+                "subject": subject.to_json(),
+                "cases": cases
+                    .iter()
+                    .map(|(value, body)| json!({"value": value.to_json(), "body": statements_json(body)}))
+                    .collect::<Vec<_>>(),
+                "default": default.as_deref().map_or(Value::Null, statements_json),
+            }),
+            Stmt::STryCatch { try_body, catch_var, catch_body, finally_body, .. } => json!({
+                "type": "try",
+                "body": statements_json(try_body),
+                "catch_var": catch_var.as_deref(),
+                "catch": statements_json(catch_body),
+                "finally": statements_json(finally_body),
+            }),
+            Stmt::SThrow { value, .. } => json!({"type": "throw", "value": value.to_json()}),
+            Stmt::SImport { path, line, .. } => json!({"type": "import", "path": path, "line": line}),
+            Stmt::SForIn { var, iterable, body, .. } => json!({
+                "type": "for",
+                "var": &**var,
+                "iterable": iterable.to_json(),
+                "body": body.to_json(),
+            }),
+            Stmt::SWhile { condition, body, .. } => json!({
+                "type": "while",
+                "condition": condition.to_json(),
+                "body": body.to_json(),
+            }),
+            Stmt::SClass { name, superclass, methods, .. } => json!({
+                "type": "class",
+                "name": &**name,
+                "superclass": optional(superclass),
+                "methods": statements_json(methods),
+            }),
+        }
+    }
+}
+
+impl Expr {
+    pub fn to_json(&self) -> Value {
+        let list = |exprs: &[Expr]| exprs.iter().map(Expr::to_json).collect::<Vec<_>>();
+        match self {
+            // literals are always finite, so this can't fail
+            Expr::ENumber { value, .. } => json!({
+                "type": "number",
+                "value": LoxValue::LNumber(*value).to_json().unwrap(),
+            }),
+            Expr::EString { value, .. } => json!({"type": "string", "value": value}),
+            Expr::EBool { value, .. } => json!({"type": "bool", "value": value}),
+            Expr::ENil { .. } => json!({"type": "nil"}),
+            Expr::EBinary { left, op, right, line, .. } => json!({
+                "type": "binary",
+                "op": op.to_string(),
+                "left": left.to_json(),
+                "right": right.to_json(),
+                "line": line,
+            }),
+            Expr::EUnary { op, right, line, .. } => {
+                json!({"type": "unary", "op": op.to_string(), "right": right.to_json(), "line": line})
+            }
+            Expr::EGrouping { expr, .. } => json!({"type": "grouping", "expr": expr.to_json()}),
+            Expr::EVariable { name, .. } => json!({"type": "variable", "name": &**name}),
+            Expr::EAssign { name, value, .. } => json!({"type": "assign", "name": &**name, "value": value.to_json()}),
+            Expr::ECall { callee, args, line, .. } => {
+                json!({"type": "call", "callee": callee.to_json(), "args": list(args), "line": line})
+            }
+            Expr::EList { elements, .. } => json!({"type": "list", "elements": list(elements)}),
+            Expr::EMap { pairs, .. } => json!({
+                "type": "map",
+                "pairs": pairs.iter().map(|(key, value)| json!([key.to_json(), value.to_json()])).collect::<Vec<_>>(),
+            }),
+            Expr::EGet { object, name, line, .. } => {
+                json!({"type": "get", "object": object.to_json(), "name": &**name, "line": line})
+            }
+            Expr::ESet { object, name, value, line, .. } => json!({
+                "type": "set",
+                "object": object.to_json(),
+                "name": &**name,
+                "value": value.to_json(),
+                "line": line,
+            }),
+            Expr::EThis { line, .. } => json!({"type": "this", "line": line}),
+            Expr::ESuper { method, line, .. } => json!({"type": "super", "method": &**method, "line": line}),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_round_trip() {
+        let data = json!({
+            "name": "lox",
+            "version": 1.5,
+            "tags": ["small", "dynamic", null, true],
+            "nested": {"empty": [], "deeper": {"n": -3}},
+        });
+        let mut interp = Interpreter::new();
+        interp.set_global_json("data", &data).unwrap();
+        assert_eq!(interp.get_global_json("data").unwrap(), data);
+
+        interp.exec("var out = get(data, \"nested\"); set(out, \"count\", len(get(data, \"tags\")));").unwrap();
+        assert_eq!(interp.get_global_json("out").unwrap(), json!({"empty": [], "deeper": {"n": -3}, "count": 4}));
+    }
+
+    #[test]
+    fn test_ast() {
+        use crate::parser::parse;
+        use crate::reader::Source;
+        use crate::tokenize::tokenize;
+
+        let ast = parse(&tokenize(Source::from("var x = -1.5; fun f(a, ...b) { return [a, {\"k\": nil}]; } print f(x) + 2;")).unwrap()).unwrap();
+        assert_eq!(
+            ast.to_json(),
+            json!([
+                {"type": "var", "name": "x", "initializer":
+                    {"type": "unary", "op": "-", "right": {"type": "number", "value": 1.5}, "line": 1}},
+                {"type": "fun", "name": "f", "params": ["a"], "rest": "b", "body": [
+                    {"type": "return", "value": {"type": "list", "elements": [
+                        {"type": "variable", "name": "a"},
+                        {"type": "map", "pairs": [[{"type": "string", "value": "k"}, {"type": "nil"}]]},
+                    ]}},
+                ]},
+                {"type": "print", "expr": {"type": "binary", "op": "+", "line": 1,
+                    "left": {"type": "call", "callee": {"type": "variable", "name": "f"},
+                        "args": [{"type": "variable", "name": "x"}], "line": 1},
+                    "right": {"type": "number", "value": 2}}},
+            ])
+        );
+    }
+
+    #[test]
+    fn test_not_json() {
+        let mut interp = Interpreter::new();
+        interp
+            .exec("fun f() {} var funs = [1, f]; var keyed = {1: 2}; var loop = {}; set(loop, \"again\", [loop]);")
+            .unwrap();
+        let message = |name| interp.get_global_json(name).unwrap_err().to_string();
+        assert_eq!(message("funs"), "a function can't be converted to JSON");
+        assert_eq!(message("len"), "a native function can't be converted to JSON");
+        assert_eq!(message("keyed"), "a map with a number key can't be converted to JSON");
+        assert_eq!(message("loop"), "a map that contains itself can't be converted to JSON");
+        assert_eq!(LoxValue::LNumber(f64::NAN).to_json().unwrap_err().to_string(), "NaN can't be converted to JSON");
+        assert!(matches!(interp.get_global_json("missing"), Err(Error::UndefinedVariable(_))));
+    }
+}
@@ -0,0 +1,108 @@
+// The T/O/E/S/L variant prefixes (TPlus, OAdd, ENil, SPrint, LNil) are
+// deliberate: they keep glob-imported variants from colliding.
+#![allow(clippy::enum_variant_names, clippy::upper_case_acronyms)]
+// LoxValue map keys contain RefCells (inside lists and functions), but its
+// Hash never looks at anything mutable
+#![allow(clippy::mutable_key_type)]
+
+pub mod ast;
+pub mod ast_id;
+pub mod check;
+pub mod config;
+pub mod environ;
+pub mod evaluate;
+pub mod intern;
+pub mod lint;
+pub mod natives;
+pub mod parser;
+pub mod profiler;
+pub mod reader;
+pub mod resolver;
+pub mod token;
+pub mod tokenize;
+// a second scanner, checked against tokenize by first_difference. Public
+// only for fuzzing; the interpreter doesn't use it.
+#[doc(hidden)]
+pub mod tokenize2;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "serde")]
+pub mod json;
+
+pub use check::{check_files, FileReport};
+
+// Every way running Lox source can go wrong, for library users who'd rather
+// handle one error type than one per stage. (The lox binary has its own,
+// which also knows about files and the command line.)
+#[derive(Debug)]
+pub enum Error {
+    Tokenize(token::Error),
+    Parse(parser::Error),
+    Evaluate(evaluate::Error),
+}
+
+impl From<token::Error> for Error {
+    fn from(error: token::Error) -> Error {
+        Error::Tokenize(error)
+    }
+}
+
+impl From<parser::Error> for Error {
+    fn from(error: parser::Error) -> Error {
+        Error::Parse(error)
+    }
+}
+
+// the interpreter reports source it was handed that didn't scan or parse
+// as its own errors; they're taken out again here
+impl From<evaluate::Error> for Error {
+    fn from(error: evaluate::Error) -> Error {
+        match error {
+            evaluate::Error::Tokenize(error) => Error::Tokenize(error),
+            evaluate::Error::Parse(error) => Error::Parse(error),
+            error => Error::Evaluate(error),
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            Error::Tokenize(e) => write!(formatter, "{e}"),
+            Error::Parse(e) => write!(formatter, "{e}"),
+            Error::Evaluate(e) => write!(formatter, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the stages, one after another, as a library user might put them
+    fn run(source: &str) -> Result<evaluate::Interpreter, Error> {
+        let tokens = tokenize::tokenize(reader::Source::from(source))?;
+        let ast = parser::parse(&tokens)?;
+        let mut interp = evaluate::Interpreter::new();
+        interp.set_output(Box::new(std::io::sink()));
+        interp.evaluate(ast)?;
+        Ok(interp)
+    }
+
+    #[test]
+    fn test_error() {
+        assert!(run("print 1;").is_ok());
+        assert!(matches!(run("print $;"), Err(Error::Tokenize(_))));
+        assert!(matches!(run("print 1"), Err(Error::Parse(_))));
+        assert!(matches!(run("print 1 / 0;"), Err(Error::Evaluate(evaluate::Error::ZeroDivision { .. }))));
+        // exec's errors sort the same way
+        let mut interp = evaluate::Interpreter::new();
+        let exec = |interp: &mut evaluate::Interpreter, source| Error::from(interp.exec(source).unwrap_err());
+        assert!(matches!(exec(&mut interp, "print $;"), Error::Tokenize(_)));
+        assert!(matches!(exec(&mut interp, "print this;"), Error::Parse(_)));
+        let error: Box<dyn std::error::Error> = Box::new(exec(&mut interp, "nope;"));
+        assert_eq!(error.to_string(), "Undefined variable \"nope\"");
+    }
+}
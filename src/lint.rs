@@ -0,0 +1,331 @@
+// Warnings about programs that run but probably don't do what was meant:
+// local variables that are never used, and declarations that hide a local
+// of the same name from an enclosing scope.
+//
+// Like the resolver, this mirrors the scopes the evaluator creates, but it
+// only reads the AST. Globals are left alone, since another file or the
+// prompt may use them.
+//
+// The same pass finds what `lox --strict` doesn't allow: declaring a name
+// twice in one scope, and using a name in a scope before it's declared
+// there. A use inside a function doesn't count, since the function may not
+// be called until later.
+use std::rc::Rc;
+
+use crate::ast::{Expr, Stmt, AST};
+use crate::parser::Error;
+use crate::token::Tokens;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Warning {
+    pub line: usize,
+    pub msg: String,
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(formatter, "Line {}: Warning: {}", self.line, self.msg)
+    }
+}
+
+// the warnings for ast, in line order. tokens are what it was parsed from,
+// for the lines of its statements.
+pub fn lint(ast: &AST, tokens: &Tokens) -> Vec<Warning> {
+    let mut linter = Linter::run(ast, tokens);
+    linter.warnings.sort_by_key(|warning| warning.line);
+    linter.warnings
+}
+
+// what --strict makes an error, as syntax errors in line order
+pub fn strict_errors(ast: &AST, tokens: &Tokens) -> Vec<Error> {
+    let mut linter = Linter::run(ast, tokens);
+    linter.errors.sort_by_key(|error| match error {
+        Error::SyntaxError { line, .. } => *line,
+        Error::Multiple(_) => 0,
+    });
+    linter.errors
+}
+
+// strict_errors as a single error, if there are any
+pub fn check_strict(ast: &AST, tokens: &Tokens) -> Result<(), Error> {
+    let mut errors = strict_errors(ast, tokens);
+    match errors.len() {
+        0 => Ok(()),
+        1 => Err(errors.remove(0)),
+        _ => Err(Error::Multiple(errors)),
+    }
+}
+
+struct Local {
+    name: Rc<str>,
+    line: usize,
+    // only variables declared with var are expected to be read
+    var: bool,
+    used: bool,
+}
+
+#[derive(Default)]
+struct Scope {
+    locals: Vec<Local>,
+    // names used in this scope, or a block in it, before they were declared
+    // here, with the lines they were used on
+    used_early: Vec<(Rc<str>, usize)>,
+    // the program's scope or a function's, which uses inside it can't be
+    // too early for
+    function: bool,
+}
+
+struct Linter<'a> {
+    tokens: &'a Tokens,
+    // the names declared so far in each scope, the global one first and the
+    // innermost last
+    scopes: Vec<Scope>,
+    warnings: Vec<Warning>,
+    errors: Vec<Error>,
+}
+
+impl Linter<'_> {
+    fn run<'a>(ast: &AST, tokens: &'a Tokens) -> Linter<'a> {
+        let globals = Scope { function: true, ..Scope::default() };
+        let mut linter = Linter { tokens, scopes: vec![globals], warnings: Vec::new(), errors: Vec::new() };
+        linter.statements(&ast.top);
+        linter
+    }
+
+    // the line of the token starting at offset
+    fn line_at(&self, offset: usize) -> usize {
+        let n = self.tokens.tokens.partition_point(|token| token.span.start < offset);
+        self.tokens.tokens.get(n).map_or(0, |token| token.line)
+    }
+
+    fn line(&self, stmt: &Stmt) -> usize {
+        self.line_at(stmt.span().start)
+    }
+
+    fn declare(&mut self, name: &Rc<str>, line: usize, var: bool) {
+        let scope = self.scopes.last().unwrap();
+        if scope.locals.iter().any(|local| local.name == *name) {
+            let msg = format!("'{name}' is already declared in this scope");
+            self.errors.push(Error::SyntaxError { line, msg });
+        }
+        if let Some((_, line)) = scope.used_early.iter().find(|(used, _)| used == name) {
+            let msg = format!("'{name}' is used before it's declared");
+            self.errors.push(Error::SyntaxError { line: *line, msg });
+        }
+        // a global hiding a global, or a local hiding a global, is fine
+        let enclosing = &self.scopes[1.min(self.scopes.len() - 1)..self.scopes.len() - 1];
+        if let Some(outer) = enclosing.iter().rev().flat_map(|scope| &scope.locals).find(|local| local.name == *name) {
+            let msg = format!("'{name}' shadows the variable declared on line {}", outer.line);
+            self.warnings.push(Warning { line, msg });
+        }
+        let local = Local { name: name.clone(), line, var, used: false };
+        self.scopes.last_mut().unwrap().locals.push(local);
+    }
+
+    fn use_name(&mut self, name: &Rc<str>, line: usize) {
+        let mut early = true;
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some(local) = scope.locals.iter_mut().rev().find(|local| local.name == *name) {
+                local.used = true;
+                return;
+            }
+            if early {
+                scope.used_early.push((name.clone(), line));
+                early = !scope.function;
+            }
+        }
+    }
+
+    fn push_scope(&mut self) {
+        self.scopes.push(Scope::default());
+    }
+
+    fn pop_scope(&mut self) {
+        for local in self.scopes.pop().unwrap_or_default().locals {
+            if local.var && !local.used {
+                let msg = format!("Local variable '{}' is never used", local.name);
+                self.warnings.push(Warning { line: local.line, msg });
+            }
+        }
+    }
+
+    // statements in a scope of their own
+    fn scope(&mut self, statements: &[Stmt]) {
+        self.push_scope();
+        self.statements(statements);
+        self.pop_scope();
+    }
+
+    // a function's parameters and body
+    fn function(&mut self, stmt: &Stmt) {
+        let Stmt::SFunction { params, rest, body, .. } = stmt else { return };
+        let line = self.line(stmt);
+        self.scopes.push(Scope { function: true, ..Scope::default() });
+        for param in params.iter().chain(rest) {
+            self.declare(param, line, false);
+        }
+        self.statements(body);
+        self.pop_scope();
+    }
+
+    fn statements(&mut self, statements: &[Stmt]) {
+        for stmt in statements {
+            self.statement(stmt);
+        }
+    }
+
+    fn statement(&mut self, stmt: &Stmt) {
+        stmt.expressions().into_iter().for_each(|expr| self.expression(expr));
+        match stmt {
+            Stmt::SVarDecl { name, .. } => self.declare(name, self.line(stmt), true),
+            Stmt::SBlock { statements, .. } => self.scope(statements),
+            Stmt::SFunction { name, .. } => {
+                self.declare(name, self.line(stmt), false);
+                self.function(stmt);
+            }
+            Stmt::SSwitch { cases, default, .. } => {
+                for (_, body) in cases {
+                    self.scope(body);
+                }
+                if let Some(body) = default {
+                    self.scope(body);
+                }
+            }
+            Stmt::STryCatch { try_body, catch_var, catch_body, finally_body, .. } => {
+                self.scope(try_body);
+                self.push_scope();
+                if let Some(var) = catch_var {
+                    self.declare(var, self.line(stmt), false);
+                }
+                self.statements(catch_body);
+                self.pop_scope();
+                self.scope(finally_body);
+            }
+            Stmt::SForIn { var, body, .. } => {
+                self.push_scope();
+                self.declare(var, self.line(stmt), false);
+                self.statement(body);
+                self.pop_scope();
+            }
+            Stmt::SClass { name, methods, .. } => {
+                self.declare(name, self.line(stmt), false);
+                for method in methods {
+                    self.function(method);
+                }
+            }
+            _ => stmt.statements().into_iter().for_each(|inner| self.statement(inner)),
+        }
+    }
+
+    fn expression(&mut self, expr: &Expr) {
+        match expr {
+            Expr::EVariable { name, span, .. } => self.use_name(name, self.line_at(span.start)),
+            Expr::EBinary { left, right, .. } => {
+                self.expression(left);
+                self.expression(right);
+            }
+            Expr::EUnary { right, .. } => self.expression(right),
+            Expr::EGrouping { expr, .. } => self.expression(expr),
+            Expr::EAssign { value, .. } => self.expression(value),
+            Expr::ECall { callee, args, .. } => {
+                self.expression(callee);
+                args.iter().for_each(|arg| self.expression(arg));
+            }
+            Expr::EList { elements, .. } => elements.iter().for_each(|element| self.expression(element)),
+            Expr::EMap { pairs, .. } => {
+                for (key, value) in pairs {
+                    self.expression(key);
+                    self.expression(value);
+                }
+            }
+            Expr::EGet { object, .. } => self.expression(object),
+            Expr::ESet { object, value, .. } => {
+                self.expression(object);
+                self.expression(value);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use crate::reader::Source;
+    use crate::tokenize::tokenize;
+
+    fn warnings(program: &str) -> Vec<String> {
+        let tokens = tokenize(Source::from(program)).unwrap();
+        lint(&parse(&tokens).unwrap(), &tokens).iter().map(Warning::to_string).collect()
+    }
+
+    #[test]
+    fn test_unused() {
+        let program = "
+            var global = 1;
+            fun f(unused_param) {
+                var used = 1;
+                var assigned;
+                assigned = 2;
+                var read_by_closure = 3;
+                fun g() { return read_by_closure; }
+                return used + g();
+            }
+            { var never; }";
+        assert_eq!(
+            warnings(program),
+            ["Line 5: Warning: Local variable 'assigned' is never used", "Line 11: Warning: Local variable 'never' is never used"]
+        );
+    }
+
+    #[test]
+    fn test_shadowing() {
+        let program = "
+            var x = 1;
+            {
+                var x = 2;
+                fun f(x) { print x; }
+                for (i in [x]) { var i = 1; print i; }
+                f(x);
+            }";
+        assert_eq!(
+            warnings(program),
+            ["Line 5: Warning: 'x' shadows the variable declared on line 4", "Line 6: Warning: 'i' shadows the variable declared on line 6"]
+        );
+        assert_eq!(warnings("var a = 1; var a = 2; { var b = 1; print b; } { var b = 2; print b; }"), [] as [&str; 0]);
+    }
+
+    #[test]
+    fn test_strict_errors() {
+        let errors = |program: &str| -> Vec<String> {
+            let tokens = tokenize(Source::from(program)).unwrap();
+            strict_errors(&parse(&tokens).unwrap(), &tokens).iter().map(Error::to_string).collect()
+        };
+        let program = "
+            var a = 1;
+            var a = 2;
+            print b;
+            var b = 3;
+            fun f(x, x) {
+                { print y; }
+                var y = x;
+                return y;
+            }
+            { var c = c; print c; }";
+        assert_eq!(
+            errors(program),
+            [
+                "Line 3: Syntax error: 'a' is already declared in this scope",
+                "Line 4: Syntax error: 'b' is used before it's declared",
+                "Line 6: Syntax error: 'x' is already declared in this scope",
+                "Line 7: Syntax error: 'y' is used before it's declared",
+                "Line 11: Syntax error: 'c' is used before it's declared",
+            ]
+        );
+        // a function can use what's declared after it, as it's called later
+        let program = "fun f() { return g() + later; } fun g() { return 1; } var later = 2; print f();";
+        assert_eq!(errors(program), [] as [&str; 0]);
+        assert_eq!(errors("var a = 1; { var a = a; print a; } print len(\"a\");"), ["Line 1: Syntax error: 'a' is used before it's declared"]);
+    }
+}
@@ -1,20 +1,30 @@
+use std::io::IsTerminal;
 use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
-mod evaluate;
-mod parser;
-mod reader;
-mod tokenize;
-mod ast;
-mod environ;
-mod tokenize2;
+use lox::config::{self, Config, HELP, USAGE};
+use lox::profiler::Coverage;
+use lox::{ast, evaluate, lint, parser, reader, resolver, token, tokenize};
+
+mod diagnostics;
+mod repl;
+
+use diagnostics::{report_errors, report_warnings, Style};
 
 // top-level error
 #[derive(Debug)]
 pub enum Error {
     Read(reader::Error),
-    Tokenize(tokenize::Error),
+    Tokenize(token::Error),
     Parse(parser::Error),
     Evaluate(evaluate::Error),
+    // warnings, with --warn-error
+    Warnings(Vec<lint::Warning>),
+    // an error in a named source, like a script file
+    InFile { name: String, error: Box<Error> },
+    // an error with the source it's in, for showing where
+    InSource { error: Box<Error>, source: reader::Source },
 }
 
 impl From<reader::Error> for Error {
@@ -23,8 +33,8 @@ impl From<reader::Error> for Error {
     }
 }
 
-impl From<tokenize::Error> for Error {
-    fn from(error: tokenize::Error) -> Error {
+impl From<token::Error> for Error {
+    fn from(error: token::Error) -> Error {
         Error::Tokenize(error)
     }
 }
@@ -41,104 +51,342 @@ impl From<evaluate::Error> for Error {
     }
 }
 
-fn report_errors(err: Error) {
-    match err {
-        Error::Read(e) => {
-            eprintln!("{}", e.msg);
+impl Error {
+    // exit status for a failed run, following the BSD sysexits convention
+    fn exit_code(&self) -> u8 {
+        match self {
+            Error::Read(_) => EX_NOINPUT,
+            Error::Tokenize(_) | Error::Parse(_) | Error::Warnings(_) => EX_DATAERR,
+            // found by the resolver before anything ran
+            Error::Evaluate(evaluate::Error::Parse(_)) => EX_DATAERR,
+            Error::Evaluate(_) => EX_SOFTWARE,
+            Error::InFile { error, .. } | Error::InSource { error, .. } => error.exit_code(),
+        }
+    }
+}
+
+const EX_USAGE: u8 = 64;
+const EX_DATAERR: u8 = 65;
+const EX_NOINPUT: u8 = 66;
+const EX_SOFTWARE: u8 = 70;
+
+// --record appends what worked at the prompt to a file; --replay types a
+// file in at the prompt instead of reading stdin
+fn run_prompt(mut interpreter: evaluate::Interpreter, style: Style, record: Option<&str>, replay: Option<&str>) -> Result<(), Error> {
+    let mut record = match record {
+        Some(filename) => {
+            let file = std::fs::OpenOptions::new().create(true).append(true).open(filename);
+            Some(file.map_err(|e| Error::Read(reader::Error::reading(filename, e)))?)
+        }
+        None => None,
+    };
+    let record = record.as_mut().map(|file| file as &mut dyn Write);
+    if let Some(filename) = replay {
+        let file = std::fs::File::open(filename).map_err(|e| Error::Read(reader::Error::reading(filename, e)))?;
+        let mut lines = repl::Plain::echoing(std::io::BufReader::new(file), std::io::stdout());
+        repl::run(&mut lines, &mut interpreter, style, record);
+        return Ok(());
+    }
+    #[cfg(feature = "editor")]
+    if std::io::stdin().is_terminal()
+        && let Some(mut editor) = repl::Editor::new()
+    {
+        repl::run(&mut editor, &mut interpreter, style, record);
+        return Ok(());
+    }
+    let history = if std::io::stdin().is_terminal() { repl::History::from_home() } else { None };
+    let mut lines = repl::Plain::new(std::io::stdin().lock(), std::io::stdout()).with_history(history);
+    repl::run(&mut lines, &mut interpreter, style, record);
+    Ok(())
+}
+
+// --profile and --stats: what ran, written to stderr once the program is
+// done (even if it failed)
+fn report_counts(interpreter: &evaluate::Interpreter) {
+    if let Some(profiler) = interpreter.profiler() {
+        let _ = profiler.write_summary(&mut std::io::stderr().lock());
+    }
+    if let Some(stats) = interpreter.stats() {
+        eprintln!("{stats}");
+    }
+}
+
+// errors are tagged with the name of the source, if it has one
+fn run_interp(interp: &mut evaluate::Interpreter, source: reader::Source, config: &Config) -> Result<(), Error> {
+    let name = source.name.clone();
+    in_file(name, run_source(interp, source, config))
+}
+
+fn in_file<T>(name: Option<String>, result: Result<T, Error>) -> Result<T, Error> {
+    match (result, name) {
+        (Err(error), Some(name)) => Err(Error::InFile { name, error: Box::new(error) }),
+        (result, _) => result,
+    }
+}
+
+// --tokens and --ast show what the front end made of the source, on stderr.
+// Warnings are printed there too before it runs, unless there are to be
+// none or they're to stop it running. --strict finds more to stop it.
+fn run_source(interp: &mut evaluate::Interpreter, source: reader::Source, config: &Config) -> Result<(), Error> {
+    let name = source.name.clone();
+    // kept for pointing at where a runtime error happened, and for --coverage
+    let text = reader::Source::from(source.contents.clone());
+    // comments are kept for the doc comments they may hold
+    let tokens = tokenize::tokenize_with_comments(source)?;
+    if config.debug_tokens {
+        for token in &tokens.tokens {
+            eprintln!("{}\t{:?}\t{}", token.line, token.toktype, token.lexeme);
         }
-        Error::Tokenize(e) => {
-            use crate::tokenize::ScanError;
-            // for scan_error in e.iter() {
-            //     match scan_error {
-            //         ScanError::UnexpectedCharacter { line, ch } => {
-            //             eprintln!("Line {line}: Unexpected character {ch:?}");
-            //         }
-            //         ScanError::UnterminatedString { line } => {
-            //             eprintln!("Line {line}: Unterminated string");
-            //         }
-            //     }
-            // }
+    }
+    let ast = parser::parse(&tokens)?;
+    if config.debug_ast {
+        for stmt in &ast.top {
+            eprintln!("{stmt:?}");
+        }
+    }
+    if config.options.strict_scopes {
+        lint::check_strict(&ast, &tokens)?;
+    }
+    if !config.no_warn {
+        let warnings = lint::lint(&ast, &tokens);
+        if config.warn_error && !warnings.is_empty() {
+            return Err(Error::Warnings(warnings));
+        }
+        report_warnings(name.as_deref(), &warnings, Style::detect(config.no_color));
+    }
+    if config.coverage {
+        interp.set_coverage(Coverage::new(&ast, &text));
+    }
+    let result = interp.evaluate(ast);
+    if let Some(coverage) = interp.coverage() {
+        report_coverage(&text, coverage);
+    }
+    match result {
+        Err(error) if error.span().is_some() => Err(Error::InSource { error: Box::new(error.into()), source: text }),
+        result => Ok(result.map(|_| ())?),
+    }
+}
+
+// --coverage: the program's lines on stderr, each marked if it ran, then
+// how many of those that could run did
+fn report_coverage(source: &reader::Source, coverage: &Coverage) {
+    let lines = source.contents.lines().count();
+    let width = lines.to_string().len();
+    for line in 1..=lines {
+        let mark = if coverage.has_run(line) { '✓' } else { ' ' };
+        eprintln!("{mark} {line:>width$} | {}", source.line_text(line));
+    }
+    let (ran, total) = (coverage.executed_lines().len(), coverage.executable_lines().len());
+    eprintln!("{ran} of {total} lines run ({:.1}%)", coverage.percentage());
+}
+
+// the filename "-" means standard input. A file that can't be read is
+// named by the error itself.
+fn read_file(filename: &str) -> Result<reader::Source, Error> {
+    match filename {
+        "-" => in_file(Some(filename.to_string()), reader::read_stdin().map_err(Error::from)),
+        _ => Ok(reader::read_source(filename)?),
+    }
+}
+
+// an interpreter set up as the command line asked
+fn new_interpreter(config: &Config) -> evaluate::Interpreter {
+    let mut builder = evaluate::LoxBuilder::new().options(config.options).max_call_depth(config.max_call_depth());
+    for dir in &config.search_path {
+        builder = builder.search_path(dir);
+    }
+    builder.build()
+}
+
+// run the files in order, all in the same interpreter, stopping at the first
+// error
+fn run_files(filenames: &[String], config: &Config) -> Result<(), Error> {
+    let mut interpreter = new_interpreter(config);
+    for filename in filenames {
+        let result = read_file(filename).and_then(|source| {
+            // imports are relative to the importing file
+            interpreter.set_directory(source.directory.clone().unwrap_or_default());
+            run_interp(&mut interpreter, source, config)
+        });
+        if result.is_err() {
+            report_counts(&interpreter);
+            return result;
         }
-        Error::Parse(e) => {
-            use crate::parser::Error;
-            match e {
-                Error::SyntaxError { line, msg } => {
-                    eprintln!("Line {line}: Syntax error: {msg}");
-                }
-            }
+    }
+    report_counts(&interpreter);
+    Ok(())
+}
+
+// -c: tokenize and parse without running anything, reporting every syntax
+// error in every file. The exit status is 1 if there were any.
+fn check(eval: Option<String>, filenames: &[String], style: Style) -> u8 {
+    let sources = match eval {
+        Some(snippet) => vec![Ok(reader::Source::from(snippet))],
+        None => filenames.iter().map(|filename| read_file(filename)).collect(),
+    };
+    let mut failed = false;
+    for source in sources {
+        let result = source.and_then(|source| {
+            let name = source.name.clone();
+            in_file(name, check_source(source))
+        });
+        if let Err(e) = result {
+            report_errors(e, style);
+            failed = true;
         }
-        Error::Evaluate(e) => {
-            use crate::evaluate::Error::*;
-            match e {
-                ZeroDivision => {
-                    eprintln!("Division by zero");
-                }
-                UnsupportedBinOp(left, op, right) => {
-                    eprintln!("Unsupported operation: {left:?} {op} {right:?}");
-                }
-                UnsupportedUnaryOp(op, value) => {
-                    eprintln!("Unsupported operation: {op}{value:?}");
-                }
-            }
+    }
+    u8::from(failed)
+}
+
+fn check_source(source: reader::Source) -> Result<(), Error> {
+    let tokens = tokenize::tokenize(source)?;
+    resolver::resolve(&mut parser::parse(&tokens)?)?;
+    Ok(())
+}
+
+// --check: like -c, but directories stand for every .lox file under them,
+// and the files are checked in parallel. Reports come out in the order the
+// files were found, with one line per error.
+fn check_paths(paths: &[String], style: Style) -> u8 {
+    let mut files = Vec::new();
+    for path in paths {
+        lox_files(Path::new(path), &mut files);
+    }
+    let mut failed = false;
+    for report in lox::check_files(&files) {
+        for error in &report.errors {
+            eprintln!("{}: {}", style.bold(&report.path.to_string_lossy()), style.error(error));
         }
+        failed |= !report.is_ok();
     }
+    u8::from(failed)
 }
 
-fn run_prompt() {
-    let mut stdout = std::io::stdout();
-    let stdin = std::io::stdin();
-    let mut interpreter = evaluate::Interpreter::new();
-    loop {
-        stdout.write_all(b"> ").unwrap();
-        stdout.flush().unwrap();
-        let mut buffer = String::new();
-        stdin.read_line(&mut buffer).unwrap();
-        let source = reader::Source {contents: buffer};
-        match run_interp(&mut interpreter, source) {
-            Ok(_) => {},
-            Err(e) => {
-                report_errors(e);
-            }
+// path if it's a file, or the .lox files in and below it (sorted by name) if
+// it's a directory. A path that doesn't exist is kept, so checking reports it.
+fn lox_files(path: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(path) else {
+        files.push(path.to_path_buf());
+        return;
+    };
+    let mut entries: Vec<PathBuf> = entries.filter_map(|entry| Some(entry.ok()?.path())).collect();
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir() {
+            lox_files(&entry, files);
+        } else if entry.extension().is_some_and(|extension| extension == "lox") {
+            files.push(entry);
         }
     }
 }
 
-fn run(source: reader::Source) -> Result<(), Error> {
-    let mut interpreter = evaluate::Interpreter::new();
-    run_interp(&mut interpreter, source)
+// a program given with -e. If it's just an expression without the final
+// semicolon, its value is printed, as at the prompt.
+fn run_eval(snippet: &str, config: &Config) -> Result<(), Error> {
+    let mut interpreter = new_interpreter(config);
+    let result = run_snippet(&mut interpreter, snippet, config);
+    report_counts(&interpreter);
+    result
 }
 
-fn run_interp(interp: &mut evaluate::Interpreter, source: reader::Source) -> Result<(), Error> {
-    let tokens = tokenize2::tokenize(source);
-    // println!("tokens: {:?}", tokens);
-    // let ast = parser::parse(tokens)?;
-    // println!("ast: {:?}", ast);
-    // interp.evaluate(ast)?;
+fn run_snippet(interp: &mut evaluate::Interpreter, snippet: &str, config: &Config) -> Result<(), Error> {
+    let program = run_interp(interp, reader::Source::from(snippet), config);
+    // nothing has run if the program didn't parse, so interp is still fresh
+    if let Err(Error::Parse(_)) = program {
+        let tokens = tokenize::tokenize(reader::Source::from(format!("{snippet};")))?;
+        if let Ok(ast) = parser::parse(&tokens)
+            && let [ast::Stmt::SExpression { .. }] = &ast.top[..]
+        {
+            println!("{}", interp.evaluate(ast)?.repr());
+            return Ok(());
+        }
+    }
+    program
+}
+
+// --highlight: every span of the file, one per line as
+// "start<TAB>end<TAB>class", with byte offsets
+fn highlight(filename: &str) -> Result<(), Error> {
+    let source = read_file(filename)?;
+    let mut out = std::io::stdout().lock();
+    for (range, class) in tokenize::classify(&source.contents) {
+        // stop quietly if whoever is reading goes away
+        if writeln!(out, "{}\t{}\t{class}", range.start, range.end).is_err() {
+            break;
+        }
+    }
     Ok(())
 }
 
-fn run_file(filename: &str) -> Result<(), Error> {
-    let source = reader::read_source(filename)?;
-    run(source)
+// deeply nested Lox calls take a lot of Rust stack (several times as much in
+// debug builds, and more the deeper the expressions and blocks in each
+// function), so the interpreter gets a thread with this much room for each
+// call it allows. Past the stack, the process aborts rather than reporting a
+// Lox stack overflow.
+//
+// That's 12.5MB for the default 200 calls in a release build, and 640MB at
+// the most --max-call-depth allows. It's address space set aside rather than
+// memory used: only the pages that calls get down to are ever touched.
+const STACK_PER_CALL: usize = if cfg!(debug_assertions) { 256 * 1024 } else { 64 * 1024 };
+
+fn main() -> ExitCode {
+    let config = match Config::parse(std::env::args().skip(1)) {
+        Ok(config) => config,
+        Err(msg) => {
+            let style = Style::detect(std::env::args().any(|arg| arg == "--no-color"));
+            eprintln!("{}", style.error(&msg));
+            eprintln!("{USAGE}");
+            return ExitCode::from(EX_USAGE);
+        }
+    };
+    let depth = config.max_call_depth();
+    let interpreter = std::thread::Builder::new()
+        .stack_size(depth * STACK_PER_CALL)
+        .spawn(|| lox_main(config));
+    match interpreter {
+        Ok(interpreter) => ExitCode::from(interpreter.join().unwrap()),
+        Err(error) => {
+            eprintln!("Can't make a stack for {depth} calls: {error}");
+            ExitCode::from(EX_SOFTWARE)
+        }
+    }
 }
 
-fn main() {
-    println!("Hello, Lox!");
-    ast::main();
-    let args: Vec<String> = std::env::args().collect();
+fn lox_main(config: Config) -> u8 {
+    if config.help {
+        println!("{USAGE}\n{HELP}");
+        return 0;
+    }
+    if config.version {
+        println!("{}", config::version());
+        return 0;
+    }
+    let style = Style::detect(config.no_color);
+    if config.check_only {
+        return check(config.eval, &config.filenames, style);
+    }
+    if config.check {
+        return check_paths(&config.filenames, style);
+    }
 
-    if args.len() == 1 {
-        run_prompt();
-    } else if args.len() == 2 {
-        match run_file(&args[1]) {
-            Ok(_) => {
-                println!("It worked")
-            }
-            Err(e) => {
-                report_errors(e);
-            }
+    // a program piped in with nothing else to run is run whole, as with
+    // "lox -", rather than read a line at a time by the prompt
+    let piped = config.record.is_none() && config.replay.is_none() && !std::io::stdin().is_terminal();
+    let result = match &config.eval {
+        None if config.highlight => highlight(&config.filenames[0]),
+        Some(snippet) => run_eval(snippet, &config),
+        None if config.filenames.is_empty() && piped => run_files(&["-".to_string()], &config),
+        None if config.filenames.is_empty() => {
+            run_prompt(new_interpreter(&config), style, config.record.as_deref(), config.replay.as_deref())
+        }
+        None => run_files(&config.filenames, &config),
+    };
+    match result {
+        Ok(_) => 0,
+        Err(e) => {
+            let code = e.exit_code();
+            report_errors(e, style);
+            code
         }
-    } else {
-        eprintln!("Usage: lox [filename]");
     }
 }
@@ -0,0 +1,614 @@
+// Functions built into the interpreter, implemented in Rust.
+//
+// Every native takes its (already arity-checked) arguments as a slice and
+// returns a LoxValue or a runtime error. The few that run Lox code, like
+// eval, are also given the interpreter calling them.
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::evaluate::NativeFn::{Plain, WithInterpreter};
+use crate::evaluate::{self, Error, LoxValue, NativeFunction};
+
+// all natives, to be declared in the global scope
+pub fn natives() -> Vec<NativeFunction> {
+    vec![
+        NativeFunction { name: "abs", arity: 1..=1, function: Plain(abs) },
+        NativeFunction { name: "assert", arity: 2..=2, function: Plain(assert) },
+        NativeFunction { name: "assert_eq", arity: 3..=3, function: Plain(assert_eq) },
+        NativeFunction { name: "ceil", arity: 1..=1, function: Plain(ceil) },
+        NativeFunction { name: "contains", arity: 2..=2, function: Plain(contains) },
+        NativeFunction { name: "doc", arity: 1..=1, function: Plain(doc) },
+        NativeFunction { name: "ends_with", arity: 2..=2, function: Plain(ends_with) },
+        NativeFunction { name: "eval", arity: 1..=1, function: WithInterpreter(eval) },
+        NativeFunction { name: "floor", arity: 1..=1, function: Plain(floor) },
+        NativeFunction { name: "floor_div", arity: 2..=2, function: Plain(floor_div) },
+        NativeFunction { name: "format", arity: 1..=usize::MAX, function: Plain(format) },
+        NativeFunction { name: "get", arity: 2..=2, function: Plain(get) },
+        NativeFunction { name: "hash", arity: 1..=1, function: Plain(hash) },
+        NativeFunction { name: "len", arity: 1..=1, function: Plain(len) },
+        NativeFunction { name: "pow", arity: 2..=2, function: Plain(pow) },
+        NativeFunction { name: "range", arity: 2..=3, function: Plain(range) },
+        NativeFunction { name: "repr", arity: 1..=1, function: Plain(repr) },
+        NativeFunction { name: "round", arity: 1..=1, function: Plain(round) },
+        NativeFunction { name: "set", arity: 3..=3, function: Plain(set) },
+        NativeFunction { name: "split", arity: 2..=2, function: Plain(split) },
+        NativeFunction { name: "sqrt", arity: 1..=1, function: Plain(sqrt) },
+        NativeFunction { name: "starts_with", arity: 2..=2, function: Plain(starts_with) },
+        NativeFunction { name: "substr", arity: 2..=3, function: Plain(substr) },
+        NativeFunction { name: "to_fixed", arity: 2..=2, function: Plain(to_fixed) },
+        NativeFunction { name: "to_lower", arity: 1..=1, function: Plain(to_lower) },
+        NativeFunction { name: "to_upper", arity: 1..=1, function: Plain(to_upper) },
+        NativeFunction { name: "trim", arity: 1..=1, function: Plain(trim) },
+        NativeFunction { name: "trim_end", arity: 1..=1, function: Plain(trim_end) },
+        NativeFunction { name: "trim_start", arity: 1..=1, function: Plain(trim_start) },
+    ]
+}
+
+fn list(elements: Vec<LoxValue>) -> LoxValue {
+    LoxValue::LList(Rc::new(RefCell::new(elements)))
+}
+
+// argument helpers: unwrap a value of the expected type or raise a TypeError
+fn number(name: &str, value: &LoxValue) -> Result<f64, Error> {
+    match value {
+        LoxValue::LNumber(n) => Ok(*n),
+        other => Err(Error::TypeError(format!(
+            "{name}() expects a number, not {}",
+            other.type_name()
+        ))),
+    }
+}
+
+// a number that must be whole, like an index or a length
+fn whole(name: &str, value: &LoxValue) -> Result<f64, Error> {
+    let n = number(name, value)?;
+    if n.fract() != 0.0 {
+        return Err(Error::ValueError(format!(
+            "{name}() expects a whole number, not {}",
+            evaluate::lox_fmt_number(n)
+        )));
+    }
+    Ok(n)
+}
+
+fn map<'a>(name: &str, value: &'a LoxValue) -> Result<&'a RefCell<HashMap<LoxValue, LoxValue>>, Error> {
+    match value {
+        LoxValue::LMap(entries) => Ok(entries),
+        other => Err(Error::TypeError(format!(
+            "{name}() expects a map, not {}",
+            other.type_name()
+        ))),
+    }
+}
+
+fn key<'a>(name: &str, value: &'a LoxValue) -> Result<&'a LoxValue, Error> {
+    if !value.is_hashable() {
+        return Err(Error::TypeError(format!("{name}() can't use a {} as a map key", value.type_name())));
+    }
+    Ok(value)
+}
+
+fn string<'a>(name: &str, value: &'a LoxValue) -> Result<&'a str, Error> {
+    match value {
+        LoxValue::LString(s) => Ok(s),
+        other => Err(Error::TypeError(format!(
+            "{name}() expects a string, not {}",
+            other.type_name()
+        ))),
+    }
+}
+
+// assert(condition, message): throw message (as a string) unless condition
+// is truthy. Like anything thrown, it can be caught with try/catch.
+fn assert(args: &[LoxValue]) -> Result<LoxValue, Error> {
+    if !args[0].is_truthy() {
+        return Err(Error::LoxThrow(LoxValue::LString(args[1].to_string().into())));
+    }
+    Ok(LoxValue::LNil)
+}
+
+// assert_eq(actual, expected, message): throw message along with both values
+// unless actual == expected
+fn assert_eq(args: &[LoxValue]) -> Result<LoxValue, Error> {
+    if args[0] != args[1] {
+        let message = format!("{}: {} != {}", args[2], args[0].repr(), args[1].repr());
+        return Err(Error::LoxThrow(LoxValue::LString(message.into())));
+    }
+    Ok(LoxValue::LNil)
+}
+
+// contains(string, part), starts_with(string, prefix), ends_with(string, suffix)
+fn contains(args: &[LoxValue]) -> Result<LoxValue, Error> {
+    let s = string("contains", &args[0])?;
+    Ok(LoxValue::LBoolean(s.contains(string("contains", &args[1])?)))
+}
+
+fn starts_with(args: &[LoxValue]) -> Result<LoxValue, Error> {
+    let s = string("starts_with", &args[0])?;
+    Ok(LoxValue::LBoolean(s.starts_with(string("starts_with", &args[1])?)))
+}
+
+fn ends_with(args: &[LoxValue]) -> Result<LoxValue, Error> {
+    let s = string("ends_with", &args[0])?;
+    Ok(LoxValue::LBoolean(s.ends_with(string("ends_with", &args[1])?)))
+}
+
+// eval(source): run source as a program in the global scope, giving back the
+// value of its last statement if that's an expression, or else nil. Being a
+// program, it needs its semicolons: eval("1 + 2;") is 3. The
+// code can do anything the program calling eval can, which is everything,
+// so hosts running code they don't trust should build their interpreter
+// with LoxBuilder::disable_eval.
+fn eval(interpreter: &mut evaluate::Interpreter, args: &[LoxValue]) -> Result<LoxValue, Error> {
+    interpreter.eval_source(string("eval", &args[0])?)
+}
+
+// floor(n), ceil(n), round(n): n rounded down, up, or to the nearest whole
+// number (halves round away from zero)
+fn floor(args: &[LoxValue]) -> Result<LoxValue, Error> {
+    Ok(LoxValue::LNumber(number("floor", &args[0])?.floor()))
+}
+
+fn ceil(args: &[LoxValue]) -> Result<LoxValue, Error> {
+    Ok(LoxValue::LNumber(number("ceil", &args[0])?.ceil()))
+}
+
+fn round(args: &[LoxValue]) -> Result<LoxValue, Error> {
+    Ok(LoxValue::LNumber(number("round", &args[0])?.round()))
+}
+
+// to_fixed(n, digits): n as a string with exactly digits places after the
+// point, like print's NumberFormat but keeping zeros: to_fixed(2.5, 2) is
+// "2.50"
+fn to_fixed(args: &[LoxValue]) -> Result<LoxValue, Error> {
+    let n = number("to_fixed", &args[0])?;
+    let digits = whole("to_fixed", &args[1])?;
+    if !(0.0..=100.0).contains(&digits) {
+        return Err(Error::ValueError(format!(
+            "to_fixed() digits must be from 0 to 100, not {}",
+            evaluate::lox_fmt_number(digits)
+        )));
+    }
+    let format = evaluate::NumberFormat { max_precision: Some(digits as usize), trim_trailing_zeros: false };
+    Ok(LoxValue::LString(format.format(n).into()))
+}
+
+// sqrt(n): like the other arithmetic, this follows IEEE 754, so the square
+// root of a negative number is NaN rather than an error
+fn sqrt(args: &[LoxValue]) -> Result<LoxValue, Error> {
+    Ok(LoxValue::LNumber(number("sqrt", &args[0])?.sqrt()))
+}
+
+fn abs(args: &[LoxValue]) -> Result<LoxValue, Error> {
+    Ok(LoxValue::LNumber(number("abs", &args[0])?.abs()))
+}
+
+// pow(base, exponent)
+fn pow(args: &[LoxValue]) -> Result<LoxValue, Error> {
+    let base = number("pow", &args[0])?;
+    Ok(LoxValue::LNumber(base.powf(number("pow", &args[1])?)))
+}
+
+// floor_div(x, y): x / y rounded towards negative infinity, the
+// counterpart of the % operator
+fn floor_div(args: &[LoxValue]) -> Result<LoxValue, Error> {
+    let x = number("floor_div", &args[0])?;
+    let y = number("floor_div", &args[1])?;
+    if y == 0.0 {
+        // the interpreter fills in the line of the call
+        return Err(Error::ZeroDivision { line: 0 });
+    }
+    Ok(LoxValue::LNumber(evaluate::floor_div(x, y)))
+}
+
+// format(template, args...): the template with each {} replaced by the next
+// argument, as print would show it. {{ and }} stand for literal braces.
+fn format(args: &[LoxValue]) -> Result<LoxValue, Error> {
+    let template = string("format", &args[0])?;
+    let mut values = args[1..].iter();
+    let mut placeholders = 0;
+    let mut result = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('{', Some('{')) | ('}', Some('}')) => {
+                chars.next();
+                result.push(c);
+            }
+            ('{', Some('}')) => {
+                chars.next();
+                placeholders += 1;
+                if let Some(value) = values.next() {
+                    result.push_str(&value.to_string());
+                }
+            }
+            _ => result.push(c),
+        }
+    }
+    if placeholders != args.len() - 1 {
+        return Err(Error::ValueError(format!(
+            "format() template has {placeholders} placeholders but got {} values",
+            args.len() - 1
+        )));
+    }
+    Ok(LoxValue::LString(result.into()))
+}
+
+// get(map, key): the value for key, or nil if there isn't one
+fn get(args: &[LoxValue]) -> Result<LoxValue, Error> {
+    let entries = map("get", &args[0])?;
+    let key = key("get", &args[1])?;
+    Ok(entries.borrow().get(key).cloned().unwrap_or(LoxValue::LNil))
+}
+
+// set(map, key, value): add or replace the value for key
+fn set(args: &[LoxValue]) -> Result<LoxValue, Error> {
+    let entries = map("set", &args[0])?;
+    let key = key("set", &args[1])?;
+    entries.borrow_mut().insert(key.clone(), args[2].clone());
+    Ok(LoxValue::LNil)
+}
+
+// hash(value): a number that's the same for equal values (so 0 and -0 hash
+// alike), as used for map keys. Lists and maps can't be keys, so they have no
+// hash. Hashes can change between versions of the interpreter, so don't
+// save them anywhere.
+fn hash(args: &[LoxValue]) -> Result<LoxValue, Error> {
+    use std::hash::{DefaultHasher, Hash, Hasher};
+    if !args[0].is_hashable() {
+        return Err(Error::TypeError(format!("hash() can't hash a {}", args[0].type_name())));
+    }
+    let mut hasher = DefaultHasher::new();
+    args[0].hash(&mut hasher);
+    Ok(LoxValue::LNumber(hasher.finish() as f64))
+}
+
+// len(list or string)
+fn len(args: &[LoxValue]) -> Result<LoxValue, Error> {
+    let n = match &args[0] {
+        LoxValue::LList(elements) => elements.borrow().len(),
+        LoxValue::LString(s) => s.chars().count(),
+        other => {
+            return Err(Error::TypeError(format!(
+                "len() expects a list or a string, not {}",
+                other.type_name()
+            )))
+        }
+    };
+    Ok(LoxValue::LNumber(n as f64))
+}
+
+// the most numbers range() makes, so a step too small to change start
+// (range(1e16, 1e17)) is an error rather than a hang
+const RANGE_LIMIT: f64 = 10_000_000.0;
+
+// range(start, end[, step]): the numbers from start (inclusive) to end
+// (exclusive), counting by step
+fn range(args: &[LoxValue]) -> Result<LoxValue, Error> {
+    let start = number("range", &args[0])?;
+    let end = number("range", &args[1])?;
+    let step = match args.get(2) {
+        Some(step) => number("range", step)?,
+        None => 1.0,
+    };
+    if !(start.is_finite() && end.is_finite() && step.is_finite()) {
+        return Err(Error::ValueError("range() arguments must be finite".to_string()));
+    }
+    if step == 0.0 {
+        return Err(Error::ValueError("range() step must not be zero".to_string()));
+    }
+    let count = ((end - start) / step).ceil().max(0.0);
+    if count > RANGE_LIMIT {
+        return Err(Error::ValueError(format!("range() can't make more than {RANGE_LIMIT} numbers")));
+    }
+    // start + i * step rather than adding step each time, so rounding
+    // doesn't build up
+    let elements = (0..count as usize).map(|i| LoxValue::LNumber(start + i as f64 * step)).collect();
+    Ok(list(elements))
+}
+
+// repr(value): value as it would be written in source, so strings are quoted
+// and escaped, unlike in print or format
+fn repr(args: &[LoxValue]) -> Result<LoxValue, Error> {
+    Ok(LoxValue::LString(args[0].repr().into()))
+}
+
+// doc(value): the doc comment of a function or class, or nil
+fn doc(args: &[LoxValue]) -> Result<LoxValue, Error> {
+    Ok(args[0].doc().map_or(LoxValue::LNil, |doc| LoxValue::LString(doc.clone())))
+}
+
+// split(string, delimiter): the pieces between delimiters. An empty
+// delimiter splits the string into characters.
+fn split(args: &[LoxValue]) -> Result<LoxValue, Error> {
+    let s = string("split", &args[0])?;
+    let delimiter = string("split", &args[1])?;
+    let parts: Vec<LoxValue> = if delimiter.is_empty() {
+        s.chars().map(|c| LoxValue::LString(c.to_string().into())).collect()
+    } else {
+        s.split(delimiter).map(|part| LoxValue::LString(part.into())).collect()
+    };
+    Ok(list(parts))
+}
+
+// substr(string, start[, length]): up to length characters starting at
+// character index start. Without a length, runs to the end of the string.
+fn substr(args: &[LoxValue]) -> Result<LoxValue, Error> {
+    let s = string("substr", &args[0])?;
+    let start = whole("substr", &args[1])?;
+    let chars = s.chars().count();
+    if start < 0.0 || start > chars as f64 {
+        return Err(Error::IndexOutOfBounds(format!(
+            "substr() start {} is out of bounds for a string of length {chars}",
+            evaluate::lox_fmt_number(start)
+        )));
+    }
+    let length = match args.get(2) {
+        Some(length) => whole("substr", length)?,
+        None => chars as f64,
+    };
+    if length < 0.0 {
+        return Err(Error::ValueError("substr() length must not be negative".to_string()));
+    }
+    let part: String = s.chars().skip(start as usize).take(length as usize).collect();
+    Ok(LoxValue::LString(part.into()))
+}
+
+// to_upper(string), to_lower(string): the string with its case changed.
+// These follow Unicode rather than ASCII rules, so the result can be longer
+// than the input: to_upper("ß") is "SS".
+fn to_upper(args: &[LoxValue]) -> Result<LoxValue, Error> {
+    Ok(LoxValue::LString(string("to_upper", &args[0])?.to_uppercase().into()))
+}
+
+fn to_lower(args: &[LoxValue]) -> Result<LoxValue, Error> {
+    Ok(LoxValue::LString(string("to_lower", &args[0])?.to_lowercase().into()))
+}
+
+// trim(string), trim_start(string), trim_end(string): the string without
+// leading and/or trailing whitespace
+fn trim(args: &[LoxValue]) -> Result<LoxValue, Error> {
+    Ok(LoxValue::LString(string("trim", &args[0])?.trim().into()))
+}
+
+fn trim_start(args: &[LoxValue]) -> Result<LoxValue, Error> {
+    Ok(LoxValue::LString(string("trim_start", &args[0])?.trim_start().into()))
+}
+
+fn trim_end(args: &[LoxValue]) -> Result<LoxValue, Error> {
+    Ok(LoxValue::LString(string("trim_end", &args[0])?.trim_end().into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use LoxValue::*;
+
+    fn numbers(values: &[f64]) -> LoxValue {
+        list(values.iter().map(|n| LNumber(*n)).collect())
+    }
+
+    #[test]
+    fn test_range() {
+        assert_eq!(range(&[LNumber(0.0), LNumber(3.0)]).unwrap(), numbers(&[0.0, 1.0, 2.0]));
+        assert_eq!(
+            range(&[LNumber(1.0), LNumber(10.0), LNumber(4.0)]).unwrap(),
+            numbers(&[1.0, 5.0, 9.0])
+        );
+        assert_eq!(
+            range(&[LNumber(3.0), LNumber(0.0), LNumber(-1.0)]).unwrap(),
+            numbers(&[3.0, 2.0, 1.0])
+        );
+        assert_eq!(range(&[LNumber(5.0), LNumber(1.0)]).unwrap(), numbers(&[]));
+        assert!(matches!(
+            range(&[LNumber(0.0), LNumber(3.0), LNumber(0.0)]),
+            Err(Error::ValueError(_))
+        ));
+        assert!(matches!(range(&[LNil, LNumber(3.0)]), Err(Error::TypeError(_))));
+        assert_eq!(
+            range(&[LNumber(0.0), LNumber(0.3), LNumber(0.1)]).unwrap(),
+            numbers(&[0.0, 0.1, 0.2])
+        );
+    }
+
+    #[test]
+    fn test_range_limits() {
+        let message = |args: [f64; 3]| match range(&args.map(LNumber)) {
+            Err(Error::ValueError(message)) => message,
+            other => panic!("expected a ValueError, not {other:?}"),
+        };
+        // steps that don't change numbers this big used to loop forever
+        for args in [[1e16, 1e17, 1.0], [1e300, 1e301, 1.0], [0.0, 1.0, 1e-300]] {
+            assert_eq!(message(args), "range() can't make more than 10000000 numbers");
+        }
+        assert_eq!(len(&[range(&[LNumber(1e16), LNumber(1e16 + 8.0)]).unwrap()]).unwrap(), LNumber(8.0));
+        for args in [[0.0, f64::INFINITY, 1.0], [0.0, 1.0, f64::NAN], [f64::NEG_INFINITY, 0.0, 1.0]] {
+            assert_eq!(message(args), "range() arguments must be finite");
+        }
+        assert_eq!(message([0.0, 3.0, 0.0]), "range() step must not be zero");
+    }
+
+    fn thrown(result: Result<LoxValue, Error>) -> String {
+        match result {
+            Err(Error::LoxThrow(LString(message))) => message.to_string(),
+            other => panic!("expected a thrown string, not {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_assert() {
+        assert_eq!(assert(&[LBoolean(true), LString("fine".into())]).unwrap(), LNil);
+        assert_eq!(assert(&[LNumber(0.0), LString("zero is truthy".into())]).unwrap(), LNil);
+        assert_eq!(thrown(assert(&[LNil, LString("no value".into())])), "no value");
+        assert_eq!(assert_eq(&[LNumber(2.0), LNumber(2.0), LString("same".into())]).unwrap(), LNil);
+        assert_eq!(
+            thrown(assert_eq(&[LNumber(2.0), LString("2".into()), LString("sum".into())])),
+            "sum: 2 != \"2\""
+        );
+    }
+
+    #[test]
+    fn test_hash() {
+        let hashed = |value: LoxValue| hash(&[value]).unwrap();
+        assert_eq!(hashed(LNil), hashed(LNil));
+        assert_eq!(hashed(LNumber(1.0)), hashed(LNumber(1.0)));
+        assert_eq!(hashed(LNumber(0.0)), hashed(LNumber(-0.0)));
+        assert_eq!(hashed(LString("a".into())), hashed(LString("a".into())));
+        assert_ne!(hashed(LString("a".into())), hashed(LString("b".into())));
+        assert_ne!(hashed(LNumber(1.0)), hashed(LBoolean(true)));
+        assert!(matches!(hashed(LBoolean(false)), LNumber(_)));
+        assert!(matches!(hash(&[numbers(&[1.0])]), Err(Error::TypeError(_))));
+    }
+
+    #[test]
+    fn test_repr() {
+        let repr_of = |value: LoxValue| match repr(&[value]) {
+            Ok(LString(s)) => s.to_string(),
+            other => panic!("expected a string, not {other:?}"),
+        };
+        assert_eq!(repr_of(LString("hello".into())), "\"hello\"");
+        assert_eq!(repr_of(LString("a\tb\n".into())), "\"a\\tb\\n\"");
+        assert_eq!(repr_of(LNumber(42.0)), "42");
+        assert_eq!(repr_of(LNil), "nil");
+        assert_eq!(repr_of(numbers(&[1.0, 2.0])), "[1, 2]");
+        assert_eq!(repr_of(strings(&["a"])), "[\"a\"]");
+    }
+
+    #[test]
+    fn test_format() {
+        let formatted = |args: &[LoxValue]| match format(args) {
+            Ok(LString(s)) => s.to_string(),
+            other => panic!("expected a string, not {other:?}"),
+        };
+        let greeting = LString("Hello, {}! You are {} years old.".into());
+        assert_eq!(
+            formatted(&[greeting.clone(), LString("Ada".into()), LNumber(36.0)]),
+            "Hello, Ada! You are 36 years old."
+        );
+        assert_eq!(formatted(&[LString("{} {}".into()), LNil, numbers(&[1.5])]), "nil [1.5]");
+        assert_eq!(formatted(&[LString("{{}} {}}".into()), LBoolean(true)]), "{} true}");
+        assert_eq!(formatted(&[LString("none".into())]), "none");
+        assert_eq!(
+            format(&[greeting, LString("Ada".into())]).unwrap_err().to_string(),
+            "format() template has 2 placeholders but got 1 values"
+        );
+        assert!(matches!(format(&[LString("{}".into()), LNil, LNil]), Err(Error::ValueError(_))));
+        assert!(matches!(format(&[LNumber(1.0)]), Err(Error::TypeError(_))));
+    }
+
+    fn strings(values: &[&str]) -> LoxValue {
+        list(values.iter().map(|s| LString((*s).into())).collect())
+    }
+
+    #[test]
+    fn test_split() {
+        let split_on = |s: &str, d: &str| split(&[LString(s.into()), LString(d.into())]).unwrap();
+        assert_eq!(split_on("a,b,c", ","), strings(&["a", "b", "c"]));
+        assert_eq!(split_on("abc", ""), strings(&["a", "b", "c"]));
+        assert_eq!(split_on("abc", "x"), strings(&["abc"]));
+        assert_eq!(split_on("a::b", "::"), strings(&["a", "b"]));
+        assert!(matches!(split(&[LString("a".into()), LNil]), Err(Error::TypeError(_))));
+    }
+
+    #[test]
+    fn test_substr() {
+        let hello = || LString("hello".into());
+        assert_eq!(substr(&[hello(), LNumber(1.0), LNumber(3.0)]).unwrap(), LString("ell".into()));
+        assert_eq!(substr(&[hello(), LNumber(2.0), LNumber(100.0)]).unwrap(), LString("llo".into()));
+        assert_eq!(substr(&[hello(), LNumber(2.0)]).unwrap(), LString("llo".into()));
+        assert_eq!(substr(&[hello(), LNumber(5.0)]).unwrap(), LString("".into()));
+        assert!(matches!(substr(&[hello(), LNumber(-1.0)]), Err(Error::IndexOutOfBounds(_))));
+        assert!(matches!(substr(&[hello(), LNumber(6.0)]), Err(Error::IndexOutOfBounds(_))));
+        assert!(matches!(substr(&[hello(), LNumber(0.5)]), Err(Error::ValueError(_))));
+    }
+
+    #[test]
+    fn test_case() {
+        let mixed = || [LString("Hello, World 1".into())];
+        assert_eq!(to_upper(&mixed()).unwrap(), LString("HELLO, WORLD 1".into()));
+        assert_eq!(to_lower(&mixed()).unwrap(), LString("hello, world 1".into()));
+        assert_eq!(to_upper(&[LString("ß".into())]).unwrap(), LString("SS".into()));
+        assert!(matches!(to_lower(&[LNil]), Err(Error::TypeError(_))));
+    }
+
+    #[test]
+    fn test_trim() {
+        let padded = || [LString("  hello  ".into())];
+        assert_eq!(trim(&padded()).unwrap(), LString("hello".into()));
+        assert_eq!(trim_start(&padded()).unwrap(), LString("hello  ".into()));
+        assert_eq!(trim_end(&padded()).unwrap(), LString("  hello".into()));
+        assert_eq!(trim(&[LString("\t\nhi\n".into())]).unwrap(), LString("hi".into()));
+        assert!(matches!(trim(&[LNumber(1.0)]), Err(Error::TypeError(_))));
+    }
+
+    #[test]
+    fn test_search() {
+        let pair = |a: &str, b: &str| [LString(a.into()), LString(b.into())];
+        assert_eq!(contains(&pair("hello world", "world")).unwrap(), LBoolean(true));
+        assert_eq!(contains(&pair("hello world", "World")).unwrap(), LBoolean(false));
+        assert_eq!(starts_with(&pair("foobar", "foo")).unwrap(), LBoolean(true));
+        assert_eq!(ends_with(&pair("foobar", "baz")).unwrap(), LBoolean(false));
+        assert_eq!(ends_with(&pair("foobar", "")).unwrap(), LBoolean(true));
+        assert!(matches!(contains(&[LString("a".into()), LNumber(1.0)]), Err(Error::TypeError(_))));
+        assert!(matches!(starts_with(&[LNil, LString("a".into())]), Err(Error::TypeError(_))));
+    }
+
+    #[test]
+    fn test_rounding() {
+        let n = |n: f64| [LNumber(n)];
+        assert_eq!(floor(&n(1.9)).unwrap(), LNumber(1.0));
+        assert_eq!(floor(&n(-1.9)).unwrap(), LNumber(-2.0));
+        assert_eq!(ceil(&n(1.1)).unwrap(), LNumber(2.0));
+        assert_eq!(ceil(&n(-1.1)).unwrap(), LNumber(-1.0));
+        assert_eq!(round(&n(1.5)).unwrap(), LNumber(2.0));
+        assert_eq!(round(&n(-1.5)).unwrap(), LNumber(-2.0));
+        assert!(matches!(round(&[LString("1".into())]), Err(Error::TypeError(_))));
+    }
+
+    #[test]
+    fn test_to_fixed() {
+        let fixed = |n: f64, digits: f64| to_fixed(&[LNumber(n), LNumber(digits)]);
+        assert_eq!(fixed(0.1 + 0.2, 2.0).unwrap(), LString("0.30".into()));
+        assert_eq!(fixed(2.5, 0.0).unwrap(), LString("2".into()));
+        assert_eq!(fixed(-1.005, 1.0).unwrap(), LString("-1.0".into()));
+        assert_eq!(fixed(1.5e21, 2.0).unwrap(), LString("1.50e21".into()));
+        assert!(matches!(fixed(1.0, -1.0), Err(Error::ValueError(_))));
+        assert!(matches!(fixed(1.0, 1.5), Err(Error::ValueError(_))));
+    }
+
+    #[test]
+    fn test_math() {
+        assert_eq!(sqrt(&[LNumber(4.0)]).unwrap(), LNumber(2.0));
+        assert!(matches!(sqrt(&[LNumber(-1.0)]).unwrap(), LNumber(n) if n.is_nan()));
+        assert_eq!(abs(&[LNumber(-5.0)]).unwrap(), LNumber(5.0));
+        assert_eq!(pow(&[LNumber(2.0), LNumber(10.0)]).unwrap(), LNumber(1024.0));
+        assert_eq!(pow(&[LNumber(4.0), LNumber(0.5)]).unwrap(), LNumber(2.0));
+        assert!(matches!(abs(&[LNil]), Err(Error::TypeError(_))));
+        assert!(matches!(pow(&[LNumber(2.0), LBoolean(true)]), Err(Error::TypeError(_))));
+    }
+
+    #[test]
+    fn test_floor_div() {
+        assert_eq!(floor_div(&[LNumber(-7.0), LNumber(2.0)]).unwrap(), LNumber(-4.0));
+        assert!(matches!(floor_div(&[LNumber(1.0), LNumber(0.0)]), Err(Error::ZeroDivision { .. })));
+    }
+
+    #[test]
+    fn test_get_and_set() {
+        let entries = LMap(Rc::new(RefCell::new(HashMap::new())));
+        assert_eq!(get(&[entries.clone(), LString("a".into())]).unwrap(), LNil);
+        set(&[entries.clone(), LString("a".into()), LNumber(1.0)]).unwrap();
+        set(&[entries.clone(), LString("a".into()), LNumber(2.0)]).unwrap();
+        assert_eq!(get(&[entries.clone(), LString("a".into())]).unwrap(), LNumber(2.0));
+        assert!(matches!(get(&[entries.clone(), numbers(&[])]), Err(Error::TypeError(_))));
+        assert!(matches!(set(&[LNil, LNil, LNil]), Err(Error::TypeError(_))));
+    }
+
+    #[test]
+    fn test_len() {
+        assert_eq!(len(&[range(&[LNumber(1.0), LNumber(5.0)]).unwrap()]).unwrap(), LNumber(4.0));
+        assert_eq!(len(&[LString("héllo".into())]).unwrap(), LNumber(5.0));
+        assert!(matches!(len(&[LNumber(1.0)]), Err(Error::TypeError(_))));
+    }
+}
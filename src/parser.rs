@@ -1,53 +1,155 @@
+use std::rc::Rc;
+
 use crate::ast::{AST, Expr, Operator, Stmt};
-use crate::tokenize::TokenType::*;
-use crate::tokenize::{Token, TokenType, Tokens};
-
-impl From<&Token> for Operator {
-    fn from(tok: &Token) -> Self {
-        match tok.toktype {
-            TPlus => Operator::OAdd,
-            TMinus => Operator::OSub,
-            TStar => Operator::OMul,
-            TSlash => Operator::ODiv,
-            TLess => Operator::OLt,
-            TLessEqual => Operator::OLe,
-            TGreater => Operator::OGt,
-            TGreaterEqual => Operator::OGe,
-            TEqualEqual => Operator::OEq,
-            TBangEqual => Operator::ONe,
-            TAnd => Operator::OAnd,
-            TOr => Operator::OOr,
-            TBang => Operator::ONot,
-            _ => panic!("Not an operator {:?}", tok.toktype),
-        }
+use crate::ast_id::{ExprId, StmtId};
+use crate::token::TokenType::*;
+use crate::token::{Literal, Span, Token, TokenType, Tokens};
+
+impl TryFrom<&Token> for Operator {
+    type Error = Error;
+
+    fn try_from(tok: &Token) -> Result<Self, Error> {
+        operator(&tok.toktype).ok_or_else(|| Error::SyntaxError {
+            line: tok.line,
+            msg: format!("'{}' is not an operator", tok.lexeme),
+        })
     }
 }
 
+// the operator a token stands for, if it's one
+fn operator(toktype: &TokenType) -> Option<Operator> {
+    Some(match toktype {
+        TPlus => Operator::OAdd,
+        TMinus => Operator::OSub,
+        TStar => Operator::OMul,
+        TSlash => Operator::ODiv,
+        TPercent => Operator::OMod,
+        TLess => Operator::OLt,
+        TLessEqual => Operator::OLe,
+        TGreater => Operator::OGt,
+        TGreaterEqual => Operator::OGe,
+        TEqualEqual => Operator::OEq,
+        TBangEqual => Operator::ONe,
+        TAnd => Operator::OAnd,
+        TOr => Operator::OOr,
+        TBang => Operator::ONot,
+        _ => return None,
+    })
+}
+
 #[derive(Debug)]
 pub enum Error {
     SyntaxError { line: usize, msg: String },
+    // everything that was wrong, when there was more than one thing
+    Multiple(Vec<Error>),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            Error::SyntaxError { line, msg } => write!(formatter, "Line {line}: Syntax error: {msg}"),
+            Error::Multiple(errors) => {
+                for (n, error) in errors.iter().enumerate() {
+                    if n > 0 {
+                        writeln!(formatter)?;
+                    }
+                    write!(formatter, "{error}")?;
+                }
+                Ok(())
+            }
+        }
+    }
 }
 
 // pub type Error = ();  // roughly playing the same role as Python’s None
 
-pub struct Parser {
+pub struct Parser<'a> {
     // parsing involves a left-to-right scan over tokens. Sometimes
     // peeking ahead is necessary. This struct is used for managing that.
-    tokens: Vec<Token>,
+    // The tokens are borrowed, so they can be parsed again or looked at
+    // after a failed parse.
+    tokens: &'a [Token],
+    // the next token, and the one last consumed. Comments in the tokens are
+    // stepped over, so the next token is never one.
     n: usize,
+    last: Option<usize>,
+    // syntax errors recovered from so far
+    errors: Vec<Error>,
+    // what the expression being parsed is part of, innermost last, so that
+    // an error inside it can say so: "Expected expression in if condition"
+    context: Vec<&'static str>,
+    // parsing the body of a class's init method, where `return` can't have
+    // a value
+    in_initializer: bool,
+    // the id for the next node made (see ast_id)
+    next_id: u32,
 }
 
-impl Parser {
-    pub fn new(tokens: Tokens) -> Self {
-        Self {
-            tokens: tokens.tokens,
+impl<'a> Parser<'a> {
+    pub fn new(tokens: &'a [Token]) -> Self {
+        let mut parser = Self {
+            tokens,
             n: 0,
+            last: None,
+            errors: Vec::new(),
+            context: Vec::new(),
+            in_initializer: false,
+            next_id: 1,
+        };
+        parser.skip_comments();
+        parser
+    }
+
+    // ids for the nodes as they're made, none the same as another's
+    fn expr_id(&mut self) -> ExprId {
+        self.next_id += 1;
+        ExprId(self.next_id - 1)
+    }
+
+    fn stmt_id(&mut self) -> StmtId {
+        self.next_id += 1;
+        StmtId(self.next_id - 1)
+    }
+
+    // consume the next token
+    fn advance(&mut self) {
+        self.last = Some(self.n);
+        self.n += 1;
+        self.skip_comments();
+    }
+
+    // the text of the /// comments on the lines just above the first'th
+    // token, one line each. A blank line or code in between means they're
+    // about something else.
+    fn doc_comment(&self, first: usize) -> Option<Rc<str>> {
+        let mut lines = Vec::new();
+        let mut line = self.tokens[first].line;
+        for n in (0..first).rev() {
+            let comment = &self.tokens[n];
+            let alone = n == 0 || self.tokens[n - 1].line < comment.line;
+            if comment.toktype != (TComment { doc: true }) || comment.line + 1 != line || !alone {
+                break;
+            }
+            let text = &comment.lexeme["///".len()..];
+            lines.push(text.strip_prefix(' ').unwrap_or(text));
+            line = comment.line;
+        }
+        if lines.is_empty() {
+            return None;
+        }
+        lines.reverse();
+        Some(lines.join("\n").into())
+    }
+
+    fn skip_comments(&mut self) {
+        while matches!(self.tokens.get(self.n), Some(Token { toktype: TComment { .. }, .. })) {
+            self.n += 1;
         }
     }
 
     fn accept(&mut self, toktype: TokenType) -> bool {
         if !self.at_end() && self.tokens[self.n].toktype == toktype {
-            self.n += 1;
+            self.advance();
             true
         } else {
             false
@@ -57,13 +159,28 @@ impl Parser {
     // accept any token from a list of possible types
     fn accepts<const N: usize>(&mut self, toktypes: [TokenType; N]) -> bool {
         if !self.at_end() && toktypes.contains(&self.tokens[self.n].toktype) {
-            self.n += 1;
+            self.advance();
             true
         } else {
             false
         }
     }
 
+    // the type of the token offset places after the next one (0: the next
+    // one), stepping over comments. None past the end.
+    fn peek_type(&self, offset: usize) -> Option<&TokenType> {
+        self.tokens[self.n..]
+            .iter()
+            .filter(|token| !matches!(token.toktype, TComment { .. }))
+            .nth(offset)
+            .map(|token| &token.toktype)
+    }
+
+    // is the next token of the given type? (does not consume it)
+    fn check(&self, toktype: TokenType) -> bool {
+        !self.at_end() && self.tokens[self.n].toktype == toktype
+    }
+
     fn consume(&mut self, toktype: TokenType, msg: &str) -> Result<(), Error> {
         // require the next token to exactly match the given tokentype or else error
         if !self.accept(toktype) {
@@ -73,7 +190,57 @@ impl Parser {
         }
     }
 
+    // consume, for tokens inside an expression: the error names the construct
+    // the expression is part of
+    fn consume_in_context(&mut self, toktype: TokenType, msg: &str) -> Result<(), Error> {
+        if !self.accept(toktype) {
+            Err(self.syntax_error_in_context(msg))
+        } else {
+            Ok(())
+        }
+    }
+
+    // an identifier used as a `what` ("variable name"). A keyword in its place
+    // gets an error of its own, and is skipped so recovery doesn't take it for
+    // the start of the next statement.
+    fn consume_identifier(&mut self, what: &str, msg: &str) -> Result<(), Error> {
+        if self.next_is_keyword() {
+            let token = &self.tokens[self.n];
+            let error = Error::SyntaxError {
+                line: token.line,
+                msg: format!("'{}' is a reserved word and cannot be used as a {what}", token.lexeme),
+            };
+            self.advance();
+            return Err(error);
+        }
+        self.consume(TIdentifier, msg)
+    }
+
+    fn next_is_keyword(&self) -> bool {
+        !self.at_end() && self.tokens[self.n].toktype.is_keyword()
+    }
+
+    // an expression that's part of context
+    fn parse_expression_in(&mut self, context: &'static str) -> Result<Expr, Error> {
+        self.context.push(context);
+        let expr = self.parse_expression();
+        self.context.pop();
+        expr
+    }
+
+    fn syntax_error_in_context(&self, msg: &str) -> Error {
+        match self.context.last() {
+            Some(context) => self.syntax_error(&format!("{msg} in {context}")),
+            None => self.syntax_error(msg),
+        }
+    }
+
     // helper function to create a syntax error
+    // how many tokens have been consumed so far
+    pub fn position(&self) -> usize {
+        self.n
+    }
+
     fn syntax_error(&self, msg: &str) -> Error {
         Error::SyntaxError {
             line: self.tokens[self.n].line,
@@ -81,13 +248,30 @@ impl Parser {
         }
     }
 
+    // is the next token the first of a statement, or just after a keyword
+    // that begins one?
+    fn at_statement_start(&self) -> bool {
+        match self.last.map(|n| &self.tokens[n]) {
+            None => true,
+            Some(token) => {
+                matches!(token.toktype, TSemicolon | TLeftBrace | TRightBrace) || token.toktype.is_keyword()
+            }
+        }
+    }
+
     // return the last matched token (a borrow)
-    fn last_token(&self) -> &Token {
-        &self.tokens[self.n - 1]
+    fn last_token(&self) -> &'a Token {
+        &self.tokens[self.last.expect("a token has been consumed")]
+    }
+
+    // from the start of the first'th token to the end of the last one
+    // consumed
+    fn span_from(&self, first: usize) -> Span {
+        self.tokens[first].span.to(self.last_token().span)
     }
 
-    fn last_lexeme(&self) -> &String {
-        &self.tokens[self.n - 1].lexeme
+    fn last_lexeme(&self) -> &'a Rc<str> {
+        &self.last_token().lexeme
     }
 
     fn at_end(&self) -> bool {
@@ -95,63 +279,370 @@ impl Parser {
     }
 
     fn parse_top(&mut self) -> Result<AST, Error> {
-        let top = self.parse_statements()?;
+        let top = self.parse_statements_until(TEof);
+
+        if !self.at_end() {
+            let error = self.syntax_error("Unparsed input");
+            self.errors.push(error);
+        }
+        match self.errors.len() {
+            0 => Ok(AST { top }),
+            1 => Err(self.errors.remove(0)),
+            _ => Err(Error::Multiple(std::mem::take(&mut self.errors))),
+        }
+    }
 
+    fn parse_top_expression(&mut self) -> Result<Expr, Error> {
+        let expr = self.parse_expression()?;
         if !self.at_end() {
             return Err(self.syntax_error("Unparsed input"));
         }
-        Ok(AST { top })
+        Ok(expr)
+    }
+
+    fn parse_statements_until(&mut self, stop: TokenType) -> Vec<Stmt> {
+        // zero or more statements, up to the stop token (not consumed) or
+        // the end: TEof for a whole program, '}' for a block
+        let mut statements = Vec::new();
+        while self.peek_type(0) != Some(&stop) && !self.at_end() {
+            statements.extend(self.parse_declaration_or_recover());
+        }
+        statements
     }
 
-    fn parse_statements(&mut self) -> Result<Vec<Stmt>, Error> {
-        // zero or more statements
-        let mut statements= Vec::new();
+    // a declaration, or None after a syntax error. The error is recorded and
+    // the rest of the statement skipped, so parsing can go on to find more.
+    fn parse_declaration_or_recover(&mut self) -> Option<Stmt> {
+        let start = self.n;
+        match self.parse_declaration() {
+            Ok(stmt) => Some(stmt),
+            Err(error) => {
+                self.errors.push(error);
+                // always make progress, even if the first token was the problem
+                if self.n == start {
+                    self.advance();
+                }
+                self.synchronize();
+                None
+            }
+        }
+    }
+
+    // skip to what looks like the start of the next statement
+    fn synchronize(&mut self) {
         while !self.at_end() {
-            statements.push(self.parse_declaration()?);
+            if self.accept(TSemicolon) {
+                return;
+            }
+            match self.tokens[self.n].toktype {
+                TVar | TFun | TClass | TImport | TPrint | TIf | TFor | TWhile | TReturn
+                | TSwitch | TTry | TThrow => return,
+                _ => self.advance(),
+            }
         }
-        Ok(statements)
     }
 
     fn parse_var_declaration(&mut self) -> Result<Stmt, Error> {
-        self.consume(TIdentifier, "Expect variable name")?;
+        self.consume_identifier("variable name", "Expected variable name")?;
         let name = self.last_lexeme().clone();
         let  mut initializer = None;
         if self.accept(TEqual) {
-            initializer = Some(self.parse_expression()?);
+            initializer = Some(self.parse_expression_in("variable initializer")?);
         }
-        self.consume(TSemicolon, "Expect ';' after variable declaration")?;
+        self.consume(TSemicolon, "Expected ';' after variable declaration")?;
         Ok(Stmt::vardecl(name, initializer))
     }
 
+    fn parse_function_declaration(&mut self, method: bool) -> Result<Stmt, Error> {
+        // fun name(param, ..., ...rest) { body }, or a method without the `fun`
+        if method {
+            self.consume_identifier("method name", "Expected method name")?;
+        } else {
+            self.consume_identifier("function name", "Expected function name")?;
+        }
+        let name = self.last_lexeme().clone();
+        self.consume(TLeftParen, "Expected '(' after function name")?;
+        let mut params = Vec::new();
+        let mut rest = None;
+        if !self.check(TRightParen) {
+            loop {
+                if self.accept(TEllipsis) {
+                    // only the last parameter can be a rest parameter
+                    self.consume_identifier("parameter name", "Expected parameter name after '...'")?;
+                    rest = Some(self.last_lexeme().clone());
+                    break;
+                }
+                self.consume_identifier("parameter name", "Expected parameter name")?;
+                params.push(self.last_lexeme().clone());
+                if !self.accept(TComma) {
+                    break;
+                }
+            }
+        }
+        self.consume(TRightParen, "Expected ')' after parameters")?;
+        self.consume(TLeftBrace, "Expected '{' to begin function body")?;
+        let in_initializer = std::mem::replace(&mut self.in_initializer, method && &*name == "init");
+        let body = self.parse_block();
+        self.in_initializer = in_initializer;
+        Ok(Stmt::function(name, params, rest, body?))
+    }
+
+    fn parse_class_declaration(&mut self) -> Result<Stmt, Error> {
+        // class Name [< Superclass] { method(params) { body } ... }
+        self.consume_identifier("class name", "Expected class name")?;
+        let name = self.last_lexeme().clone();
+        let mut superclass = None;
+        if self.accept(TLess) {
+            self.consume_identifier("superclass name", "Expected superclass name")?;
+            if *self.last_lexeme() == name {
+                let line = self.last_token().line;
+                return Err(Error::SyntaxError { line, msg: "A class can't inherit from itself".to_string() });
+            }
+            superclass = Some(Expr::variable(self.last_lexeme().clone()).with_span(self.last_token().span).with_id(self.expr_id()));
+        }
+        self.consume(TLeftBrace, "Expected '{' to begin class body")?;
+        let mut methods = Vec::new();
+        while !self.check(TRightBrace) && !self.at_end() {
+            let first = self.n;
+            let method = self.parse_function_declaration(true)?;
+            methods.push(method.with_span(self.span_from(first)).with_id(self.stmt_id()).with_doc(self.doc_comment(first)));
+        }
+        self.consume(TRightBrace, "Expected '}' after class body")?;
+        Ok(Stmt::class(name, superclass, methods))
+    }
+
+    fn parse_import_declaration(&mut self) -> Result<Stmt, Error> {
+        // import "path/to/file.lox";
+        self.consume(TString, "Expected file name after 'import'")?;
+        let line = self.last_token().line;
+        let lexeme = self.last_lexeme();
+        let path = lexeme[1..lexeme.len() - 1].to_string();
+        self.consume(TSemicolon, "Expected ';' after import")?;
+        Ok(Stmt::import(path, line))
+    }
+
     fn parse_declaration(&mut self) -> Result<Stmt, Error> {
         // parse a declaration or a statement.
-        if self.accept(TVar) {
+        let first = self.n;
+        let declaration = if self.accept(TVar) {
             self.parse_var_declaration()
-        }else {
-            self.parse_statement()
-        }
+        } else if self.accept(TFun) {
+            self.parse_function_declaration(false).map(|function| function.with_doc(self.doc_comment(first)))
+        } else if self.accept(TClass) {
+            self.parse_class_declaration().map(|class| class.with_doc(self.doc_comment(first)))
+        } else if self.accept(TImport) {
+            self.parse_import_declaration()
+        } else {
+            return self.parse_statement();
+        };
+        Ok(declaration?.with_span(self.span_from(first)).with_id(self.stmt_id()))
     }
 
     fn parse_statement(&mut self) -> Result<Stmt, Error> {
         // parse a single statement
-        if self.accept(TPrint) {
+        let first = self.n;
+        let statement = if self.accept(TPrint) {
             self.parse_print_statement()
-        }else {
+        } else if self.accept(TIf) {
+            self.parse_if_statement()
+        } else if self.accept(TFor) {
+            self.parse_for_statement()
+        } else if self.accept(TWhile) {
+            self.parse_while_statement()
+        } else if self.accept(TReturn) {
+            self.parse_return_statement()
+        } else if self.accept(TSwitch) {
+            self.parse_switch_statement()
+        } else if self.accept(TTry) {
+            self.parse_try_statement()
+        } else if self.accept(TThrow) {
+            self.parse_throw_statement()
+        } else if self.accept(TLeftBrace) {
+            self.parse_block().map(Stmt::block)
+        } else {
             self.parse_expression_statement()
+        };
+        Ok(statement?.with_span(self.span_from(first)).with_id(self.stmt_id()))
+    }
+
+    fn parse_block(&mut self) -> Result<Vec<Stmt>, Error> {
+        // declarations up to the closing '}' (the '{' is already consumed)
+        let opened = self.last_token().line;
+        let statements = self.parse_statements_until(TRightBrace);
+        if !self.accept(TRightBrace) {
+            return Err(self.syntax_error(&format!("Expected '}}' to close the block begun on line {opened}")));
         }
+        Ok(statements)
+    }
+
+    fn parse_if_statement(&mut self) -> Result<Stmt, Error> {
+        // if (condition) statement [else statement]
+        self.consume(TLeftParen, "Expected '(' after 'if'")?;
+        let condition = self.parse_expression_in("if condition")?;
+        self.consume(TRightParen, "Expected ')' after condition")?;
+        let then_branch = self.parse_statement()?;
+        let else_branch = if self.accept(TElse) {
+            Some(self.parse_statement()?)
+        } else {
+            None
+        };
+        Ok(Stmt::if_(condition, then_branch, else_branch))
+    }
+
+    fn parse_switch_statement(&mut self) -> Result<Stmt, Error> {
+        // switch (subject) { case value: statements ... default: statements }
+        self.consume(TLeftParen, "Expected '(' after 'switch'")?;
+        let subject = self.parse_expression_in("switch subject")?;
+        self.consume(TRightParen, "Expected ')' after switch subject")?;
+        self.consume(TLeftBrace, "Expected '{' to begin switch cases")?;
+        let mut cases = Vec::new();
+        let mut default = None;
+        while self.accept(TCase) {
+            let value = self.parse_expression_in("case value")?;
+            self.consume(TColon, "Expected ':' after case value")?;
+            cases.push((value, self.parse_case_body()?));
+        }
+        if self.accept(TDefault) {
+            self.consume(TColon, "Expected ':' after 'default'")?;
+            default = Some(self.parse_case_body()?);
+        }
+        self.consume(TRightBrace, "Expected '}' after switch cases")?;
+        Ok(Stmt::switch(subject, cases, default))
+    }
+
+    fn parse_case_body(&mut self) -> Result<Vec<Stmt>, Error> {
+        // statements up to the next case, the default or the closing '}'
+        let mut statements = Vec::new();
+        while !self.check(TCase) && !self.check(TDefault) && !self.check(TRightBrace) && !self.at_end() {
+            statements.push(self.parse_declaration()?);
+        }
+        Ok(statements)
+    }
+
+    fn parse_try_statement(&mut self) -> Result<Stmt, Error> {
+        // try { ... } catch [(name)] { ... } [finally { ... }]
+        self.consume(TLeftBrace, "Expected '{' to begin block after 'try'")?;
+        let try_body = self.parse_block()?;
+        self.consume(TCatch, "Expected 'catch' after try block")?;
+        let mut catch_var = None;
+        if self.accept(TLeftParen) {
+            self.consume_identifier("variable name", "Expected variable name after '('")?;
+            catch_var = Some(self.last_lexeme().clone());
+            self.consume(TRightParen, "Expected ')' after catch variable")?;
+        }
+        self.consume(TLeftBrace, "Expected '{' to begin block after 'catch'")?;
+        let catch_body = self.parse_block()?;
+        let mut finally_body = Vec::new();
+        if self.accept(TFinally) {
+            self.consume(TLeftBrace, "Expected '{' to begin block after 'finally'")?;
+            finally_body = self.parse_block()?;
+        }
+        Ok(Stmt::try_catch(try_body, catch_var, catch_body, finally_body))
+    }
+
+    fn parse_throw_statement(&mut self) -> Result<Stmt, Error> {
+        // throw expression;
+        let value = self.parse_expression_in("thrown value")?;
+        self.consume(TSemicolon, "Expected ';' after thrown value")?;
+        Ok(Stmt::throw(value))
+    }
+
+    fn parse_while_statement(&mut self) -> Result<Stmt, Error> {
+        // while (condition) statement
+        self.consume(TLeftParen, "Expected '(' after 'while'")?;
+        let condition = self.parse_expression_in("loop condition")?;
+        self.consume(TRightParen, "Expected ')' after condition")?;
+        let body = self.parse_statement()?;
+        Ok(Stmt::while_(condition, body))
+    }
+
+    fn parse_for_statement(&mut self) -> Result<Stmt, Error> {
+        // a name and then `in` make it a for-in loop, else it's C-style
+        self.consume(TLeftParen, "Expected '(' after 'for'")?;
+        let for_in = match self.peek_type(0) {
+            Some(TVar) => self.peek_type(2) == Some(&TIn),
+            Some(TIdentifier) => self.peek_type(1) == Some(&TIn),
+            _ => false,
+        };
+        if for_in {
+            self.parse_for_in_statement()
+        } else {
+            self.parse_c_for_statement()
+        }
+    }
+
+    fn parse_for_in_statement(&mut self) -> Result<Stmt, Error> {
+        // for ([var] name in iterable) statement
+        self.accept(TVar);
+        self.consume_identifier("variable name", "Expected loop variable name")?;
+        let var = self.last_lexeme().clone();
+        self.consume(TIn, "Expected 'in' after loop variable")?;
+        let iterable = self.parse_expression_in("for loop")?;
+        self.consume(TRightParen, "Expected ')' after for clauses")?;
+        let body = self.parse_statement()?;
+        Ok(Stmt::for_in(var, iterable, body))
+    }
+
+    fn parse_c_for_statement(&mut self) -> Result<Stmt, Error> {
+        // for ([initializer]; [condition]; [increment]) statement, which runs
+        // as { initializer; while (condition) { statement increment; } }
+        let first = self.n;
+        let initializer = if self.accept(TSemicolon) {
+            None
+        } else if self.accept(TVar) {
+            Some(self.parse_var_declaration()?.with_span(self.span_from(first)).with_id(self.stmt_id()))
+        } else {
+            Some(self.parse_expression_statement()?.with_span(self.span_from(first)).with_id(self.stmt_id()))
+        };
+        let condition = if self.check(TSemicolon) {
+            Expr::bool(true)
+        } else {
+            self.parse_expression_in("loop condition")?
+        };
+        self.consume(TSemicolon, "Expected ';' after loop condition")?;
+        let increment = if self.check(TRightParen) {
+            None
+        } else {
+            Some(self.parse_expression_in("loop increment")?)
+        };
+        self.consume(TRightParen, "Expected ')' after for clauses")?;
+        let mut body = self.parse_statement()?;
+        if let Some(increment) = increment {
+            let span = increment.span();
+            let increment = Stmt::expression(increment).with_span(span).with_id(self.stmt_id());
+            body = Stmt::block(vec![body, increment]).with_span(span.to(self.span_from(first))).with_id(self.stmt_id());
+        }
+        let looped = Stmt::while_(condition, body);
+        Ok(match initializer {
+            Some(initializer) => Stmt::block(vec![initializer, looped.with_span(self.span_from(first)).with_id(self.stmt_id())]),
+            None => looped,
+        })
+    }
+
+    fn parse_return_statement(&mut self) -> Result<Stmt, Error> {
+        // return [expression];
+        let value = if self.check(TSemicolon) {
+            None
+        } else if self.in_initializer {
+            return Err(self.syntax_error("Can't return a value from an initializer"));
+        } else {
+            Some(self.parse_expression_in("return value")?)
+        };
+        self.consume(TSemicolon, "Expected ';' after return value")?;
+        Ok(Stmt::return_(value))
     }
 
     fn parse_print_statement(&mut self) -> Result<Stmt, Error> {
         // print expression
-        let value = self.parse_expression()?;
-        self.consume(TSemicolon, "Expected ';' after value.")?;
+        let value = self.parse_expression_in("print statement")?;
+        self.consume(TSemicolon, "Expected ';' after value")?;
         Ok(Stmt::print(value))
     }
 
     fn parse_expression_statement(&mut self) -> Result<Stmt, Error> {
         // expression
         let value = self.parse_expression()?;
-        self.consume(TSemicolon, "Expected ';' after value.")?;
+        self.consume(TSemicolon, "Expected ';' after expression")?;
         Ok(Stmt::expression(value))
     }
 
@@ -159,45 +650,100 @@ impl Parser {
         self.parse_assignment()
     }
 
+    // assignment binds more loosely than any operator, `or` included, and
+    // to the right: `a = b = 3` assigns 3 to b and then to a. Its value is
+    // the value assigned, so it can be part of a larger expression in
+    // parentheses.
     pub fn parse_assignment(&mut self) -> Result<Expr, Error> {
+        let first = self.n;
         let expr = self.parse_binary()?;
         if self.accept(TEqual) {
+            let line = self.last_token().line;
             let value = self.parse_assignment()?;
-            if let Expr::EVariable {name} = expr {
-                return Ok(Expr::assign(name, value));
-            }else {
-                panic!("invalid assignment target");
-            }
+            let span = self.span_from(first);
+            return match expr {
+                Expr::EVariable { name, .. } => Ok(Expr::assign(name, value).with_span(span).with_id(self.expr_id())),
+                Expr::EGet { object, name, line, .. } => Ok(Expr::set(*object, name, value, line).with_span(span).with_id(self.expr_id())),
+                _ => Err(Error::SyntaxError { line, msg: "Invalid assignment target".to_string() }),
+            };
         }
         Ok(expr)
     }
 
     pub fn parse_binary(&mut self) -> Result<Expr, Error> {
-        let left = self.parse_unary()?;
-        if self.accepts([
-            TPlus, TMinus, TStar, TSlash, TLess, TLessEqual, TGreater, TGreaterEqual, TEqualEqual, TBangEqual
-        ]) {
-            let op = Operator::from(self.last_token());
+        self.parse_binary_from(1)
+    }
+
+    // operands joined by binary operators that bind at least as tightly as
+    // min (see Operator::precedence), by precedence climbing: a looser
+    // operator further on ends this call and is taken up by a caller
+    fn parse_binary_from(&mut self, min: u8) -> Result<Expr, Error> {
+        let first = self.n;
+        let mut left = self.parse_unary()?;
+        while let Some(op) = self.next_binary_operator().filter(|op| op.precedence() >= min) {
+            self.advance();
+            let line = self.last_token().line;
+            let next = if op.is_right_associative() { op.precedence() } else { op.precedence() + 1 };
+            let right = self.parse_binary_from(next)?;
+            left = Expr::binary(left, op, right, line).with_span(self.span_from(first)).with_id(self.expr_id());
+        }
+        Ok(left)
+    }
+
+    fn next_binary_operator(&self) -> Option<Operator> {
+        operator(&self.tokens.get(self.n)?.toktype).filter(Operator::is_binary)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, Error> {
+        let first = self.n;
+        if self.accepts([TMinus, TBang]) {
+            let op = Operator::try_from(self.last_token())?;
+            let line = self.last_token().line;
             let right = self.parse_unary()?;
-            Ok(Expr::binary(left, op, right))
+            Ok(Expr::unary(op, right, line).with_span(self.span_from(first)).with_id(self.expr_id()))
         } else {
-            Ok(left)
+            self.parse_call()
         }
     }
 
-    fn parse_unary(&mut self) -> Result<Expr, Error> {
-        if self.accepts([TMinus, TBang]){
-            let op = Operator::from(self.last_token());
-            Ok(Expr::unary(op, self.parse_expression()?))
-        }else {
-            Ok(self.parse_primary()?)
+    // a primary followed by any number of argument lists and property
+    // accesses: f(1)(2), point.x, list.first().name
+    fn parse_call(&mut self) -> Result<Expr, Error> {
+        let first = self.n;
+        let mut expr = self.parse_primary()?;
+        loop {
+            if self.accept(TDot) {
+                let line = self.last_token().line;
+                self.consume_in_context(TIdentifier, "Expected property name after '.'")?;
+                expr = Expr::get(expr, self.last_lexeme().clone(), line).with_span(self.span_from(first)).with_id(self.expr_id());
+                continue;
+            }
+            if !self.accept(TLeftParen) {
+                break;
+            }
+            let mut args = Vec::new();
+            if !self.check(TRightParen) {
+                loop {
+                    args.push(self.parse_expression_in("argument list")?);
+                    if !self.accept(TComma) {
+                        break;
+                    }
+                }
+            }
+            self.consume_in_context(TRightParen, "Expected ')' after arguments")?;
+            expr = Expr::call(expr, args, self.last_token().line).with_span(self.span_from(first)).with_id(self.expr_id());
         }
+        Ok(expr)
     }
 
     // parse a single value (like a literal number, string, etc.)
     fn parse_primary(&mut self) -> Result<Expr, Error> {
-        Ok(if self.accept(TNumber) {
-            Expr::number(self.last_lexeme())
+        let first = self.n;
+        let expr = if self.accept(TNumber) {
+            match self.last_token().literal {
+                Literal::Num(value) => Expr::number(value),
+                _ => unreachable!("number tokens carry their value"),
+            }
         } else if self.accept(TString) {
             let lexeme = self.last_lexeme();
             Expr::string(&lexeme[1..lexeme.len() - 1])
@@ -207,32 +753,261 @@ impl Parser {
             Expr::bool(true)
         } else if self.accept(TFalse) {
             Expr::bool(false)
+        } else if self.accept(TThis) {
+            Expr::this(self.last_token().line)
+        } else if self.accept(TSuper) {
+            let line = self.last_token().line;
+            self.consume(TDot, "Expected '.' after 'super'")?;
+            self.consume(TIdentifier, "Expected superclass method name")?;
+            Expr::super_(self.last_lexeme().clone(), line)
         } else if self.accept(TLeftParen) {
-            let expr = self.parse_expression()?;
-            self.consume(TRightParen, "Expect ')' after expression.")?;
+            let expr = self.parse_expression_in("parentheses")?;
+            self.consume_in_context(TRightParen, "Expected ')' after expression")?;
             Expr::grouping(expr)
 
         } else if self.accept(TIdentifier) {
-            Expr::variable(self.last_lexeme())
+            Expr::variable(self.last_lexeme().clone())
+        } else if self.accept(TLeftBracket) {
+            let mut elements = Vec::new();
+            if !self.check(TRightBracket) {
+                loop {
+                    elements.push(self.parse_expression_in("list")?);
+                    if !self.accept(TComma) {
+                        break;
+                    }
+                }
+            }
+            self.consume_in_context(TRightBracket, "Expected ']' after list elements")?;
+            Expr::list(elements)
+        } else if self.accept(TLeftBrace) {
+            // only reached in expression position: a '{' starting a statement
+            // is a block
+            let mut pairs = Vec::new();
+            if !self.check(TRightBrace) {
+                loop {
+                    let key = self.parse_expression_in("map")?;
+                    self.consume_in_context(TColon, "Expected ':' after map key")?;
+                    pairs.push((key, self.parse_expression_in("map")?));
+                    if !self.accept(TComma) {
+                        break;
+                    }
+                }
+            }
+            self.consume_in_context(TRightBrace, "Expected '}' after map entries")?;
+            Expr::map(pairs)
         }
-        else {
-            return Err(self.syntax_error("Expected primary"));
-        })
+        else if self.check(TEqual) && self.at_statement_start() {
+            // `= 3;`, or `print = 3;` with a keyword meant as a variable
+            let line = self.tokens[self.n].line;
+            let msg = "Unexpected '='; did you mean to declare a variable with 'var'?".to_string();
+            return Err(Error::SyntaxError { line, msg });
+        } else {
+            return Err(self.syntax_error_in_context("Expected expression"));
+        };
+        Ok(expr.with_span(self.span_from(first)).with_id(self.expr_id()))
     }
 }
 
-pub fn parse(tokens: Tokens) -> Result<AST, Error> {
-    println!("Parsing");
-    Parser::new(tokens).parse_top()
+pub fn parse(tokens: &Tokens) -> Result<AST, Error> {
+    Parser::new(&tokens.tokens).parse_top()
     // Ok(AST {top: None})
 }
 
+// the tokens as one expression, with nothing after it
+pub fn parse_expr(tokens: &Tokens) -> Result<Expr, Error> {
+    Parser::new(&tokens.tokens).parse_top_expression()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::reader::Source;
+    use crate::token::shape;
+    use crate::tokenize::tokenize;
 
     #[test]
     fn its_alive() {
         assert_eq!(true, true);
     }
+
+    #[test]
+    fn test_error_messages() {
+        let cases = [
+            ("if x > 1) print x;", "Line 1: Syntax error: Expected '(' after 'if' at \"x\""),
+            ("if (x > 1 print x;", "Line 1: Syntax error: Expected ')' after condition at \"print\""),
+            ("if () print 1;", "Line 1: Syntax error: Expected expression in if condition at \")\""),
+            ("if (f(1,)) print 1;", "Line 1: Syntax error: Expected expression in argument list at \")\""),
+            ("print [1, 2;", "Line 1: Syntax error: Expected ']' after list elements in print statement at \";\""),
+            ("var m = {1 2};", "Line 1: Syntax error: Expected ':' after map key in variable initializer at \"2\""),
+            ("try print 1;", "Line 1: Syntax error: Expected '{' to begin block after 'try' at \"print\""),
+            ("fun f() {\n  print 1;\n", "Line 3: Syntax error: Expected '}' to close the block begun on line 1 at \"\""),
+            ("for (x in ) {}", "Line 1: Syntax error: Expected expression in for loop at \")\""),
+            ("for (var i = 0; i < 3 i) {}", "Line 1: Syntax error: Expected ';' after loop condition at \"i\""),
+            ("while x {}", "Line 1: Syntax error: Expected '(' after 'while' at \"x\""),
+            ("1 + 2", "Line 1: Syntax error: Expected ';' after expression at \"\""),
+            ("var class = 1;", "Line 1: Syntax error: 'class' is a reserved word and cannot be used as a variable name"),
+            ("fun f(a, this) {}", "Line 1: Syntax error: 'this' is a reserved word and cannot be used as a parameter name"),
+            ("fun if() {}", "Line 1: Syntax error: 'if' is a reserved word and cannot be used as a function name"),
+            ("print = 3;", "Line 1: Syntax error: Unexpected '='; did you mean to declare a variable with 'var'?"),
+            ("print 1;\n= 3;", "Line 2: Syntax error: Unexpected '='; did you mean to declare a variable with 'var'?"),
+            ("class A < A {}", "Line 1: Syntax error: A class can't inherit from itself"),
+            ("class A { m() { super; } }", "Line 1: Syntax error: Expected '.' after 'super' at \";\""),
+            ("var x = = 3;", "Line 1: Syntax error: Expected expression in variable initializer at \"=\""),
+            ("a + b = c;", "Line 1: Syntax error: Invalid assignment target"),
+            ("a or b = c;", "Line 1: Syntax error: Invalid assignment target"),
+            ("print 1;\nf() = 2;", "Line 2: Syntax error: Invalid assignment target"),
+            ("(a) = 1;", "Line 1: Syntax error: Invalid assignment target"),
+        ];
+        for (program, message) in cases {
+            let error = parse(&tokenize(Source::from(program)).unwrap()).unwrap_err();
+            assert_eq!(error.to_string(), message, "{program}");
+        }
+    }
+
+    #[test]
+    fn test_operator_tokens() {
+        use Operator::*;
+        for op in [OAdd, OSub, OMul, ODiv, OMod, OLt, OLe, OGt, OGe, OEq, ONe, OAnd, OOr, ONot] {
+            let tokens = tokenize(Source::from(op.symbol())).unwrap();
+            assert_eq!(Operator::try_from(&tokens.tokens[0]).unwrap(), op);
+        }
+        let tokens = tokenize(Source::from("\nvar")).unwrap();
+        let error = Operator::try_from(&tokens.tokens[0]).unwrap_err();
+        assert_eq!(error.to_string(), "Line 2: Syntax error: 'var' is not an operator");
+    }
+
+    #[test]
+    fn test_block_bodies() {
+        // a block's statements end at its '}', however deeply it's nested
+        let ast = parse(&tokenize(Source::from("{ var x = 1; { print x; } {} } print 2;")).unwrap()).unwrap();
+        let [Stmt::SBlock { statements, .. }, Stmt::SPrint { .. }] = &ast.top[..] else { panic!("{:?}", ast.top) };
+        assert!(matches!(&statements[..], [Stmt::SVarDecl { .. }, Stmt::SBlock { .. }, Stmt::SBlock { statements: empty, .. }] if empty.is_empty()));
+        // but a '}' with no block to close is an error
+        let error = parse(&tokenize(Source::from("print 1; }")).unwrap()).unwrap_err();
+        assert_eq!(error.to_string(), "Line 1: Syntax error: Expected expression at \"}\"");
+    }
+
+    #[test]
+    fn test_reparse() {
+        let tokens = tokenize(Source::from("var x = (1 + 2);\nprint x;")).unwrap();
+        let first = parse(&tokens).unwrap();
+        let second = parse(&tokens).unwrap();
+        assert_eq!(format!("{:?}", first.top), format!("{:?}", second.top));
+
+        // the tokens are still there to look at after a failure, and
+        // position says how far the parser got
+        let tokens = tokenize(Source::from("1 + ;")).unwrap();
+        let mut parser = Parser::new(&tokens.tokens);
+        assert!(parser.parse_expression().is_err());
+        assert_eq!(parser.position(), 2);
+        assert_eq!(&*tokens.tokens[parser.position()].lexeme, ";");
+    }
+
+    #[test]
+    fn test_var_initializers() {
+        // the initializer is whatever the same expression parses to alone.
+        // There are no block expressions: `{` begins a map.
+        let initializers = [
+            "1", "(1 + 2)", "((1))", "-x", "!(a and b)", "a or b", "f(1, g())", "f()()", "a.b.c",
+            "a.b(1).c", "y = 2", "a.b = 3", "[1, [2]]", "{\"k\": 1}", "{}", "this", "super.m",
+        ];
+        for initializer in initializers {
+            let ast = parse(&tokenize(Source::from(format!("var x = {initializer};"))).unwrap()).unwrap();
+            let expected = parse_expr(&tokenize(Source::from(initializer)).unwrap()).unwrap();
+            match &ast.top[..] {
+                [Stmt::SVarDecl { name, initializer: Some(expr), .. }] => {
+                    assert_eq!(&**name, "x");
+                    assert_eq!(shape(expr), shape(&expected), "{initializer}");
+                }
+                top => panic!("{initializer}: {top:?}"),
+            }
+        }
+        assert!(parse(&tokenize(Source::from("var x = { var y = 1; y + 2; };")).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_spans() {
+        let program = "fun f(a) {\n  if (a > 1) { print [a, -(a + 2)]; } else return f(a).x;\n}\nvar m = {\"k\": f(1)};\n";
+        let ast = parse(&tokenize(Source::from(program)).unwrap()).unwrap();
+        let text = |span: Span| &program[span.start..span.end];
+
+        // every node lies within the one it's part of
+        fn check_expr(expr: &Expr, parent: Span) {
+            assert!(parent.encloses(expr.span()), "{expr:?} isn't within {parent:?}");
+            for inner in expr.subexpressions() {
+                check_expr(inner, expr.span());
+            }
+        }
+        fn check_stmt(stmt: &Stmt, parent: Span) {
+            assert!(parent.encloses(stmt.span()), "{stmt:?} isn't within {parent:?}");
+            stmt.expressions().into_iter().for_each(|expr| check_expr(expr, stmt.span()));
+            stmt.statements().into_iter().for_each(|inner| check_stmt(inner, stmt.span()));
+        }
+        ast.top.iter().for_each(|stmt| check_stmt(stmt, Span::new(0, program.len())));
+
+        assert_eq!(text(ast.top[0].span()), &program[..program.find("\nvar").unwrap()]);
+        assert_eq!(text(ast.top[1].span()), "var m = {\"k\": f(1)};");
+        let print = ast.node_at(program.find("print").unwrap()).unwrap();
+        assert_eq!(text(print.span()), "print [a, -(a + 2)];");
+        let list = print.expressions()[0];
+        assert_eq!(text(list.span()), "[a, -(a + 2)]");
+        assert_eq!(text(list.subexpressions()[1].span()), "-(a + 2)");
+        let ret = ast.node_at(program.find(".x").unwrap()).unwrap();
+        assert_eq!(text(ret.span()), "return f(a).x;");
+        assert_eq!(text(ret.expressions()[0].span()), "f(a).x");
+        // between statements in the function, the function is the innermost
+        assert_eq!(ast.node_at(program.find("  if").unwrap()), Some(&ast.top[0]));
+        assert!(matches!(ast.node_at(program.find("{\"k").unwrap()), Some(Stmt::SVarDecl { .. })));
+        assert_eq!(ast.node_at(program.len() - 1), None);
+    }
+
+    #[test]
+    fn test_comments_skipped() {
+        use crate::tokenize::tokenize_with_comments;
+        let program = "// leading\nfun f(a) { /// doc\n  return a; // done\n}\nprint f(1) // trailing\n;\n// last";
+        let with = tokenize_with_comments(Source::from(program)).unwrap();
+        let without = tokenize(Source::from(program)).unwrap();
+        assert!(with.tokens.len() > without.tokens.len());
+        // spans included
+        assert_eq!(format!("{:?}", parse(&with).unwrap()), format!("{:?}", parse(&without).unwrap()));
+        let one = parse_expr(&tokenize_with_comments(Source::from("// x\n1 // y")).unwrap()).unwrap();
+        assert_eq!(shape(&one), shape(&Expr::number(1.0)));
+        // errors name the token after the comment
+        let error = parse(&tokenize_with_comments(Source::from("print // x\n;")).unwrap()).unwrap_err();
+        assert_eq!(error.to_string(), "Line 2: Syntax error: Expected expression in print statement at \";\"");
+    }
+
+    #[test]
+    fn test_doc_comments() {
+        use crate::tokenize::tokenize_with_comments;
+        let program = "/// Adds one.\n///\n///   a: a number\nfun inc(a) { return a + 1; }\n\
+                       fun plain() {}\n\
+                       /// too far away\n\nfun far() {}\n\
+                       var x = 1; /// about x\nfun after() {}\n\
+                       // not a doc comment\nfun ordinary() {}\n\
+                       /// A point.\nclass P {\n  /// Makes one.\n  init() {}\n  other() {}\n}\n";
+        let ast = parse(&tokenize_with_comments(Source::from(program)).unwrap()).unwrap();
+        let docs: Vec<(&str, Option<&str>)> = ast
+            .top
+            .iter()
+            .chain(ast.top.iter().flat_map(|stmt| stmt.statements()))
+            .filter_map(|stmt| match stmt {
+                Stmt::SFunction { name, doc, .. } | Stmt::SClass { name, doc, .. } => Some((&**name, doc.as_deref())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            docs,
+            [
+                ("inc", Some("Adds one.\n\n  a: a number")),
+                ("plain", None),
+                ("far", None),
+                ("after", None),
+                ("ordinary", None),
+                ("P", Some("A point.")),
+                ("init", Some("Makes one.")),
+                ("other", None),
+            ]
+        );
+    }
 }
@@ -0,0 +1,249 @@
+// Counting what a program spends its time doing, for `lox --profile` and
+// `lox --stats`.
+//
+// The interpreter holds a Profiler when profiling is on and tells it about
+// every statement it executes and every function it calls. Statements carry
+// no line numbers, so they're counted by kind ("print", "if", ...);
+// functions are counted by name, natives included. ExecStats keeps totals
+// instead, plus how deep the program went. Coverage, for `lox --coverage`,
+// keeps which lines of one program ran.
+use std::collections::HashMap;
+use std::io::Write;
+
+use crate::ast::{Expr, Stmt, AST};
+use crate::ast_id::StmtId;
+use crate::reader::Source;
+
+#[derive(Debug, Default)]
+pub struct Profiler {
+    pub statement_counts: HashMap<String, usize>,
+    pub function_calls: HashMap<String, usize>,
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler::default()
+    }
+
+    pub fn record_statement(&mut self, stmt: &Stmt) {
+        *self.statement_counts.entry(statement_kind(stmt).to_string()).or_default() += 1;
+    }
+
+    pub fn record_call(&mut self, name: &str) {
+        // looked up first so a call to a known function doesn't allocate
+        match self.function_calls.get_mut(name) {
+            Some(count) => *count += 1,
+            None => {
+                self.function_calls.insert(name.to_string(), 1);
+            }
+        }
+    }
+
+    // both tables, most frequent first (ties by name)
+    pub fn write_summary(&self, out: &mut dyn Write) -> std::io::Result<()> {
+        write_table(out, "calls", "function", &self.function_calls)?;
+        write_table(out, "statements", "statement", &self.statement_counts)
+    }
+}
+
+fn write_table(out: &mut dyn Write, title: &str, heading: &str, counts: &HashMap<String, usize>) -> std::io::Result<()> {
+    let mut rows: Vec<(&String, &usize)> = counts.iter().collect();
+    rows.sort_by(|(name_a, count_a), (name_b, count_b)| count_b.cmp(count_a).then(name_a.cmp(name_b)));
+    writeln!(out, "{title}")?;
+    writeln!(out, "{:>10}  {heading}", "count")?;
+    for (name, count) in rows {
+        writeln!(out, "{count:>10}  {name}")?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct ExecStats {
+    pub statements: usize,
+    // every expression, and the interesting kinds among them
+    pub expressions: usize,
+    pub binary: usize,
+    pub unary: usize,
+    pub calls: usize,
+    // the most scopes that enclosed a statement (0: only the global scope)
+    pub max_env_depth: usize,
+    // the most Lox function calls active at once
+    pub max_call_depth: usize,
+}
+
+impl ExecStats {
+    pub fn new() -> ExecStats {
+        ExecStats::default()
+    }
+
+    pub fn record_statement(&mut self, env_depth: usize) {
+        self.statements += 1;
+        self.max_env_depth = self.max_env_depth.max(env_depth);
+    }
+
+    pub fn record_expression(&mut self, expr: &Expr) {
+        self.expressions += 1;
+        match expr {
+            Expr::EBinary { .. } => self.binary += 1,
+            Expr::EUnary { .. } => self.unary += 1,
+            Expr::ECall { .. } => self.calls += 1,
+            _ => {}
+        }
+    }
+
+    pub fn record_call_depth(&mut self, depth: usize) {
+        self.max_call_depth = self.max_call_depth.max(depth);
+    }
+}
+
+impl std::fmt::Display for ExecStats {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        writeln!(formatter, "statements executed:   {}", self.statements)?;
+        writeln!(formatter, "expressions evaluated: {}", self.expressions)?;
+        writeln!(formatter, "  binary:              {}", self.binary)?;
+        writeln!(formatter, "  unary:               {}", self.unary)?;
+        writeln!(formatter, "  calls:               {}", self.calls)?;
+        writeln!(formatter, "max scope depth:       {}", self.max_env_depth)?;
+        write!(formatter, "max call depth:        {}", self.max_call_depth)
+    }
+}
+
+// A line of the program can run if a statement starts on it, and has run
+// once any statement starting on it has. Statements are known by id (see
+// ast_id). Another program's ids can be the same, so only statements from
+// the covered program count, and imported code isn't taken for its.
+#[derive(Debug, Default)]
+pub struct Coverage {
+    lines: HashMap<StmtId, usize>,
+    // by line number, counting from 1
+    executed: Vec<bool>,
+    // the number the interpreter gave the program (see
+    // Interpreter::set_coverage)
+    pub(crate) program: usize,
+}
+
+impl Coverage {
+    // for ast, parsed from source, before it runs
+    pub fn new(ast: &AST, source: &Source) -> Coverage {
+        let mut coverage = Coverage::default();
+        let mut statements: Vec<&Stmt> = ast.top.iter().collect();
+        while let Some(stmt) = statements.pop() {
+            let (line, _) = source.line_col(stmt.span().start);
+            coverage.lines.insert(stmt.id(), line);
+            statements.extend(stmt.statements());
+        }
+        let last = coverage.lines.values().max().copied().unwrap_or(0);
+        coverage.executed = vec![false; last + 1];
+        coverage
+    }
+
+    // stmt is from program
+    pub fn record_statement(&mut self, program: usize, stmt: &Stmt) {
+        if program != self.program {
+            return;
+        }
+        if let Some(&line) = self.lines.get(&stmt.id()) {
+            self.executed[line] = true;
+        }
+    }
+
+    // the lines that can run, in order
+    pub fn executable_lines(&self) -> Vec<usize> {
+        let mut lines: Vec<usize> = self.lines.values().copied().collect();
+        lines.sort();
+        lines.dedup();
+        lines
+    }
+
+    // the lines that ran, in order
+    pub fn executed_lines(&self) -> Vec<usize> {
+        (0..self.executed.len()).filter(|&line| self.executed[line]).collect()
+    }
+
+    pub fn has_run(&self, line: usize) -> bool {
+        self.executed.get(line).copied().unwrap_or(false)
+    }
+
+    // how much of what can run did, out of 100
+    pub fn percentage(&self) -> f64 {
+        match self.executable_lines().len() {
+            0 => 100.0,
+            total => 100.0 * self.executed_lines().len() as f64 / total as f64,
+        }
+    }
+}
+
+// the keyword a statement starts with, or "expression"
+fn statement_kind(stmt: &Stmt) -> &'static str {
+    match stmt {
+        Stmt::SPrint { .. } => "print",
+        Stmt::SExpression { .. } => "expression",
+        Stmt::SVarDecl { .. } => "var",
+        Stmt::SBlock { .. } => "block",
+        Stmt::SIf { .. } => "if",
+        Stmt::SFunction { .. } => "fun",
+        Stmt::SReturn { .. } => "return",
+        Stmt::SSwitch { .. } => "switch",
+        Stmt::STryCatch { .. } => "try",
+        Stmt::SThrow { .. } => "throw",
+        Stmt::SImport { .. } => "import",
+        Stmt::SForIn { .. } => "for",
+        Stmt::SWhile { .. } => "while",
+        Stmt::SClass { .. } => "class",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        let mut profiler = Profiler::new();
+        for name in ["f", "len", "f", "g", "f", "g"] {
+            profiler.record_call(name);
+        }
+        profiler.record_statement(&Stmt::print(Expr::nil()));
+        profiler.record_statement(&Stmt::expression(Expr::nil()));
+        profiler.record_statement(&Stmt::print(Expr::nil()));
+        let mut out = Vec::new();
+        profiler.write_summary(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "calls\n     count  function\n         3  f\n         2  g\n         1  len\n\
+             statements\n     count  statement\n         2  print\n         1  expression\n"
+        );
+    }
+
+    #[test]
+    fn test_coverage() {
+        let source = Source::from(
+            "var x = 2;\n\
+             if (x > 1) {\n\
+               print \"big\";\n\
+             } else {\n\
+               print \"small\";\n\
+             }\n\
+             // never called\n\
+             fun f() {\n\
+               return 1;\n\
+             }\n\
+             while (x < 3) x = x + 1;\n",
+        );
+        let ast = crate::parser::parse(&crate::tokenize::tokenize_with_comments(Source::from(source.contents.clone())).unwrap()).unwrap();
+        let coverage = Coverage::new(&ast, &source);
+        let mut interp = crate::evaluate::LoxBuilder::new().build();
+        interp.set_output(Box::new(std::io::sink()));
+        interp.set_coverage(coverage);
+        interp.evaluate(ast).unwrap();
+        let coverage = interp.coverage().unwrap();
+        assert_eq!(coverage.executable_lines(), [1, 2, 3, 4, 5, 8, 9, 11]);
+        // not the else branch, nor the body of the function never called
+        assert_eq!(coverage.executed_lines(), [1, 2, 3, 8, 11]);
+        assert!(!coverage.has_run(5) && !coverage.has_run(7));
+        assert_eq!(coverage.percentage(), 62.5);
+        // statements of another program with the same ids don't count
+        interp.exec("print 1;\nprint 2;\nprint 3;\nprint 4;\nprint 5;\nprint 6;\nprint 7;").unwrap();
+        assert_eq!(interp.coverage().unwrap().executed_lines(), [1, 2, 3, 8, 11]);
+    }
+}
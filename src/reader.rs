@@ -1,36 +1,148 @@
+use std::cell::OnceCell;
+use std::path::PathBuf;
+
+#[derive(Debug)]
 pub struct Source {
     pub contents: String,
+    // where the source came from, for error messages: a filename, "<stdin>"
+    // or "<repl>"
+    pub name: Option<String>,
+    // the directory of the file it was read from, which paths in it are
+    // relative to
+    pub directory: Option<PathBuf>,
+    // byte offset at which each line starts, built when first needed
+    line_starts: OnceCell<Vec<usize>>,
 }
 
 impl Source {
     pub fn from(s: impl Into<String>) -> Source {
-        Source { contents: s.into() }
+        Source { contents: s.into(), name: None, directory: None, line_starts: OnceCell::new() }
+    }
+
+    pub fn named(s: impl Into<String>, name: impl Into<String>) -> Source {
+        Source { contents: s.into(), name: Some(name.into()), directory: None, line_starts: OnceCell::new() }
+    }
+
+    fn line_starts(&self) -> &[usize] {
+        self.line_starts.get_or_init(|| {
+            let newlines = self.contents.match_indices('\n').map(|(n, _)| n + 1);
+            std::iter::once(0).chain(newlines).collect()
+        })
+    }
+
+    // the (line, column) of a byte offset, both counting from 1. Columns
+    // count characters, not bytes. Offsets past the end map to the end, and
+    // offsets inside a character to that character.
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let offset = self.contents.floor_char_boundary(offset);
+        let line = self.line_starts().partition_point(|&start| start <= offset);
+        let start = self.line_starts()[line - 1];
+        let column = self.contents[start..offset].chars().count() + 1;
+        (line, column)
+    }
+
+    // the text of a line (counting from 1) without its line ending, or ""
+    // if there's no such line
+    pub fn line_text(&self, line: usize) -> &str {
+        let starts = self.line_starts();
+        let Some(&start) = line.checked_sub(1).and_then(|n| starts.get(n)) else {
+            return "";
+        };
+        let end = starts.get(line).copied().unwrap_or(self.contents.len());
+        let text = &self.contents[start..end];
+        let text = text.strip_suffix('\n').unwrap_or(text);
+        text.strip_suffix('\r').unwrap_or(text)
     }
 }
 
+// NotFound and PermissionDenied hold the whole message, which names the file
+// when it's known
 #[derive(Debug)]
-pub struct Error {
-    pub msg: String,
+pub enum Error {
+    NotFound(String),
+    PermissionDenied(String),
+    Io(std::io::Error),
 }
 
 impl From<std::io::Error> for Error {
     fn from(e: std::io::Error) -> Error {
-        Error {
-            msg: format!("{}", e),
+        use std::io::ErrorKind;
+        match e.kind() {
+            ErrorKind::NotFound => Error::NotFound(describe(&e)),
+            ErrorKind::PermissionDenied => Error::PermissionDenied(describe(&e)),
+            _ => Error::Io(e),
+        }
+    }
+}
+
+impl Error {
+    // e, from trying to read path
+    pub fn reading(path: &str, e: std::io::Error) -> Error {
+        let message = format!("could not read '{path}': {}", describe(&e));
+        match Error::from(e) {
+            Error::NotFound(_) => Error::NotFound(message),
+            Error::PermissionDenied(_) => Error::PermissionDenied(message),
+            Error::Io(e) => Error::Io(std::io::Error::new(e.kind(), message)),
         }
     }
 }
 
+// what went wrong, without the " (os error 2)" that io::Error adds
+fn describe(e: &std::io::Error) -> String {
+    let message = e.to_string();
+    match message.rfind(" (os error ") {
+        Some(end) => message[..end].to_string(),
+        None => message,
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            Error::NotFound(msg) => write!(formatter, "{msg}. Check the filename."),
+            Error::PermissionDenied(msg) => write!(formatter, "{msg}. Check file permissions."),
+            Error::Io(e) => write!(formatter, "{e}"),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
 pub fn read_source(filename: &str) -> Result<Source, Error> {
-    println!("Reading source");
-    let contents = std::fs::read_to_string(filename)?;
-    Ok(Source { contents })
+    let contents = std::fs::read_to_string(filename).map_err(|e| Error::reading(filename, e))?;
+    let mut source = Source::named(without_bom(contents), filename);
+    source.directory = Some(std::path::Path::new(filename).parent().map(PathBuf::from).unwrap_or_default());
+    Ok(source)
+}
+
+// wasm32 has no files, so run_file and import fail with this there
+#[cfg(target_arch = "wasm32")]
+pub fn read_source(filename: &str) -> Result<Source, Error> {
+    let unsupported = std::io::Error::new(std::io::ErrorKind::Unsupported, "there are no files on wasm32");
+    Err(Error::reading(filename, unsupported))
+}
+
+// the whole of standard input, for "lox -"
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read_stdin() -> Result<Source, Error> {
+    let mut contents = String::new();
+    std::io::Read::read_to_string(&mut std::io::stdin(), &mut contents)?;
+    Ok(Source::named(without_bom(contents), "<stdin>"))
+}
+
+// editors on Windows often start UTF-8 files with a byte order mark
+#[cfg(not(target_arch = "wasm32"))]
+fn without_bom(contents: String) -> String {
+    match contents.strip_prefix('\u{feff}') {
+        Some(rest) => rest.to_string(),
+        None => contents,
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::ast::{AST, Expr, Operator};
+    use crate::ast::{AST, Expr, Operator, Stmt};
     use crate::parser::{parse, Parser};
+    use crate::token::shape;
 
     // helper
     fn parse_string(s: &str) -> AST {
@@ -38,7 +150,7 @@ mod tests {
         use crate::tokenize::tokenize;
         let source = Source::from(s);
         let tokens = tokenize(source).unwrap();
-        parse(tokens).unwrap()
+        parse(&tokens).unwrap()
     }
 
     fn parse_expr_string(s: &str) -> Expr {
@@ -46,7 +158,7 @@ mod tests {
         use crate::tokenize::tokenize;
         let source = Source::from(s);
         let tokens = tokenize(source).unwrap();
-        Parser::new(tokens).parse_expression().unwrap()
+        Parser::new(&tokens.tokens).parse_expression().unwrap()
     }
 
     #[test]
@@ -54,21 +166,116 @@ mod tests {
         assert_eq!(true, true);
     }
 
+    #[test]
+    fn test_read_errors() {
+        use crate::reader::{read_source, Error};
+        use std::io::ErrorKind;
+        assert!(matches!(
+            read_source("no/such/file.lox"),
+            Err(Error::NotFound(_))
+        ));
+        let Err(error) = read_source("no/such/file.lox") else { panic!("expected an error") };
+        assert_eq!(
+            error.to_string(),
+            "could not read 'no/such/file.lox': No such file or directory. Check the filename."
+        );
+        let denied = std::io::Error::from(ErrorKind::PermissionDenied);
+        assert!(matches!(Error::from(denied), Error::PermissionDenied(_)));
+        let other = std::io::Error::from(ErrorKind::InvalidData);
+        assert!(matches!(Error::from(other), Error::Io(_)));
+    }
+
     #[test]
     fn test_primary() {
-        assert_eq!(parse_expr_string("123"), Expr::number("123"));
-        assert_eq!(parse_expr_string("\"hello\""), Expr::string("hello"));
-        assert_eq!(parse_expr_string("(2)"), Expr::grouping(Expr::number("2")));
-        assert_eq!(parse_expr_string("nil"), Expr::nil());
-        assert_eq!(parse_expr_string("true"), Expr::bool(true));
-        assert_eq!(parse_expr_string("false"), Expr::bool(false));
+        assert_eq!(shape(&parse_expr_string("123")), shape(&Expr::number(123.0)));
+        assert_eq!(shape(&parse_expr_string("\"hello\"")), shape(&Expr::string("hello")));
+        assert_eq!(shape(&parse_expr_string("(2)")), shape(&Expr::grouping(Expr::number(2.0))));
+        assert_eq!(shape(&parse_expr_string("nil")), shape(&Expr::nil()));
+        assert_eq!(shape(&parse_expr_string("true")), shape(&Expr::bool(true)));
+        assert_eq!(shape(&parse_expr_string("false")), shape(&Expr::bool(false)));
     }
 
     #[test]
     fn test_binary() {
         assert_eq!(
-            parse_expr_string("1 + 2"),
-            Expr::binary(Expr::number("1"), Operator::OAdd, Expr::number("2"))
+            shape(&parse_expr_string("1 + 2")),
+            shape(&Expr::binary(Expr::number(1.0), Operator::OAdd, Expr::number(2.0), 1))
+        );
+        assert_eq!(
+            shape(&parse_expr_string("a or nil")),
+            shape(&Expr::binary(Expr::variable("a"), Operator::OOr, Expr::nil(), 1))
+        );
+    }
+
+    #[test]
+    fn test_statements() {
+        assert_eq!(
+            shape(&parse_string("print 1; var x = 2;").top),
+            shape(&vec![
+                Stmt::print(Expr::number(1.0)),
+                Stmt::vardecl("x", Some(Expr::number(2.0))),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_bom_and_crlf() {
+        use crate::reader::read_source;
+        let path = std::env::temp_dir().join(format!("lox-bom-{}.lox", std::process::id()));
+        std::fs::write(&path, "\u{feff}var x = 1;\r\nprint x;\r\n").unwrap();
+        let source = read_source(path.to_str().unwrap()).unwrap();
+        assert_eq!(source.contents, "var x = 1;\r\nprint x;\r\n");
+        let tokens = crate::tokenize::tokenize(source).unwrap().tokens;
+        assert_eq!(tokens.last().unwrap().line, 3);
+        assert_eq!(parse_string("var x = 1;\r\nprint x;\r\n").top.len(), 2);
+    }
+
+    #[test]
+    fn test_line_col() {
+        use crate::reader::Source;
+        let source = Source::from("ab\r\ncé\n\nlast");
+        assert_eq!(source.line_col(0), (1, 1));
+        assert_eq!(source.line_col(2), (1, 3)); // the \r
+        assert_eq!(source.line_col(4), (2, 1));
+        assert_eq!(source.line_col(7), (2, 3)); // after the two-byte é
+        assert_eq!(source.line_col(6), (2, 2)); // inside it
+        assert_eq!(source.line_col(8), (3, 1));
+        assert_eq!(source.line_col(9), (4, 1));
+        assert_eq!(source.line_col(100), (4, 5));
+        assert_eq!(source.line_text(1), "ab");
+        assert_eq!(source.line_text(2), "cé");
+        assert_eq!(source.line_text(3), "");
+        assert_eq!(source.line_text(4), "last");
+        assert_eq!(source.line_text(0), "");
+        assert_eq!(source.line_text(5), "");
+        assert_eq!(Source::from("").line_col(0), (1, 1));
+    }
+
+    #[test]
+    fn test_multiple_syntax_errors() {
+        use crate::parser::Error;
+        let tokens = crate::tokenize::tokenize(crate::reader::Source::from(
+            "var = 1;\nprint 2;\nfun f() { print ; var y = 3; }\nprint 4 4;",
+        ))
+        .unwrap();
+        let Err(Error::Multiple(errors)) = parse(&tokens) else { panic!("expected several errors") };
+        let lines: Vec<usize> = errors
+            .iter()
+            .map(|error| match error {
+                Error::SyntaxError { line, .. } => *line,
+                Error::Multiple(_) => panic!("nested errors"),
+            })
+            .collect();
+        assert_eq!(lines, [1, 3, 4]);
+    }
+
+    #[test]
+    fn test_rest_parameter() {
+        assert_eq!(
+            shape(&parse_string("fun f(a, ...rest) {}").top),
+            shape(&vec![Stmt::function("f", vec!["a".into()], Some("rest".into()), vec![])])
         );
+        let tokens = crate::tokenize::tokenize(crate::reader::Source::from("fun f(...rest, a) {}")).unwrap();
+        assert!(parse(&tokens).is_err());
     }
 }
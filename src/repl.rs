@@ -0,0 +1,602 @@
+// The interactive prompt.
+//
+// Lines come from a line editor with history when stdin is a terminal (and
+// the `editor` feature is enabled) and are read plainly from stdin
+// otherwise, so piping a script through stdin keeps working. Either way,
+// inputs that worked are kept in ~/.lox_history for the next session; the
+// line editor also has the ones that didn't for the rest of this one.
+//
+// A session can be recorded to a file (--record) and played back later
+// (--replay), which reads the file as if it were being typed.
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use lox::token::{ScanError, TokenType};
+use lox::{ast, evaluate, lint, parser, reader, tokenize};
+
+use crate::Error;
+use crate::diagnostics::{render, Style};
+
+pub enum Input {
+    Line(String),
+    // Ctrl-C: abandon the current input
+    #[cfg_attr(not(feature = "editor"), allow(dead_code))]
+    Interrupted,
+    Eof,
+}
+
+pub trait LineSource {
+    fn read_line(&mut self, prompt: &str) -> Input;
+
+    // every input, to go back to later in this session
+    fn add_history(&mut self, _entry: &str) {}
+
+    // the inputs that worked, to keep for later sessions
+    fn keep_history(&mut self, _entry: &str) {}
+}
+
+// how many inputs of history are kept between sessions
+const HISTORY_LIMIT: usize = 1000;
+
+// where history is kept, if there's a home directory to keep it in
+fn history_file() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| Path::new(&home).join(".lox_history"))
+}
+
+// the inputs that worked in earlier sessions and this one. It's written
+// back, the last HISTORY_LIMIT of them, when dropped.
+//
+// The file is in the line editor's format: a "#V2" line, then an input a
+// line, with newlines in it written as \n and backslashes as \\. Files
+// without the "#V2" line have an input a line, as written.
+pub struct History {
+    path: PathBuf,
+    entries: Vec<String>,
+}
+
+impl History {
+    // the history in path; there's none the first time round
+    pub fn load(path: PathBuf) -> History {
+        let entries = match std::fs::File::open(&path) {
+            Ok(file) => {
+                let mut lines = BufReader::new(file).lines().map_while(Result::ok).peekable();
+                if lines.next_if(|line| line == "#V2").is_some() {
+                    lines.map(|line| unescape(&line)).collect()
+                } else {
+                    lines.collect()
+                }
+            }
+            Err(_) => Vec::new(),
+        };
+        History { path, entries }
+    }
+
+    // ~/.lox_history
+    pub fn from_home() -> Option<History> {
+        history_file().map(History::load)
+    }
+
+    #[cfg_attr(not(feature = "editor"), allow(dead_code))]
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    pub fn add(&mut self, entry: &str) {
+        self.entries.push(entry.to_string());
+    }
+
+    fn save(&self) -> std::io::Result<()> {
+        let mut out = BufWriter::new(std::fs::File::create(&self.path)?);
+        write!(out, "#V2")?;
+        for entry in &self.entries[self.entries.len().saturating_sub(HISTORY_LIMIT)..] {
+            write!(out, "\n{}", entry.replace('\\', "\\\\").replace('\n', "\\n"))?;
+        }
+        writeln!(out)?;
+        out.flush()
+    }
+}
+
+// a line of a "#V2" history file as the input it stands for
+fn unescape(line: &str) -> String {
+    let mut entry = String::with_capacity(line.len());
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\\', Some('n')) => {
+                entry.push('\n');
+                chars.next();
+            }
+            ('\\', Some('\\')) => {
+                entry.push('\\');
+                chars.next();
+            }
+            _ => entry.push(c),
+        }
+    }
+    entry
+}
+
+impl Drop for History {
+    fn drop(&mut self) {
+        if let Err(e) = self.save() {
+            eprintln!("Could not save history to {}: {e}", self.path.display());
+        }
+    }
+}
+
+// no editing: prompts go to output, lines come from input. History, if
+// there is any, is only kept for later.
+pub struct Plain<R, W> {
+    input: R,
+    output: W,
+    // write each line after its prompt, as though it had been typed
+    echo: bool,
+    history: Option<History>,
+}
+
+impl<R: BufRead, W: Write> Plain<R, W> {
+    pub fn new(input: R, output: W) -> Plain<R, W> {
+        Plain { input, output, echo: false, history: None }
+    }
+
+    // for replaying a recorded session
+    pub fn echoing(input: R, output: W) -> Plain<R, W> {
+        Plain { input, output, echo: true, history: None }
+    }
+
+    pub fn with_history(mut self, history: Option<History>) -> Plain<R, W> {
+        self.history = history;
+        self
+    }
+}
+
+impl<R: BufRead, W: Write> LineSource for Plain<R, W> {
+    fn read_line(&mut self, prompt: &str) -> Input {
+        // a closed stdout shouldn't stop us reading
+        let _ = self.output.write_all(prompt.as_bytes());
+        let _ = self.output.flush();
+        let mut line = String::new();
+        match self.input.read_line(&mut line) {
+            Ok(0) | Err(_) => Input::Eof,
+            Ok(_) => {
+                if self.echo {
+                    let _ = write!(self.output, "{line}");
+                    if !line.ends_with('\n') {
+                        let _ = writeln!(self.output);
+                    }
+                }
+                Input::Line(line)
+            }
+        }
+    }
+
+    fn keep_history(&mut self, entry: &str) {
+        if let Some(history) = &mut self.history {
+            history.add(entry);
+        }
+    }
+}
+
+// The editor's own history has every input of the session, so it can go
+// back to one that didn't work and fix it. Only what worked is kept in
+// history, which is saved when the editor is dropped.
+#[cfg(feature = "editor")]
+pub struct Editor {
+    editor: rustyline::DefaultEditor,
+    history: Option<History>,
+}
+
+#[cfg(feature = "editor")]
+impl Editor {
+    // history is kept in ~/.lox_history
+    pub fn new() -> Option<Editor> {
+        let config = rustyline::Config::builder().max_history_size(HISTORY_LIMIT).ok()?.build();
+        let mut editor = rustyline::DefaultEditor::with_config(config).ok()?;
+        let history = History::from_home();
+        for entry in history.iter().flat_map(History::entries) {
+            let _ = editor.add_history_entry(entry.as_str());
+        }
+        Some(Editor { editor, history })
+    }
+}
+
+#[cfg(feature = "editor")]
+impl LineSource for Editor {
+    fn read_line(&mut self, prompt: &str) -> Input {
+        use rustyline::error::ReadlineError;
+        match self.editor.readline(prompt) {
+            Ok(line) => Input::Line(line + "\n"),
+            Err(ReadlineError::Interrupted) => Input::Interrupted,
+            Err(_) => Input::Eof,
+        }
+    }
+
+    fn add_history(&mut self, entry: &str) {
+        let _ = self.editor.add_history_entry(entry);
+    }
+
+    fn keep_history(&mut self, entry: &str) {
+        if let Some(history) = &mut self.history {
+            history.add(entry);
+        }
+    }
+}
+
+// true if source is the start of something longer: a bracket or a string
+// is still open, or it ends with a doc comment for what comes next
+fn is_incomplete(source: &str) -> bool {
+    match tokenize::tokenize_with_comments(reader::Source::from(source)) {
+        Ok(tokens) => {
+            let mut depth = 0;
+            for token in &tokens.tokens {
+                match token.toktype {
+                    TokenType::TLeftParen | TokenType::TLeftBrace | TokenType::TLeftBracket => depth += 1,
+                    TokenType::TRightParen | TokenType::TRightBrace | TokenType::TRightBracket => depth -= 1,
+                    _ => {}
+                }
+            }
+            let before_eof = tokens.tokens.len().checked_sub(2).map(|n| &tokens.tokens[n].toktype);
+            depth > 0 || before_eof == Some(&TokenType::TComment { doc: true })
+        }
+        Err(e) => e.iter().any(|e| matches!(e, ScanError::UnterminatedString { .. })),
+    }
+}
+
+// one complete piece of input, which may span several lines
+pub fn read_input(lines: &mut dyn LineSource) -> Input {
+    let mut buffer = String::new();
+    loop {
+        let prompt = if buffer.is_empty() { "> " } else { "... " };
+        match lines.read_line(prompt) {
+            // meta-commands are always a single line
+            Input::Line(line) if buffer.is_empty() && line.trim_start().starts_with(':') => {
+                return Input::Line(line);
+            }
+            Input::Line(line) => {
+                buffer.push_str(&line);
+                if !is_incomplete(&buffer) {
+                    return Input::Line(buffer);
+                }
+            }
+            Input::Interrupted => return Input::Interrupted,
+            // run whatever we've got; an unclosed bracket gets reported
+            Input::Eof if !buffer.is_empty() => return Input::Line(buffer),
+            Input::Eof => return Input::Eof,
+        }
+    }
+}
+
+// Read and run input until it runs out or says :quit. Inputs that worked are
+// appended to record, if there is one, so that it can be replayed.
+pub fn run(lines: &mut dyn LineSource, interp: &mut evaluate::Interpreter, style: Style, mut record: Option<&mut dyn Write>) {
+    loop {
+        match read_input(lines) {
+            Input::Line(source) => {
+                if source.trim().is_empty() {
+                    continue;
+                }
+                let mut stdout = std::io::stdout();
+                let reply = handle_line(interp, &source, &mut stdout);
+                // as with the prompt, a closed stdout isn't worth stopping for
+                let _ = stdout.flush();
+                lines.add_history(source.trim_end());
+                if reply.error.is_none() && !reply.quit {
+                    lines.keep_history(source.trim_end());
+                }
+                if reply.quit {
+                    break;
+                }
+                if let Some(record) = record.as_deref_mut()
+                    && let Err(e) = record_input(record, &source, reply.error.as_ref())
+                {
+                    eprintln!("Could not record the session: {e}");
+                }
+                if let Some(e) = reply.error {
+                    crate::report_errors(e, style);
+                }
+            }
+            Input::Interrupted => continue,
+            Input::Eof => break,
+        }
+    }
+}
+
+// what one piece of input did
+pub struct Reply {
+    pub error: Option<Error>,
+    // it was :quit
+    pub quit: bool,
+}
+
+// run one piece of input: a :command, or Lox code. What the command shows,
+// and the value of a lone expression, go to out; `print` writes wherever
+// the interpreter's output is, as it happens.
+pub fn handle_line(interp: &mut evaluate::Interpreter, source: &str, out: &mut dyn Write) -> Reply {
+    if let Some(command) = source.trim().strip_prefix(':') {
+        let quit = matches!(meta_command(command, interp, out), Meta::Quit);
+        return Reply { error: None, quit };
+    }
+    let result = run_line(interp, reader::Source::named(source, "<repl>"), out);
+    Reply { error: result.err(), quit: false }
+}
+
+// An input that worked goes into the record as it was typed. One that didn't
+// is commented out, after the error, so a replay skips it.
+fn record_input(record: &mut dyn Write, source: &str, error: Option<&Error>) -> std::io::Result<()> {
+    match error {
+        None => writeln!(record, "{}", source.strip_suffix('\n').unwrap_or(source))?,
+        Some(error) => {
+            for line in render(error, Style { enabled: false }).lines() {
+                writeln!(record, "// error: {line}")?;
+            }
+            for line in source.lines() {
+                writeln!(record, "// {line}")?;
+            }
+        }
+    }
+    record.flush()
+}
+
+pub enum Meta {
+    Continue,
+    Quit,
+}
+
+const HELP: &str = "\
+:env            show the global variables
+:reset          forget all variables and functions
+:ast <expr>     show how an expression is parsed, without evaluating it
+:tokens <code>  show the tokens in some code
+:doc <name>     show the doc comment of a function or class
+:help           show this help
+:quit           leave the prompt";
+
+// a REPL :command (with the colon stripped), writing any output to out
+pub fn meta_command(command: &str, interp: &mut evaluate::Interpreter, out: &mut dyn Write) -> Meta {
+    let (name, rest) = command.split_once(char::is_whitespace).unwrap_or((command, ""));
+    let result = match name {
+        "env" => interp.dump_env(out),
+        "ast" => match tokenize::tokenize(reader::Source::from(rest)) {
+            Ok(tokens) => match parser::parse_expr(&tokens) {
+                Ok(expr) => writeln!(out, "{}", ast::format_expr(&expr)),
+                Err(e) => writeln!(out, "{e}"),
+            },
+            Err(e) => writeln!(out, "{e}"),
+        },
+        "tokens" => match tokenize::tokenize(reader::Source::from(rest)) {
+            Ok(tokens) => tokens.tokens.iter().try_for_each(|token| writeln!(out, "{token:?}")),
+            Err(e) => writeln!(out, "{e}"),
+        },
+        "doc" => match interp.evaluate_global(&ast::Expr::variable(rest.trim())) {
+            Ok(value) => match value.doc() {
+                Some(doc) => writeln!(out, "{doc}"),
+                None => writeln!(out, "{} has no doc comment", rest.trim()),
+            },
+            Err(e) => writeln!(out, "{e}"),
+        },
+        "reset" => {
+            interp.reset();
+            Ok(())
+        }
+        "help" => writeln!(out, "{HELP}"),
+        "quit" => return Meta::Quit,
+        _ => writeln!(out, "Unknown command :{name}. Try :help."),
+    };
+    // as with the prompt, a closed stdout isn't worth stopping for
+    let _ = result;
+    Meta::Continue
+}
+
+// like run_interp, but a lone expression is echoed back (to out) in its
+// repr form
+fn run_line(interp: &mut evaluate::Interpreter, source: reader::Source, out: &mut dyn Write) -> Result<(), Error> {
+    let tokens = tokenize::tokenize_with_comments(source)?;
+    let ast = parser::parse(&tokens)?;
+    if interp.options().strict_scopes {
+        lint::check_strict(&ast, &tokens)?;
+    }
+    let echo = matches!(&ast.top[..], [ast::Stmt::SExpression { .. }]);
+    let value = interp.evaluate(ast)?;
+    if echo {
+        let _ = writeln!(out, "{}", value.repr());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lox::evaluate::LoxValue;
+
+    fn global(interp: &mut evaluate::Interpreter, name: &str) -> LoxValue {
+        interp.evaluate_global(&ast::Expr::variable(name)).unwrap()
+    }
+
+    // run input through the non-terminal path, returning what was prompted
+    fn run_plain(input: &str, interp: &mut evaluate::Interpreter) -> String {
+        let mut prompts = Vec::new();
+        run(&mut Plain::new(input.as_bytes(), &mut prompts), interp, Style { enabled: false }, None);
+        String::from_utf8(prompts).unwrap()
+    }
+
+    #[test]
+    fn test_plain_input() {
+        let mut interp = evaluate::Interpreter::new();
+        let prompts = run_plain("var a = 1;\n\nvar b = a + 1;\n", &mut interp);
+        assert_eq!(prompts, "> > > > ");
+        assert_eq!(global(&mut interp, "b"), LoxValue::LNumber(2.0));
+    }
+
+    #[test]
+    fn test_history() {
+        let path = std::env::temp_dir().join(format!("lox-history-{}", std::process::id()));
+        let old: Vec<String> = (0..HISTORY_LIMIT).map(|n| format!("print {n};")).collect();
+        std::fs::write(&path, format!("#V2\n{}\nprint \"a\\\\b\";\n", old.join("\n"))).unwrap();
+        let mut interp = evaluate::Interpreter::new();
+        let mut prompts = Vec::new();
+        let mut lines = Plain::new("var a = 1;\nprint nope;\nfun f() {\n}\n:quit\n".as_bytes(), &mut prompts)
+            .with_history(Some(History::load(path.clone())));
+        run(&mut lines, &mut interp, Style { enabled: false }, None);
+        drop(lines);
+        // only what worked is added, and the oldest inputs make way for it
+        let saved = std::fs::read_to_string(&path).unwrap();
+        assert!(saved.starts_with("#V2\nprint 3;\n"));
+        assert!(saved.ends_with("\nprint \"a\\\\b\";\nvar a = 1;\nfun f() {\\n}\n"), "{saved}");
+        let saved = History::load(path.clone());
+        assert_eq!(saved.entries().len(), HISTORY_LIMIT);
+        assert_eq!(saved.entries()[HISTORY_LIMIT - 3..], ["print \"a\\b\";", "var a = 1;", "fun f() {\n}"]);
+        drop(saved);
+        // files without the version line have an input a line
+        std::fs::write(&path, "print 1;\nprint \"a\\\\b\";\n").unwrap();
+        assert_eq!(History::load(path.clone()).entries(), ["print 1;", "print \"a\\\\b\";"]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_continuation() {
+        let mut interp = evaluate::Interpreter::new();
+        let prompts = run_plain("fun f() {\n  return [1,\n 2];\n}\nvar s = \"a\nb\";\n", &mut interp);
+        assert_eq!(prompts, "> ... ... ... > ... > ");
+        assert_eq!(global(&mut interp, "s"), LoxValue::LString("a\nb".into()));
+        let call = ast::Expr::call(ast::Expr::variable("f"), vec![], 1);
+        let LoxValue::LList(list) = interp.evaluate_global(&call).unwrap() else {
+            panic!("expected a list")
+        };
+        assert_eq!(list.borrow().len(), 2);
+    }
+
+    #[test]
+    fn test_input_without_final_newline() {
+        let mut interp = evaluate::Interpreter::new();
+        run_plain("var a = 3;", &mut interp);
+        assert_eq!(global(&mut interp, "a"), LoxValue::LNumber(3.0));
+    }
+
+    fn meta_output(command: &str, interp: &mut evaluate::Interpreter) -> String {
+        let mut out = Vec::new();
+        meta_command(command, interp, &mut out);
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_meta_env() {
+        let mut interp = evaluate::Interpreter::new();
+        assert_eq!(meta_output("env", &mut interp), "");
+        run_plain("var b = \"two\";\nvar a = 1;\n", &mut interp);
+        assert_eq!(meta_output("env", &mut interp), "a = 1\nb = \"two\"\n");
+        assert_eq!(meta_output("reset", &mut interp), "");
+        assert_eq!(meta_output("env", &mut interp), "");
+    }
+
+    #[test]
+    fn test_meta_doc() {
+        let mut interp = evaluate::Interpreter::new();
+        run_plain("/// Says hi.\nfun hi() { print \"hi\"; }\nfun bye() {}\n", &mut interp);
+        assert_eq!(meta_output("doc hi", &mut interp), "Says hi.\n");
+        assert_eq!(meta_output("doc bye", &mut interp), "bye has no doc comment\n");
+        assert_eq!(meta_output("doc nope", &mut interp), "Undefined variable \"nope\"\n");
+    }
+
+    #[test]
+    fn test_meta_commands() {
+        let mut interp = evaluate::Interpreter::new();
+        assert_eq!(meta_output("ast 1 + 2", &mut interp), "(+ 1 2)\n");
+        assert!(meta_output("tokens var", &mut interp).starts_with("Token { toktype: TVar"));
+        assert!(meta_output("help", &mut interp).contains(":quit"));
+        assert_eq!(meta_output("bogus", &mut interp), "Unknown command :bogus. Try :help.\n");
+        assert!(matches!(meta_command("quit", &mut interp, &mut Vec::new()), Meta::Quit));
+    }
+
+    #[test]
+    fn test_quit_stops_reading() {
+        let mut interp = evaluate::Interpreter::new();
+        let prompts = run_plain("var a = 1;\n:quit\nvar a = 2;\n", &mut interp);
+        assert_eq!(prompts, "> > ");
+        assert_eq!(global(&mut interp, "a"), LoxValue::LNumber(1.0));
+    }
+
+    // replays a fixed sequence of inputs
+    struct Scripted(Vec<Input>);
+
+    impl LineSource for Scripted {
+        fn read_line(&mut self, _prompt: &str) -> Input {
+            if self.0.is_empty() { Input::Eof } else { self.0.remove(0) }
+        }
+    }
+
+    #[test]
+    fn test_interrupt_discards_input() {
+        let mut interp = evaluate::Interpreter::new();
+        let mut lines = Scripted(vec![
+            Input::Line("var a = [1,\n".into()),
+            Input::Interrupted,
+            Input::Line("var a = 2;\n".into()),
+        ]);
+        run(&mut lines, &mut interp, Style { enabled: false }, None);
+        assert_eq!(global(&mut interp, "a"), LoxValue::LNumber(2.0));
+    }
+
+    // handle_line, with what it printed and echoed
+    fn handle(interp: &mut evaluate::Interpreter, source: &str) -> (String, Reply) {
+        let output = evaluate::SharedOutput::default();
+        let stdout = interp.set_output(Box::new(output.clone()));
+        let reply = handle_line(interp, source, &mut output.clone());
+        interp.set_output(stdout);
+        (output.take(), reply)
+    }
+
+    #[test]
+    fn test_handle_line() {
+        let mut interp = evaluate::Interpreter::new();
+        let (output, reply) = handle(&mut interp, "var a = 2; print a;\n");
+        assert_eq!((output.as_str(), reply.error.is_none(), reply.quit), ("2\n", true, false));
+        assert_eq!(handle(&mut interp, "a * 3;\n").0, "6\n");
+        assert_eq!(handle(&mut interp, ":env\n").0, "a = 2\n");
+        assert!(handle(&mut interp, ":quit\n").1.quit);
+        let (output, reply) = handle(&mut interp, "print \"before\"; print a / 0;\n");
+        assert_eq!(output, "before\n");
+        assert_eq!(render(&reply.error.unwrap(), Style { enabled: false }), "Line 1: Division by zero");
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let inputs = [
+            "var a = 1;\n",
+            "print a + 1;\n",
+            "print a / 0;\n",
+            "fun f() {\n  return a * 10;\n}\n",
+            "var = 3;\n",
+            "f();\n",
+            ":env\n",
+        ];
+        let mut interp = evaluate::Interpreter::new();
+        let mut record = Vec::new();
+        let mut outputs = String::new();
+        for input in inputs {
+            let (output, reply) = handle(&mut interp, input);
+            outputs.push_str(&output);
+            record_input(&mut record, input, reply.error.as_ref()).unwrap();
+        }
+        let record = String::from_utf8(record).unwrap();
+        assert_eq!(
+            record,
+            "var a = 1;\nprint a + 1;\n// error: Line 1: Division by zero\n// print a / 0;\n\
+             fun f() {\n  return a * 10;\n}\n\
+             // error: Line 1: Syntax error: Expected variable name at \"=\"\n// var = 3;\nf();\n:env\n"
+        );
+
+        // the recording, read back as if typed, prints the same things
+        let mut interp = evaluate::Interpreter::new();
+        let mut echoed = Vec::new();
+        let mut lines = Plain::echoing(record.as_bytes(), &mut echoed);
+        let mut replayed = String::new();
+        while let Input::Line(source) = read_input(&mut lines) {
+            let (output, reply) = handle(&mut interp, &source);
+            assert!(reply.error.is_none());
+            replayed.push_str(&output);
+        }
+        assert_eq!(replayed, outputs);
+        assert_eq!(replayed, "2\n10\na = 1\nf = <fn f>\n");
+        assert!(String::from_utf8(echoed).unwrap().starts_with("> var a = 1;\n> print a + 1;\n> // error:"));
+    }
+}
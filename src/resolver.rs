@@ -0,0 +1,316 @@
+// A pass over the AST, between parsing and evaluation, that finds which
+// declaration each variable refers to.
+//
+// Every scope the evaluator creates (a block, a function call, a for-in
+// iteration, the parts of try and switch) is mirrored here, and variables
+// declared in one are numbered in order. A use of a local variable gets the
+// Slot it lives in, so the evaluator can go straight to it. Any other name,
+// including one a function uses before it's declared in an enclosing local
+// scope, is a global, which the evaluator looks up by name.
+//
+// The Slot is written into the EVariable or EAssign node itself rather than
+// kept in a table by ExprId, so the evaluator finds it without a lookup.
+//
+// Mistakes that parse but can't mean anything, like `this` outside a
+// method, are found here and reported as syntax errors.
+use std::rc::Rc;
+
+use crate::ast::{Expr, Slot, Stmt, AST};
+use crate::parser::Error;
+
+pub fn resolve(ast: &mut AST) -> Result<(), Error> {
+    let mut resolver = Resolver { scopes: Vec::new(), errors: Vec::new(), in_method: false, in_subclass: false };
+    resolver.statements(&mut ast.top);
+    match resolver.errors.len() {
+        0 => Ok(()),
+        1 => Err(resolver.errors.remove(0)),
+        _ => Err(Error::Multiple(resolver.errors)),
+    }
+}
+
+struct Resolver {
+    // the names declared so far in each local scope, innermost last
+    scopes: Vec<Vec<Rc<str>>>,
+    errors: Vec<Error>,
+    // inside a method body, functions nested in it included, where `this`
+    // means something
+    in_method: bool,
+    // inside a method of a class with a superclass, where `super` does
+    in_subclass: bool,
+}
+
+impl Resolver {
+    fn declare(&mut self, name: &Rc<str>) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push(name.clone());
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<Slot> {
+        self.scopes.iter().rev().enumerate().find_map(|(depth, scope)| {
+            let index = scope.iter().rposition(|declared| &**declared == name)?;
+            Some(Slot { depth, index })
+        })
+    }
+
+    // statements in a scope of their own, which starts out holding names
+    fn scope(&mut self, names: Vec<Rc<str>>, statements: &mut [Stmt]) {
+        self.scopes.push(names);
+        self.statements(statements);
+        self.scopes.pop();
+    }
+
+    fn statements(&mut self, statements: &mut [Stmt]) {
+        for stmt in statements {
+            self.statement(stmt);
+        }
+    }
+
+    fn statement(&mut self, stmt: &mut Stmt) {
+        match stmt {
+            Stmt::SPrint { expr, .. } | Stmt::SExpression { expr, .. } | Stmt::SThrow { value: expr, .. } => {
+                self.expression(expr)
+            }
+            Stmt::SVarDecl { name, initializer, .. } => {
+                // `var a = a;` means the a from outside
+                if let Some(initializer) = initializer {
+                    self.expression(initializer);
+                }
+                self.declare(name);
+            }
+            Stmt::SBlock { statements, .. } => self.scope(Vec::new(), statements),
+            Stmt::SIf { condition, then_branch, else_branch, .. } => {
+                self.expression(condition);
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            Stmt::SFunction { name, params, rest, body, .. } => {
+                // declared first, so the function can call itself
+                self.declare(name);
+                let mut names = params.clone();
+                names.extend(rest.clone());
+                // a body that's already shared has been resolved (or is
+                // running), so leave it be
+                if let Some(body) = Rc::get_mut(body) {
+                    self.scope(names, body);
+                }
+            }
+            Stmt::SReturn { value, .. } => {
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+            }
+            Stmt::SSwitch { subject, cases, default, .. } => {
+                self.expression(subject);
+                for (value, body) in cases {
+                    self.expression(value);
+                    self.scope(Vec::new(), body);
+                }
+                if let Some(body) = default {
+                    self.scope(Vec::new(), body);
+                }
+            }
+            Stmt::STryCatch { try_body, catch_var, catch_body, finally_body, .. } => {
+                self.scope(Vec::new(), try_body);
+                self.scope(catch_var.iter().cloned().collect(), catch_body);
+                self.scope(Vec::new(), finally_body);
+            }
+            Stmt::SImport { .. } => {}
+            Stmt::SForIn { var, iterable, body, .. } => {
+                self.expression(iterable);
+                self.scopes.push(vec![var.clone()]);
+                self.statement(body);
+                self.scopes.pop();
+            }
+            Stmt::SWhile { condition, body, .. } => {
+                self.expression(condition);
+                self.statement(body);
+            }
+            Stmt::SClass { name, superclass, methods, .. } => {
+                self.declare(name);
+                if let Some(superclass) = superclass {
+                    self.expression(superclass);
+                    // the superclass is "super" in a scope around the methods
+                    self.scopes.push(vec!["super".into()]);
+                }
+                let in_subclass = std::mem::replace(&mut self.in_subclass, superclass.is_some());
+                for method in methods {
+                    let Stmt::SFunction { params, rest, body, .. } = method else { continue };
+                    let mut names = params.clone();
+                    names.extend(rest.clone());
+                    // binding a method puts `this` in a scope of its own,
+                    // between the class's scope and the call's
+                    self.scopes.push(vec!["this".into()]);
+                    let in_method = std::mem::replace(&mut self.in_method, true);
+                    if let Some(body) = Rc::get_mut(body) {
+                        self.scope(names, body);
+                    }
+                    self.in_method = in_method;
+                    self.scopes.pop();
+                }
+                self.in_subclass = in_subclass;
+                if superclass.is_some() {
+                    self.scopes.pop();
+                }
+            }
+        }
+    }
+
+    fn expression(&mut self, expr: &mut Expr) {
+        match expr {
+            Expr::ENumber { .. } | Expr::EString { .. } | Expr::EBool { .. } | Expr::ENil { .. } => {}
+            Expr::EBinary { left, right, .. } => {
+                self.expression(left);
+                self.expression(right);
+            }
+            Expr::EUnary { right, .. } => self.expression(right),
+            Expr::EGrouping { expr, .. } => self.expression(expr),
+            Expr::EVariable { name, slot, .. } => *slot = self.lookup(name),
+            Expr::EAssign { name, value, slot, .. } => {
+                self.expression(value);
+                *slot = self.lookup(name);
+            }
+            Expr::ECall { callee, args, .. } => {
+                self.expression(callee);
+                args.iter_mut().for_each(|arg| self.expression(arg));
+            }
+            Expr::EList { elements, .. } => elements.iter_mut().for_each(|element| self.expression(element)),
+            Expr::EMap { pairs, .. } => {
+                for (key, value) in pairs {
+                    self.expression(key);
+                    self.expression(value);
+                }
+            }
+            Expr::EGet { object, .. } => self.expression(object),
+            Expr::ESet { object, value, .. } => {
+                self.expression(object);
+                self.expression(value);
+            }
+            Expr::EThis { line, .. } if !self.in_method => {
+                let msg = "Can't use 'this' outside of a method".to_string();
+                self.errors.push(Error::SyntaxError { line: *line, msg });
+            }
+            Expr::EThis { slot, .. } => *slot = self.lookup("this"),
+            Expr::ESuper { line, .. } if !self.in_subclass || !self.in_method => {
+                let msg = match self.in_method {
+                    true => "Can't use 'super' in a class with no superclass",
+                    false => "Can't use 'super' outside of a method",
+                };
+                self.errors.push(Error::SyntaxError { line: *line, msg: msg.to_string() });
+            }
+            Expr::ESuper { slot, .. } => *slot = self.lookup("super"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use crate::reader::Source;
+    use crate::tokenize::tokenize;
+
+    fn resolved(program: &str) -> AST {
+        let mut ast = parse(&tokenize(Source::from(program)).unwrap()).unwrap();
+        resolve(&mut ast).unwrap();
+        ast
+    }
+
+    // the slots of every variable used in print statements, in order
+    fn printed_slots(statements: &[Stmt], slots: &mut Vec<Option<Slot>>) {
+        for stmt in statements {
+            match stmt {
+                Stmt::SPrint { expr: Expr::EVariable { slot, .. }, .. } => slots.push(*slot),
+                Stmt::SBlock { statements, .. } => printed_slots(statements, slots),
+                Stmt::SFunction { body, .. } => printed_slots(body, slots),
+                Stmt::SForIn { body, .. } | Stmt::SWhile { body, .. } => printed_slots(std::slice::from_ref(body), slots),
+                _ => {}
+            }
+        }
+    }
+
+    fn slots(program: &str) -> Vec<Option<Slot>> {
+        let mut slots = Vec::new();
+        printed_slots(&resolved(program).top, &mut slots);
+        slots
+    }
+
+    fn slot(depth: usize, index: usize) -> Option<Slot> {
+        Some(Slot { depth, index })
+    }
+
+    #[test]
+    fn test_globals_unresolved() {
+        assert_eq!(slots("var a = 1; print a; { print a; }"), [None, None]);
+    }
+
+    #[test]
+    fn test_locals() {
+        let program = "
+            fun f(a, b, ...rest) {
+                var c = 1;
+                print c; print rest; print a;
+                { var a = 2; print a; print b; print f; print later; }
+                var later = 3;
+            }";
+        assert_eq!(
+            slots(program),
+            [slot(0, 3), slot(0, 2), slot(0, 0), slot(0, 0), slot(1, 1), None, None]
+        );
+    }
+
+    #[test]
+    fn test_redeclared_and_loops() {
+        let program = "
+            {
+                var x = 1; var x = x; print x;
+                for (i in [1]) { print i; print x; }
+                fun g() {}
+                print g;
+            }";
+        assert_eq!(slots(program), [slot(0, 1), slot(1, 0), slot(2, 1), slot(0, 2)]);
+    }
+
+    #[test]
+    fn test_slots_move_with_the_ast() {
+        let ast = resolved("{ var a = 1; print a; }");
+        let moved = Box::new(vec![ast]);
+        let mut slots = Vec::new();
+        printed_slots(&moved[0].top, &mut slots);
+        assert_eq!(slots, [slot(0, 0)]);
+    }
+
+    #[test]
+    fn test_initializer_sees_outer() {
+        let ast = resolved("{ var a = 1; { var a = a; } }");
+        let Stmt::SBlock { statements, .. } = &ast.top[0] else { panic!("expected a block") };
+        let Stmt::SBlock { statements, .. } = &statements[1] else { panic!("expected a block") };
+        let Stmt::SVarDecl { initializer: Some(Expr::EVariable { slot: resolution, .. }), .. } = &statements[0]
+        else {
+            panic!("expected a declaration")
+        };
+        assert_eq!(*resolution, slot(1, 0));
+    }
+
+    #[test]
+    fn test_this_and_super_outside_methods() {
+        let error = |program: &str| {
+            let mut ast = parse(&tokenize(Source::from(program)).unwrap()).unwrap();
+            resolve(&mut ast).unwrap_err().to_string()
+        };
+        assert_eq!(error("print this;"), "Line 1: Syntax error: Can't use 'this' outside of a method");
+        assert_eq!(
+            error("fun f() {\n  return this;\n}\nthis.x = 1;"),
+            "Line 2: Syntax error: Can't use 'this' outside of a method\nLine 4: Syntax error: Can't use 'this' outside of a method"
+        );
+        assert_eq!(error("print super.m;"), "Line 1: Syntax error: Can't use 'super' outside of a method");
+        assert_eq!(
+            error("class A { m() { return super.m(); } }"),
+            "Line 1: Syntax error: Can't use 'super' in a class with no superclass"
+        );
+        // a function inside a method still has the method's `this`
+        resolved("class A { m() { fun f() { return this; } return f; } }");
+    }
+}
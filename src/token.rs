@@ -0,0 +1,252 @@
+// The tokens both scanners produce, and what goes wrong making them.
+//
+// tokenize is the scanner the interpreter uses; tokenize2 is a rewrite that
+// makes the same Tokens, so the parser works with either.
+use std::rc::Rc;
+
+#[derive(Debug, PartialEq)]
+pub enum TokenType {
+    // single-character tokens
+    TLeftParen,
+    TRightParen,
+    TLeftBrace,
+    TRightBrace,
+    TLeftBracket,
+    TRightBracket,
+    TComma,
+    TDot,
+    TMinus,
+    TPlus,
+    TSemicolon,
+    TColon,
+    TEllipsis,
+    TSlash,
+    TStar,
+    TPercent,
+
+    // One or two character tokens
+    TBang,
+    TBangEqual,
+    TEqual,
+    TEqualEqual,
+    TGreater,
+    TGreaterEqual,
+    TLess,
+    TLessEqual,
+
+    // Literals
+    TIdentifier,
+    TString,
+    TNumber,
+
+    // Keywords
+    TAnd,
+    TClass,
+    TElse,
+    TFalse,
+    TFun,
+    TFor,
+    TIf,
+    TNil,
+    TOr,
+    TPrint,
+    TReturn,
+    TSuper,
+    TThis,
+    TTrue,
+    TVar,
+    TWhile,
+    TSwitch,
+    TCase,
+    TDefault,
+    TTry,
+    TCatch,
+    TFinally,
+    TThrow,
+    TImport,
+    TIn,
+
+    // only made when asked for, by tokenize_with_comments. A doc comment
+    // starts with exactly three slashes.
+    TComment { doc: bool },
+
+    TEof,
+}
+
+use TokenType::*;
+
+impl TokenType {
+    // a reserved word, which can't name anything
+    pub fn is_keyword(&self) -> bool {
+        matches!(
+            self,
+            TAnd | TClass | TElse | TFalse | TFun | TFor | TIf | TNil | TOr | TPrint | TReturn | TSuper | TThis
+                | TTrue | TVar | TWhile | TSwitch | TCase | TDefault | TTry | TCatch | TFinally | TThrow | TImport
+                | TIn
+        )
+    }
+}
+
+// the keyword an identifier's text spells, if it's a reserved word
+pub fn keyword(name: &str) -> Option<TokenType> {
+    Some(match name {
+        "and" => TAnd,
+        "class" => TClass,
+        "else" => TElse,
+        "false" => TFalse,
+        "for" => TFor,
+        "fun" => TFun,
+        "if" => TIf,
+        "nil" => TNil,
+        "or" => TOr,
+        "print" => TPrint,
+        "return" => TReturn,
+        "super" => TSuper,
+        "this" => TThis,
+        "true" => TTrue,
+        "var" => TVar,
+        "while" => TWhile,
+        "switch" => TSwitch,
+        "case" => TCase,
+        "default" => TDefault,
+        "try" => TTry,
+        "catch" => TCatch,
+        "finally" => TFinally,
+        "throw" => TThrow,
+        "import" => TImport,
+        "in" => TIn,
+        _ => return None,
+    })
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Literal {
+    Str(String),
+    Num(f64),
+    None,
+}
+
+// a range of bytes in the source: where a token, or everything a syntax
+// tree node was parsed from, starts and ends
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    // from the start of this span to the end of other
+    pub fn to(self, other: Span) -> Span {
+        Span { start: self.start, end: other.end }
+    }
+
+    pub fn contains(&self, offset: usize) -> bool {
+        self.start <= offset && offset < self.end
+    }
+
+    pub fn encloses(&self, other: Span) -> bool {
+        self.start <= other.start && other.end <= self.end
+    }
+}
+
+// what a token or tree node looks like without its spans and ids, for tests
+// that build what they expect without saying where each part was written
+#[cfg(test)]
+pub(crate) fn shape(value: &impl std::fmt::Debug) -> String {
+    let debug = format!("{value:#?}");
+    let kept = |line: &&str| !["span: ", "id: "].iter().any(|field| line.trim_start().starts_with(field));
+    debug.lines().filter(kept).collect::<Vec<_>>().join("\n")
+}
+
+impl std::fmt::Debug for Span {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(formatter, "{}..{}", self.start, self.end)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Token {
+    pub toktype: TokenType,
+    // shared by every token with the same text, so the parser can keep
+    // names without copying them
+    pub lexeme: Rc<str>,
+    pub literal: Literal,
+    pub line: usize,
+    // where on the line it starts, counting characters from 1
+    pub column: usize,
+    pub span: Span,
+}
+
+impl Token {
+    pub fn new(
+        toktype: TokenType,
+        lexeme: impl Into<Rc<str>>,
+        literal: Literal,
+        line: usize,
+        column: usize,
+    ) -> Token {
+        Token {
+            toktype,
+            lexeme: lexeme.into(),
+            literal,
+            line,
+            column,
+            span: Span::default(),
+        }
+    }
+
+    pub fn with_span(self, span: Span) -> Token {
+        Token { span, ..self }
+    }
+}
+
+#[derive(Debug)]
+pub struct Tokens {
+    pub tokens: Vec<Token>,
+}
+
+#[derive(Debug)]
+pub enum ScanError {
+    UnexpectedCharacter { line: usize, ch: char },
+    UnterminatedString { line: usize },
+}
+#[derive(Debug)]
+pub struct Error(pub(crate) Vec<ScanError>);
+
+// This makes it easier to iterate over all of the scan errors
+impl Error {
+    pub fn iter(&self) -> std::slice::Iter<'_, ScanError> {
+        self.0.iter()
+    }
+}
+
+impl std::fmt::Display for ScanError {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            ScanError::UnexpectedCharacter { line, ch } => {
+                write!(formatter, "Line {line}: Unexpected character {ch:?}")
+            }
+            ScanError::UnterminatedString { line } => {
+                write!(formatter, "Line {line}: Unterminated string")
+            }
+        }
+    }
+}
+
+// one scan error per line
+impl std::fmt::Display for Error {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        for (n, scan_error) in self.iter().enumerate() {
+            if n > 0 {
+                writeln!(formatter)?;
+            }
+            write!(formatter, "{scan_error}")?;
+        }
+        Ok(())
+    }
+}
+
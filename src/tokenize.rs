@@ -1,101 +1,10 @@
-use crate::reader::Source;
-
-#[derive(Debug, PartialEq)]
-pub enum TokenType {
-    // single-character tokens
-    TLeftParen,
-    TRightParen,
-    TLeftBrace,
-    TRightBrace,
-    TComma,
-    TDot,
-    TMinus,
-    TPlus,
-    TSemicolon,
-    TSlash,
-    TStar,
-
-    // One or two character tokens
-    TBang,
-    TBangEqual,
-    TEqual,
-    TEqualEqual,
-    TGreater,
-    TGreaterEqual,
-    TLess,
-    TLessEqual,
-
-    // Literals
-    TIdentifier,
-    TString,
-    TNumber,
-
-    // Keywords
-    TAnd,
-    TClass,
-    TElse,
-    TFalse,
-    TFun,
-    TFor,
-    TIf,
-    TNil,
-    TOr,
-    TPrint,
-    TReturn,
-    TSuper,
-    TThis,
-    TTrue,
-    TVar,
-    TWhile,
-    TEof,
-}
-
-use TokenType::*;
-
-#[derive(Debug, PartialEq)]
-pub enum Literal {
-    Str(String),
-    Num(f64),
-    None,
-}
-
-#[derive(Debug, PartialEq)]
-pub struct Token {
-    pub toktype: TokenType,
-    pub lexeme: String,
-    pub literal: Literal,
-    pub line: usize,
-}
-
-impl Token {
-    pub fn new(
-        toktype: TokenType,
-        lexeme: impl Into<String>,
-        literal: Literal,
-        line: usize,
-    ) -> Token {
-        Token {
-            toktype,
-            lexeme: lexeme.into(),
-            literal,
-            line,
-        }
-    }
-}
-
-#[derive(Debug)]
-pub struct Tokens {
-    pub tokens: Vec<Token>,
-}
-
-#[derive(Debug)]
-pub enum ScanError {
-    UnexpectedCharacter { line: usize, ch: char },
-    UnterminatedString { line: usize },
-}
-#[derive(Debug)]
-pub struct Error (Vec<ScanError>);
+use std::ops::Range;
+use std::rc::Rc;
 
+use crate::intern::StringInterner;
+use crate::reader::Source;
+use crate::token::TokenType::*;
+use crate::token::{keyword, Error, Literal, ScanError, Span, Token, TokenType, Tokens};
 
 struct Scanner {
     // we're converting the input source text into a Vec<char>.
@@ -105,24 +14,53 @@ struct Scanner {
     // naturally with characters. We need to make it easier
     source: Vec<char>,
     tokens: Vec<Token>,
+    // every distinct lexeme seen so far, and the buffer used to look them up
+    lexemes: StringInterner,
+    buffer: String,
     start: usize,
     current: usize,
+    // the line of the next character, and of the token's first
     line: usize,
+    start_line: usize,
+    // the column of the next character, and of the token's first, counting
+    // characters from 1
+    column: usize,
+    start_column: usize,
+    // the byte offset of the next character, and of the token's first
+    offset: usize,
+    start_offset: usize,
     errors: Vec<ScanError>,
+    // make TComment tokens rather than skipping comments
+    comments: bool,
 }
 
 impl Scanner {
     fn new(source: &str) -> Scanner {
         Scanner {
             source: source.chars().collect(),
-            tokens: Vec::new(),
+            // typical code has a token every three or four bytes. Guessing
+            // high wastes a little; guessing low means growing, which copies
+            // every token so far.
+            tokens: Vec::with_capacity(source.len() / 3 + 1),
+            lexemes: StringInterner::new(),
+            buffer: String::new(),
             start: 0,
             current: 0,
             line: 1,
+            start_line: 1,
+            column: 1,
+            start_column: 1,
+            offset: 0,
+            start_offset: 0,
             errors: Vec::new(),
+            comments: false,
         }
     }
 
+    fn with_comments(self, comments: bool) -> Scanner {
+        Scanner { comments, ..self }
+    }
+
     fn error(&mut self, err: ScanError) {
         self.errors.push(err);
     }
@@ -132,15 +70,20 @@ impl Scanner {
     }
 
     fn scan_tokens(mut self) -> Result<Tokens, Error> {
+        self.skip_shebang();
         while !self.is_at_end() {
             self.start = self.current;
+            self.start_line = self.line;
+            self.start_column = self.column;
+            self.start_offset = self.offset;
             self.scan_token();
         }
 
+        let end = Span::new(self.offset, self.offset);
         self.tokens
-            .push(Token::new(TEof, "", Literal::None, self.line));
+            .push(Token::new(TEof, "", Literal::None, self.line, self.column).with_span(end));
 
-        if self.errors.len() == 0 {
+        if self.errors.is_empty() {
             Ok(Tokens {
                 tokens: self.tokens,
             })
@@ -149,9 +92,25 @@ impl Scanner {
         }
     }
 
+    // a first line like "#!/usr/bin/env lox" is for the shell, not for us.
+    // The newline stays, so line numbers still count it.
+    fn skip_shebang(&mut self) {
+        if self.source.starts_with(&['#', '!']) {
+            while !self.is_at_end() && self.peek() != '\n' {
+                self.advance();
+            }
+        }
+    }
+
     fn advance(&mut self) -> char {
         let c = self.source[self.current];
         self.current += 1;
+        self.offset += c.len_utf8();
+        if c == '\n' {
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
         c
     }
 
@@ -162,13 +121,20 @@ impl Scanner {
         if self.source[self.current] != expected {
             return false;
         }
-        self.current += 1;
+        self.advance();
         true
     }
 
-    fn lexeme(&self) -> String {
-        // return the current lexeme as a string
-        self.source[self.start..self.current].iter().collect()
+    // the current lexeme, allocated only the first time it's seen
+    fn lexeme(&mut self) -> Rc<str> {
+        self.buffer.clear();
+        self.buffer.extend(&self.source[self.start..self.current]);
+        self.lexemes.intern(&self.buffer)
+    }
+
+    // the current lexeme's bytes
+    fn span(&self) -> Span {
+        Span::new(self.start_offset, self.offset)
     }
 
     fn add_token(&mut self, toktype: TokenType) {
@@ -177,15 +143,20 @@ impl Scanner {
 
     fn peek(&self) -> char {
         if self.is_at_end() {
-            return '\x00';
+            '\x00'
         } else {
             self.source[self.current]
         }
     }
 
+    fn peek_next(&self) -> char {
+        self.source.get(self.current + 1).copied().unwrap_or('\x00')
+    }
+
     fn add_token_with_literal(&mut self, toktype: TokenType, literal: Literal) {
-        self.tokens
-            .push(Token::new(toktype, self.lexeme(), literal, self.line));
+        let lexeme = self.lexeme();
+        let token = Token::new(toktype, lexeme, literal, self.start_line, self.start_column);
+        self.tokens.push(token.with_span(self.span()));
     }
 
     fn scan_token(&mut self) {
@@ -194,12 +165,24 @@ impl Scanner {
             ')' => self.add_token(TRightParen),
             '{' => self.add_token(TLeftBrace),
             '}' => self.add_token(TRightBrace),
+            '[' => self.add_token(TLeftBracket),
+            ']' => self.add_token(TRightBracket),
             ',' => self.add_token(TComma),
-            '.' => self.add_token(TDot),
+            '.' => {
+                if self.peek() == '.' && self.peek_next() == '.' {
+                    self.advance();
+                    self.advance();
+                    self.add_token(TEllipsis)
+                } else {
+                    self.add_token(TDot)
+                }
+            }
             '-' => self.add_token(TMinus),
             '+' => self.add_token(TPlus),
             ';' => self.add_token(TSemicolon),
+            ':' => self.add_token(TColon),
             '*' => self.add_token(TStar),
+            '%' => self.add_token(TPercent),
             '!' => {
                 let toktype = if self.matches('=') {
                     TBangEqual
@@ -238,6 +221,10 @@ impl Scanner {
                     while self.peek() != '\n' && !self.is_at_end() {
                         self.advance();
                     }
+                    if self.comments {
+                        let slashes = self.source[self.start..self.current].iter().take_while(|&&c| c == '/').count();
+                        self.add_token(TComment { doc: slashes == 3 });
+                    }
                 } else {
                     self.add_token(TSlash);
                 }
@@ -246,12 +233,12 @@ impl Scanner {
             ' ' | '\r' | '\t' => {}
             '\n' => self.line += 1,
             '"' => self.string(),
-            c if c.is_digit(10) => {
+            c if c.is_ascii_digit() => {
                 self.number();
             }
             c if c.is_alphabetic() => self.identifier(),
             e => {
-                self.errors.push(ScanError::UnexpectedCharacter {line: self.line, ch: e});
+                self.error(ScanError::UnexpectedCharacter {line: self.line, ch: e});
             }
         }
     }
@@ -264,7 +251,8 @@ impl Scanner {
             self.advance();
         }
         if self.is_at_end() {
-            todo!("Unterminated string")
+            self.error(ScanError::UnterminatedString { line: self.line });
+            return;
         }
         self.advance();
         let value: String = self.source[self.start + 1..self.current - 1]
@@ -273,15 +261,27 @@ impl Scanner {
         self.add_token_with_literal(TString, Literal::Str(value));
     }
     fn number(&mut self) {
-        while self.peek().is_digit(10) {
+        while self.peek().is_ascii_digit() {
             self.advance();
         }
         if self.peek() == '.' {
             self.advance();
-            while self.peek().is_digit(10) {
+            while self.peek().is_ascii_digit() {
                 self.advance();
             }
         }
+        // an exponent, if digits follow: 1e21, 1.5E-7
+        if matches!(self.peek(), 'e' | 'E') {
+            let sign = usize::from(matches!(self.peek_next(), '+' | '-'));
+            if self.source.get(self.current + 1 + sign).is_some_and(char::is_ascii_digit) {
+                for _ in 0..=sign {
+                    self.advance();
+                }
+                while self.peek().is_ascii_digit() {
+                    self.advance();
+                }
+            }
+        }
         let literal = Literal::Num(self.lexeme().parse().unwrap());
         self.add_token_with_literal(TNumber, literal);
     }
@@ -290,39 +290,112 @@ impl Scanner {
         while self.peek().is_alphanumeric() || self.peek() == '_' {
             self.advance();
         }
-        // also HashMap is a way to coup with it
-        let toktype = match &self.lexeme()[..] {
-            "and" => TAnd,
-            "class" => TClass,
-            "else" => TElse,
-            "false" => TFalse,
-            "for" => TFor,
-            "fun" => TFun,
-            "if" => TIf,
-            "nil" => TNil,
-            "or" => TOr,
-            "print" => TPrint,
-            "return" => TReturn,
-            "super" => TSuper,
-            "this" => TThis,
-            "true" => TTrue,
-            "var" => TVar,
-            "while" => TWhile,
-            _ => TIdentifier,
-        };
-        self.add_token(toktype);
+        let lexeme = self.lexeme();
+        let toktype = keyword(&lexeme).unwrap_or(TIdentifier);
+        let token = Token::new(toktype, lexeme, Literal::None, self.start_line, self.start_column);
+        self.tokens.push(token.with_span(self.span()));
     }
 }
 
 pub fn tokenize(source: Source) -> Result<Tokens, Error> {
-    println!("Tokenizing");
-
     Scanner::new(&source.contents).scan_tokens()
 }
 
+// tokenize, keeping comments as TComment tokens for tools that need them.
+// The parser skips them.
+pub fn tokenize_with_comments(source: Source) -> Result<Tokens, Error> {
+    Scanner::new(&source.contents).with_comments(true).scan_tokens()
+}
+
+// what a piece of source text is, for syntax highlighting
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Class {
+    Keyword,
+    Identifier,
+    Number,
+    String,
+    Operator,
+    Comment,
+    Whitespace,
+    Error,
+}
+
+impl std::fmt::Display for Class {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        let name = match self {
+            Class::Keyword => "keyword",
+            Class::Identifier => "identifier",
+            Class::Number => "number",
+            Class::String => "string",
+            Class::Operator => "operator",
+            Class::Comment => "comment",
+            Class::Whitespace => "whitespace",
+            Class::Error => "error",
+        };
+        write!(formatter, "{name}")
+    }
+}
+
+impl From<&TokenType> for Class {
+    fn from(toktype: &TokenType) -> Class {
+        match toktype {
+            TIdentifier => Class::Identifier,
+            TString => Class::String,
+            TNumber => Class::Number,
+            TComment { .. } => Class::Comment,
+            toktype if toktype.is_keyword() => Class::Keyword,
+            // punctuation counts as an operator too
+            _ => Class::Operator,
+        }
+    }
+}
+
+// Split source into labelled byte ranges which together cover all of it.
+// Nothing is thrown away: comments and whitespace get spans of their own,
+// and anything that doesn't scan is labelled Class::Error.
+pub fn classify(source: &str) -> Vec<(Range<usize>, Class)> {
+    // byte offset of each char, and of the end
+    let offsets: Vec<usize> = source
+        .char_indices()
+        .map(|(offset, _)| offset)
+        .chain(std::iter::once(source.len()))
+        .collect();
+    let mut scanner = Scanner::new(source);
+    let mut spans: Vec<(Range<usize>, Class)> = Vec::new();
+    scanner.skip_shebang();
+    if scanner.current > 0 {
+        spans.push((0..offsets[scanner.current], Class::Comment));
+    }
+    while !scanner.is_at_end() {
+        scanner.start = scanner.current;
+        scanner.start_line = scanner.line;
+        scanner.start_column = scanner.column;
+        scanner.start_offset = scanner.offset;
+        let (tokens, errors) = (scanner.tokens.len(), scanner.errors.len());
+        scanner.scan_token();
+        let class = if scanner.errors.len() > errors {
+            Class::Error
+        } else if let Some(token) = scanner.tokens.get(tokens) {
+            Class::from(&token.toktype)
+        } else if scanner.source[scanner.start] == '/' {
+            Class::Comment
+        } else {
+            Class::Whitespace
+        };
+        let range = offsets[scanner.start]..offsets[scanner.current];
+        // a run of whitespace is one span, not one per character
+        match spans.last_mut() {
+            Some((last, Class::Whitespace)) if class == Class::Whitespace => last.end = range.end,
+            _ => spans.push((range, class)),
+        }
+    }
+    spans
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::token::shape;
 
     #[test]
     fn its_alive() {
@@ -334,20 +407,20 @@ mod tests {
         let scanner = Scanner::new("(){},.-+;*");
         let tokens = scanner.scan_tokens();
         assert_eq!(
-            tokens.unwrap().tokens,
-            vec![
-                Token::new(TLeftParen, "(", Literal::None, 1),
-                Token::new(TRightParen, ")", Literal::None, 1),
-                Token::new(TLeftBrace, "{", Literal::None, 1),
-                Token::new(TRightBrace, "}", Literal::None, 1),
-                Token::new(TComma, ",", Literal::None, 1),
-                Token::new(TDot, ".", Literal::None, 1),
-                Token::new(TMinus, "-", Literal::None, 1),
-                Token::new(TPlus, "+", Literal::None, 1),
-                Token::new(TSemicolon, ";", Literal::None, 1),
-                Token::new(TStar, "*", Literal::None, 1),
-                Token::new(TEof, "", Literal::None, 1),
-            ]
+            shape(&tokens.unwrap().tokens),
+            shape(&vec![
+                Token::new(TLeftParen, "(", Literal::None, 1, 1),
+                Token::new(TRightParen, ")", Literal::None, 1, 2),
+                Token::new(TLeftBrace, "{", Literal::None, 1, 3),
+                Token::new(TRightBrace, "}", Literal::None, 1, 4),
+                Token::new(TComma, ",", Literal::None, 1, 5),
+                Token::new(TDot, ".", Literal::None, 1, 6),
+                Token::new(TMinus, "-", Literal::None, 1, 7),
+                Token::new(TPlus, "+", Literal::None, 1, 8),
+                Token::new(TSemicolon, ";", Literal::None, 1, 9),
+                Token::new(TStar, "*", Literal::None, 1, 10),
+                Token::new(TEof, "", Literal::None, 1, 11),
+            ])
         );
     }
 
@@ -356,18 +429,18 @@ mod tests {
         let scanner = Scanner::new("!  !=    < <=  > >= ==    =");
         let tokens = scanner.scan_tokens();
         assert_eq!(
-            tokens.unwrap().tokens,
-            vec![
-                Token::new(TBang, "!", Literal::None, 1),
-                Token::new(TBangEqual, "!=", Literal::None, 1),
-                Token::new(TLess, "<", Literal::None, 1),
-                Token::new(TLessEqual, "<=", Literal::None, 1),
-                Token::new(TGreater, ">", Literal::None, 1),
-                Token::new(TGreaterEqual, ">=", Literal::None, 1),
-                Token::new(TEqualEqual, "==", Literal::None, 1),
-                Token::new(TEqual, "=", Literal::None, 1),
-                Token::new(TEof, "", Literal::None, 1),
-            ]
+            shape(&tokens.unwrap().tokens),
+            shape(&vec![
+                Token::new(TBang, "!", Literal::None, 1, 1),
+                Token::new(TBangEqual, "!=", Literal::None, 1, 4),
+                Token::new(TLess, "<", Literal::None, 1, 10),
+                Token::new(TLessEqual, "<=", Literal::None, 1, 12),
+                Token::new(TGreater, ">", Literal::None, 1, 16),
+                Token::new(TGreaterEqual, ">=", Literal::None, 1, 18),
+                Token::new(TEqualEqual, "==", Literal::None, 1, 21),
+                Token::new(TEqual, "=", Literal::None, 1, 27),
+                Token::new(TEof, "", Literal::None, 1, 28),
+            ])
         );
     }
 
@@ -376,22 +449,24 @@ mod tests {
         let scanner = Scanner::new("\"hello\" \"world\"");
         let tokens = scanner.scan_tokens();
         assert_eq!(
-            tokens.unwrap().tokens,
-            vec![
+            shape(&tokens.unwrap().tokens),
+            shape(&vec![
                 Token::new(
                     TString,
                     "\"hello\"",
                     Literal::Str("hello".to_string()),
+                    1,
                     1
                 ),
                 Token::new(
                     TString,
                     "\"world\"",
                     Literal::Str("world".to_string()),
-                    1
+                    1,
+                    9
                 ),
-                Token::new(TEof, "", Literal::None, 1),
-            ]
+                Token::new(TEof, "", Literal::None, 1, 16),
+            ])
         );
     }
 
@@ -400,13 +475,26 @@ mod tests {
         let scanner = Scanner::new("12345 123.45");
         let tokens = scanner.scan_tokens();
         assert_eq!(
-            tokens.unwrap().tokens,
-            vec![
-                Token::new(TNumber, "12345", Literal::Num(12345.0), 1),
-                Token::new(TNumber, "123.45", Literal::Num(123.45), 1),
-                Token::new(TEof, "", Literal::None, 1),
-            ]
+            shape(&tokens.unwrap().tokens),
+            shape(&vec![
+                Token::new(TNumber, "12345", Literal::Num(12345.0), 1, 1),
+                Token::new(TNumber, "123.45", Literal::Num(123.45), 1, 7),
+                Token::new(TEof, "", Literal::None, 1, 13),
+            ])
         );
+        let scanner = Scanner::new("1e21 1.5E-7 2e+3 3e e-1 4ex");
+        let types: Vec<(TokenType, String)> = scanner
+            .scan_tokens()
+            .unwrap()
+            .tokens
+            .into_iter()
+            .map(|token| (token.toktype, token.lexeme.to_string()))
+            .collect();
+        let expected = [
+            (TNumber, "1e21"), (TNumber, "1.5E-7"), (TNumber, "2e+3"), (TNumber, "3"), (TIdentifier, "e"),
+            (TIdentifier, "e"), (TMinus, "-"), (TNumber, "1"), (TNumber, "4"), (TIdentifier, "ex"), (TEof, ""),
+        ];
+        assert_eq!(types, expected.map(|(toktype, lexeme)| (toktype, lexeme.to_string())));
     }
 
     #[test]
@@ -414,13 +502,13 @@ mod tests {
         let scanner = Scanner::new("abc def123 ab_cd");
         let tokens = scanner.scan_tokens();
         assert_eq!(
-            tokens.unwrap().tokens,
-            vec![
-                Token::new(TIdentifier, "abc", Literal::None, 1),
-                Token::new(TIdentifier, "def123", Literal::None, 1),
-                Token::new(TIdentifier, "ab_cd", Literal::None, 1),
-                Token::new(TEof, "", Literal::None, 1),
-            ]
+            shape(&tokens.unwrap().tokens),
+            shape(&vec![
+                Token::new(TIdentifier, "abc", Literal::None, 1, 1),
+                Token::new(TIdentifier, "def123", Literal::None, 1, 5),
+                Token::new(TIdentifier, "ab_cd", Literal::None, 1, 12),
+                Token::new(TEof, "", Literal::None, 1, 17),
+            ])
         );
     }
 
@@ -431,26 +519,139 @@ mod tests {
         );
         let tokens = scanner.scan_tokens();
         assert_eq!(
-            tokens.unwrap().tokens,
+            shape(&tokens.unwrap().tokens),
+            shape(&vec![
+                Token::new(TAnd, "and", Literal::None, 1, 1),
+                Token::new(TClass, "class", Literal::None, 1, 5),
+                Token::new(TElse, "else", Literal::None, 1, 11),
+                Token::new(TFalse, "false", Literal::None, 1, 16),
+                Token::new(TFor, "for", Literal::None, 1, 22),
+                Token::new(TFun, "fun", Literal::None, 1, 26),
+                Token::new(TIf, "if", Literal::None, 1, 30),
+                Token::new(TNil, "nil", Literal::None, 1, 33),
+                Token::new(TOr, "or", Literal::None, 1, 37),
+                Token::new(TPrint, "print", Literal::None, 1, 40),
+                Token::new(TReturn, "return", Literal::None, 1, 46),
+                Token::new(TSuper, "super", Literal::None, 1, 53),
+                Token::new(TThis, "this", Literal::None, 1, 59),
+                Token::new(TTrue, "true", Literal::None, 1, 64),
+                Token::new(TVar, "var", Literal::None, 1, 69),
+                Token::new(TWhile, "while", Literal::None, 1, 73),
+                Token::new(TEof, "", Literal::None, 1, 78),
+            ])
+        );
+    }
+
+    #[test]
+    fn columns() {
+        // counted in characters, from 1 on every line
+        let tokens = Scanner::new("var é =\n\t\"a\nb\" + ...x; // c\ny").scan_tokens().unwrap().tokens;
+        let columns: Vec<(usize, usize)> = tokens.iter().map(|token| (token.line, token.column)).collect();
+        assert_eq!(columns, [(1, 1), (1, 5), (1, 7), (2, 2), (3, 4), (3, 6), (3, 9), (3, 10), (4, 1), (4, 2)]);
+    }
+
+    #[test]
+    fn crlf_lines() {
+        let tokens = Scanner::new("var\r\nx\r\n\"a\r\nb\" y").scan_tokens().unwrap().tokens;
+        assert_eq!(
+            shape(&tokens),
+            shape(&vec![
+                Token::new(TVar, "var", Literal::None, 1, 1),
+                Token::new(TIdentifier, "x", Literal::None, 2, 1),
+                Token::new(TString, "\"a\r\nb\"", Literal::Str("a\r\nb".into()), 3, 1),
+                Token::new(TIdentifier, "y", Literal::None, 4, 4),
+                Token::new(TEof, "", Literal::None, 4, 5),
+            ])
+        );
+    }
+
+    #[test]
+    fn shared_lexemes() {
+        let tokens = Scanner::new("a + a + b").scan_tokens().unwrap().tokens;
+        assert!(Rc::ptr_eq(&tokens[0].lexeme, &tokens[2].lexeme));
+        assert!(Rc::ptr_eq(&tokens[1].lexeme, &tokens[3].lexeme));
+        assert!(!Rc::ptr_eq(&tokens[0].lexeme, &tokens[4].lexeme));
+    }
+
+    #[test]
+    fn shebang() {
+        let tokens = Scanner::new("#!/usr/bin/env lox\nprint 1;").scan_tokens().unwrap().tokens;
+        assert_eq!(shape(&tokens[0]), shape(&Token::new(TPrint, "print", Literal::None, 2, 1)));
+        assert_eq!(tokens[0].span, Span::new(19, 24));
+        assert_eq!(tokens.len(), 4);
+        // only on the first line
+        assert!(Scanner::new("print 1;\n#!lox").scan_tokens().is_err());
+    }
+
+    #[test]
+    fn classify_spans() {
+        let source = "iffy = \"a // b\"; // done\nif (é) x$";
+        let spans = classify(source);
+        let labelled: Vec<(&str, Class)> =
+            spans.iter().map(|(range, class)| (&source[range.clone()], *class)).collect();
+        assert_eq!(
+            labelled,
             vec![
-                Token::new(TAnd, "and", Literal::None, 1),
-                Token::new(TClass, "class", Literal::None, 1),
-                Token::new(TElse, "else", Literal::None, 1),
-                Token::new(TFalse, "false", Literal::None, 1),
-                Token::new(TFor, "for", Literal::None, 1),
-                Token::new(TFun, "fun", Literal::None, 1),
-                Token::new(TIf, "if", Literal::None, 1),
-                Token::new(TNil, "nil", Literal::None, 1),
-                Token::new(TOr, "or", Literal::None, 1),
-                Token::new(TPrint, "print", Literal::None, 1),
-                Token::new(TReturn, "return", Literal::None, 1),
-                Token::new(TSuper, "super", Literal::None, 1),
-                Token::new(TThis, "this", Literal::None, 1),
-                Token::new(TTrue, "true", Literal::None, 1),
-                Token::new(TVar, "var", Literal::None, 1),
-                Token::new(TWhile, "while", Literal::None, 1),
-                Token::new(TEof, "", Literal::None, 1),
+                ("iffy", Class::Identifier),
+                (" ", Class::Whitespace),
+                ("=", Class::Operator),
+                (" ", Class::Whitespace),
+                ("\"a // b\"", Class::String),
+                (";", Class::Operator),
+                (" ", Class::Whitespace),
+                ("// done", Class::Comment),
+                ("\n", Class::Whitespace),
+                ("if", Class::Keyword),
+                (" ", Class::Whitespace),
+                ("(", Class::Operator),
+                ("é", Class::Identifier),
+                (")", Class::Operator),
+                (" ", Class::Whitespace),
+                ("x", Class::Identifier),
+                ("$", Class::Error),
+            ]
+        );
+        // the spans cover everything, even an unterminated string
+        let spans = classify("#!lox\n\"open");
+        assert_eq!(spans, vec![(0..5, Class::Comment), (5..6, Class::Whitespace), (6..11, Class::Error)]);
+    }
+
+    #[test]
+    fn switch_keywords() {
+        let scanner = Scanner::new("switch case default :");
+        let tokens = scanner.scan_tokens();
+        assert_eq!(
+            shape(&tokens.unwrap().tokens),
+            shape(&vec![
+                Token::new(TSwitch, "switch", Literal::None, 1, 1),
+                Token::new(TCase, "case", Literal::None, 1, 8),
+                Token::new(TDefault, "default", Literal::None, 1, 13),
+                Token::new(TColon, ":", Literal::None, 1, 21),
+                Token::new(TEof, "", Literal::None, 1, 22),
+            ])
+        );
+    }
+
+    #[test]
+    fn comments() {
+        let source = "/// adds\nvar x = 1; // one\n//// rule\n";
+        let tokens = tokenize_with_comments(Source::from(source)).unwrap().tokens;
+        let comments: Vec<(&TokenType, &str, &str)> = tokens
+            .iter()
+            .filter(|token| matches!(token.toktype, TComment { .. }))
+            .map(|token| (&token.toktype, &*token.lexeme, &source[token.span.start..token.span.end]))
+            .collect();
+        assert_eq!(
+            comments,
+            [
+                (&TComment { doc: true }, "/// adds", "/// adds"),
+                (&TComment { doc: false }, "// one", "// one"),
+                (&TComment { doc: false }, "//// rule", "//// rule"),
             ]
         );
+        assert_eq!(tokens[0].line, 1);
+        assert_eq!((tokens[6].line, tokens[6].column), (2, 12));
+        // the default is to skip them
+        assert_eq!(tokenize(Source::from(source)).unwrap().tokens.len(), tokens.len() - 3);
     }
 }
@@ -1,99 +1,11 @@
 // tokenize2.rs
 //
-// A Re-envisioned tokenizer
+// A Re-envisioned tokenizer: each scan_ function tries one kind of token at
+// the start of the input and gives back its byte range. It makes the same
+// Tokens as tokenize, except where first_difference says otherwise.
 use crate::reader::Source;
-
-#[derive(Debug, PartialEq)]
-pub enum TokenType {
-    // Single-character tokens
-    TLeftParen,
-    TRightParen,
-    TLeftBrace,
-    TRightBrace,
-    TComma,
-    TDot,
-    TMinus,
-    TPlus,
-    TSemiColon,
-    TSlash,
-    TStar,
-
-    // One or two character tokens
-    TBang,
-    TBangEqual,
-    TEqual,
-    TEqualEqual,
-    TGreater,
-    TGreaterEqual,
-    TLess,
-    TLessEqual,
-
-    // Literals
-    TIdentifier,
-    TString,
-    TNumber,
-
-    // Keywords
-    TAnd,
-    TClass,
-    TElse,
-    TFalse,
-    TFun,
-    TFor,
-    TIf,
-    TNil,
-    TOr,
-    TPrint,
-    TReturn,
-    TSuper,
-    TThis,
-    TTrue,
-    TVar,
-    TWhile,
-
-    TIgnore,
-    TEof,
-}
-
-use TokenType::*;
-
-#[derive(Debug, PartialEq)]
-pub struct Token {
-    pub toktype: TokenType,
-    pub lexeme: String,
-    pub line: usize,
-}
-
-impl Token {
-    pub fn new(toktype: TokenType, lexeme: impl Into<String>, line: usize) -> Token {
-        Token {
-            toktype,
-            lexeme: lexeme.into(),
-            line,
-        }
-    }
-}
-
-#[derive(Debug)]
-pub struct Tokens {
-    pub tokens: Vec<Token>,
-}
-
-#[derive(Debug)]
-pub enum ScanError {
-    UnexpectedCharacter { line: usize, ch: char },
-    UnterminatedString { line: usize },
-}
-
-#[derive(Debug)]
-pub struct Error(Vec<ScanError>);
-
-// This makes it easier to iterate over all of the scan errors
-impl Error {
-    pub fn iter(&self) -> std::slice::Iter<'_, ScanError> {
-        self.0.iter()
-    }
-}
+use crate::token::TokenType::*;
+use crate::token::{keyword, Error, Literal, ScanError, Span, Token, TokenType, Tokens};
 
 use std::iter::Peekable;
 use std::str::CharIndices;
@@ -101,10 +13,22 @@ use std::ops::Range;
 
 type Chars<'a> = Peekable<CharIndices<'a>>;
 
+// what a scan_ function found. Whitespace and comments are scanned like
+// tokens so their newlines are counted, but TIgnore never gets out of
+// scan_tokens; nor does an unterminated string, which is reported instead.
+#[derive(Debug, PartialEq)]
+enum Scanned {
+    Kind(TokenType),
+    TIgnore,
+    Unterminated,
+}
+
+use Scanned::{Kind, TIgnore, Unterminated};
+
 fn accept(chars: &mut Chars, toktype: TokenType, start: usize) ->
-Option<(TokenType, Range<usize>)> {
+Option<(Scanned, Range<usize>)> {
     let (n, ch) = chars.next()?;
-    Some((toktype, start..n+1))
+    Some((Kind(toktype), start..n + ch.len_utf8()))
 }
 
 fn peek(chars: &mut Chars, ch: char) -> bool {
@@ -115,64 +39,151 @@ fn peek(chars: &mut Chars, ch: char) -> bool {
     }
 }
 
+// consume characters while they pass test, returning where they stop
+fn take_while(chars: &mut Chars, mut end: usize, test: impl Fn(char) -> bool) -> usize {
+    while let Some(&(n, ch)) = chars.peek() {
+        if !test(ch) {
+            break;
+        }
+        end = n + ch.len_utf8();
+        chars.next();
+    }
+    end
+}
+
 fn scan_tokens(s: String) -> Result<Tokens, Error> {
     let mut chars = s.char_indices().peekable();
     let mut result = Vec::new();
+    let mut errors = Vec::new();
     let mut line = 1;
-    while let Some((toktype, range)) = scan_token(&mut chars) {
-        if toktype != TIgnore {
-            result.push(Token::new(toktype, &s[range], line));
+    // where the line the next token is on starts, for its column
+    let mut line_start = 0;
+    // a first line like "#!/usr/bin/env lox" is for the shell
+    if s.starts_with("#!") {
+        take_while(&mut chars, 0, |ch| ch != '\n');
+    }
+    while chars.peek().is_some() {
+        let Some((scanned, range)) = scan_token(&mut chars) else {
+            // nothing starts with this character
+            let (_, ch) = chars.next().unwrap();
+            errors.push(ScanError::UnexpectedCharacter { line, ch });
+            continue;
+        };
+        let lexeme = &s[range.clone()];
+        // tokens start on the line they begin on; only '\n' counts, so
+        // "\r\n" is one line
+        let lines = lexeme.matches('\n').count();
+        match scanned {
+            Kind(toktype) => {
+                let toktype = match toktype {
+                    TIdentifier => keyword(lexeme).unwrap_or(TIdentifier),
+                    t => t,
+                };
+                let literal = literal(&toktype, lexeme);
+                let column = s[line_start..range.start].chars().count() + 1;
+                let span = Span::new(range.start, range.end);
+                result.push(Token::new(toktype, lexeme, literal, line, column).with_span(span));
+            }
+            TIgnore => {}
+            Unterminated => errors.push(ScanError::UnterminatedString { line: line + lines }),
+        }
+        line += lines;
+        if let Some(newline) = lexeme.rfind('\n') {
+            line_start = range.start + newline + 1;
         }
     }
-    result.push(Token::new(TEof, "", line));
-    Ok(Tokens { tokens: result })
+    let column = s[line_start..].chars().count() + 1;
+    let end = Span::new(s.len(), s.len());
+    result.push(Token::new(TEof, "", Literal::None, line, column).with_span(end));
+    if errors.is_empty() {
+        Ok(Tokens { tokens: result })
+    } else {
+        Err(Error(errors))
+    }
+}
+
+// the value a string or number token stands for, as tokenize gives it
+fn literal(toktype: &TokenType, lexeme: &str) -> Literal {
+    match toktype {
+        TString => Literal::Str(lexeme[1..lexeme.len() - 1].to_string()),
+        TNumber => Literal::Num(lexeme.parse().unwrap()),
+        _ => Literal::None,
+    }
 }
 
-fn scan_token(chars : &mut Chars) -> Option<(TokenType, Range<usize>)> {
+fn scan_token(chars : &mut Chars) -> Option<(Scanned, Range<usize>)> {
     scan_simple_symbol(chars)
         .or_else(|| scan_compare_symbol(chars))
+        .or_else(|| scan_slash(chars))
+        .or_else(|| scan_dots(chars))
         .or_else(|| ignore_whitespace(chars))
         .or_else(|| scan_number(chars))
         .or_else(|| scan_identifier(chars))
         .or_else(|| scan_string(chars))
 }
 
-fn ignore_whitespace(chars: &mut Chars) -> Option<(TokenType, Range<usize>)> {
+// only the whitespace tokenize skips: other Unicode spaces are errors
+fn ignore_whitespace(chars: &mut Chars) -> Option<(Scanned, Range<usize>)> {
+    let is_space = |ch| matches!(ch, ' ' | '\r' | '\t' | '\n');
     let &(start, ch) = chars.peek()?;
-    let mut end = start + 1;
-    if ch.is_whitespace() {
-        while let Some(&(n, ch)) = chars.peek() {
-            if ch.is_whitespace() {
-                end = n;
-                let _ = chars.next();
-            } else {
-                break;
-            }
-        }
-        Some((TIgnore, start..end+1))
+    if is_space(ch) {
+        Some((TIgnore, start..take_while(chars, start, is_space)))
     } else {
         None
     }
 }
 
-fn scan_simple_symbol(chars: &mut Chars) -> Option<(TokenType, Range<usize>)> {
+fn scan_simple_symbol(chars: &mut Chars) -> Option<(Scanned, Range<usize>)> {
     let &(start, ch) = chars.peek()?;
     match ch {
         '+' => accept(chars, TPlus, start),
         '-' => accept(chars, TMinus, start),
         '*' => accept(chars, TStar, start),
+        '%' => accept(chars, TPercent, start),
         '(' => accept(chars, TLeftParen, start),
         ')' => accept(chars, TRightParen, start),
         '{' => accept(chars, TLeftBrace, start),
         '}' => accept(chars, TRightBrace, start),
-        ';' => accept(chars, TSemiColon, start),
+        '[' => accept(chars, TLeftBracket, start),
+        ']' => accept(chars, TRightBracket, start),
+        ';' => accept(chars, TSemicolon, start),
+        ':' => accept(chars, TColon, start),
         ',' => accept(chars, TComma, start),
-        '.' => accept(chars, TDot, start),
         _ => None
     }
 }
 
-fn scan_compare_symbol(chars: &mut Chars) -> Option<(TokenType, Range<usize>)> {
+// '.', or "..." before a rest parameter
+fn scan_dots(chars: &mut Chars) -> Option<(Scanned, Range<usize>)> {
+    let &(start, ch) = chars.peek()?;
+    if ch != '.' {
+        return None;
+    }
+    let mut ahead = chars.clone();
+    ahead.next();
+    if ahead.next().is_some_and(|(_, ch)| ch == '.') && ahead.next().is_some_and(|(_, ch)| ch == '.') {
+        *chars = ahead;
+        Some((Kind(TEllipsis), start..start + 3))
+    } else {
+        accept(chars, TDot, start)
+    }
+}
+
+// '/', or a comment running to the end of the line
+fn scan_slash(chars: &mut Chars) -> Option<(Scanned, Range<usize>)> {
+    let &(start, ch) = chars.peek()?;
+    if ch != '/' {
+        return None;
+    }
+    chars.next();
+    if peek(chars, '/') {
+        Some((TIgnore, start..take_while(chars, start + 1, |ch| ch != '\n')))
+    } else {
+        Some((Kind(TSlash), start..start + 1))
+    }
+}
+
+fn scan_compare_symbol(chars: &mut Chars) -> Option<(Scanned, Range<usize>)> {
     let &(start, ch) = chars.peek()?;
     match ch {
         '<' => {
@@ -180,7 +191,7 @@ fn scan_compare_symbol(chars: &mut Chars) -> Option<(TokenType, Range<usize>)> {
             if peek(chars, '=') {
                 accept(chars, TLessEqual, start)
             } else {
-                Some((TLess, start..start+1))
+                Some((Kind(TLess), start..start+1))
             }
         },
         '>' => {
@@ -188,7 +199,7 @@ fn scan_compare_symbol(chars: &mut Chars) -> Option<(TokenType, Range<usize>)> {
             if peek(chars, '=') {
                 accept(chars, TGreaterEqual, start)
             } else {
-                Some((TGreater, start..start+1))
+                Some((Kind(TGreater), start..start+1))
             }
         },
         '=' => {
@@ -196,7 +207,7 @@ fn scan_compare_symbol(chars: &mut Chars) -> Option<(TokenType, Range<usize>)> {
             if peek(chars, '=') {
                 accept(chars, TEqualEqual, start)
             } else {
-                Some((TEqual, start..start+1))
+                Some((Kind(TEqual), start..start+1))
             }
         },
         '!' => {
@@ -204,85 +215,137 @@ fn scan_compare_symbol(chars: &mut Chars) -> Option<(TokenType, Range<usize>)> {
             if peek(chars, '=') {
                 accept(chars, TBangEqual, start)
             } else {
-                Some((TBang, start..start+1))
+                Some((Kind(TBang), start..start+1))
             }
         },
         _ => None
     }
 }
 
-fn scan_number(chars: &mut Chars) -> Option<(TokenType, Range<usize>)> {
+// digits, then optionally a '.' and more digits ("1." is a number too),
+// then optionally an exponent ("1e21", "1.5E-7")
+fn scan_number(chars: &mut Chars) -> Option<(Scanned, Range<usize>)> {
     let &(start, ch) = chars.peek()?;
-    let mut end = start;
-    if ch.is_digit(10) {
-        while let Some(&(n, ch)) = chars.peek() {
-            if ch.is_digit(10) {
-                end = n;
-                chars.next().unwrap();
-            } else {
-                break;
-            }
-        }
+    if ch.is_ascii_digit() {
+        let mut end = take_while(chars, start, |ch| ch.is_ascii_digit());
         if peek(chars, '.') {
-            chars.next().unwrap();
-            while let Some(&(n, ch)) = chars.peek() {
-                if ch.is_digit(10) {
-                    end = n;
-                    chars.next().unwrap();
-                } else {
-                    break;
-                }
+            let (dot, _) = chars.next().unwrap();
+            end = take_while(chars, dot + 1, |ch| ch.is_ascii_digit());
+        }
+        // the 'e' is only part of the number if digits follow it
+        let mut ahead = chars.clone();
+        if let Some((_, 'e' | 'E')) = ahead.next() {
+            if matches!(ahead.peek(), Some((_, '+' | '-'))) {
+                ahead.next();
+            }
+            if let Some(&(digit, '0'..='9')) = ahead.peek() {
+                *chars = ahead;
+                end = take_while(chars, digit, |ch| ch.is_ascii_digit());
             }
         }
-        Some((TNumber, start..end+1))
+        Some((Kind(TNumber), start..end))
     } else {
         None
     }
 }
 
-fn scan_string(chars: &mut Chars) -> Option<(TokenType, Range<usize>)> {
+fn scan_string(chars: &mut Chars) -> Option<(Scanned, Range<usize>)> {
     let &(start, ch) = chars.peek()?;
-    let mut end = start+1;
     if ch == '"' {
         chars.next().unwrap();
-        while let Some((n, ch)) = chars.next() {
-            end = n;
+        let mut end = start + 1;
+        for (n, ch) in chars.by_ref() {
+            end = n + ch.len_utf8();
             if ch == '"' {
-                break;
+                return Some((Kind(TString), start..end));
             }
         }
-        Some((TString, start..end+1))
+        Some((Unterminated, start..end))
     } else {
         None
     }
 }
 
-fn scan_identifier(chars: &mut Chars) -> Option<(TokenType, Range<usize>)> {
+fn scan_identifier(chars: &mut Chars) -> Option<(Scanned, Range<usize>)> {
     let &(start, ch) = chars.peek()?;
-    let mut end = start;
     if ch.is_alphabetic() || ch == '_' {
-        while let Some(&(n, ch)) = chars.peek() {
-            if ch.is_alphanumeric() || ch == '_' {
-                end = n;
-                chars.next().unwrap();
-            } else {
-                break;
-            }
-        }
-        Some((TIdentifier, start..end+1))
+        let end = take_while(chars, start, |ch| ch.is_alphanumeric() || ch == '_');
+        Some((Kind(TIdentifier), start..end))
     } else {
         None
     }
 }
 
 pub fn tokenize(source: Source) -> Result<Tokens, Error> {
-    // println!("Tokenizing");
     scan_tokens(source.contents)
 }
 
+// Where the two scanners disagree about source, described for a person, or
+// None if they agree. A fuzzer can feed this anything; it never panics
+// unless a scanner does. One difference is known and let through: only
+// tokenize2 lets an identifier start with '_', so those identifiers start
+// with an 'x' instead for both.
+pub fn first_difference(source: &str) -> Option<String> {
+    let mut source = source.to_string();
+    for start in underscored_identifiers(&source) {
+        source.replace_range(start..start + 1, "x");
+    }
+    let old = crate::tokenize::tokenize(Source::from(source.as_str()));
+    let new = tokenize(Source::from(source));
+    let (old, new) = match (old, new) {
+        (Ok(old), Ok(new)) => (old.tokens, new.tokens),
+        (Err(old), Err(new)) => {
+            let (old, new) = (old.to_string(), new.to_string());
+            return (old != new).then(|| format!("tokenize reports:\n{old}\ntokenize2 reports:\n{new}"));
+        }
+        (old, new) => {
+            let describe = |result: Result<Tokens, Error>| match result {
+                Ok(tokens) => format!("{} tokens", tokens.tokens.len()),
+                Err(error) => format!("errors: {error}"),
+            };
+            return Some(format!("tokenize gives {}\ntokenize2 gives {}", describe(old), describe(new)));
+        }
+    };
+    let (old, new) = (compared(&old), compared(&new));
+    let describe = |token: Option<&Compared>| match token {
+        Some((toktype, lexeme, line, column, span)) => format!("{toktype:?} {lexeme:?} at {line}:{column} (bytes {span:?})"),
+        None => "nothing".to_string(),
+    };
+    let n = (0..old.len().max(new.len())).find(|&n| old.get(n) != new.get(n))?;
+    Some(format!("token {n}: tokenize has {}\ntokenize2 has {}", describe(old.get(n)), describe(new.get(n))))
+}
+
+// what first_difference compares about a token
+type Compared<'a> = (&'a TokenType, &'a std::rc::Rc<str>, usize, usize, Span);
+
+// the type, lexeme, line, column and bytes of each token
+fn compared(tokens: &[Token]) -> Vec<Compared<'_>> {
+    tokens.iter().map(|token| (&token.toktype, &token.lexeme, token.line, token.column, token.span)).collect()
+}
+
+// where the identifiers that start with '_' are, as scan_tokens finds them
+fn underscored_identifiers(s: &str) -> Vec<usize> {
+    let mut chars = s.char_indices().peekable();
+    if s.starts_with("#!") {
+        take_while(&mut chars, 0, |ch| ch != '\n');
+    }
+    let mut starts = Vec::new();
+    while chars.peek().is_some() {
+        match scan_token(&mut chars) {
+            Some((Kind(TIdentifier), range)) if s[range.clone()].starts_with('_') => starts.push(range.start),
+            Some(_) => {}
+            None => {
+                chars.next();
+            }
+        }
+    }
+    starts
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::token::shape;
 
     #[test]
     fn its_alive() {
@@ -292,20 +355,20 @@ mod tests {
     fn single_character() {
         let tokens = scan_tokens(String::from("(){},.-+;*"));
         assert_eq!(
-            tokens.unwrap().tokens,
-            vec![
-                Token::new(TLeftParen, "(", 1),
-                Token::new(TRightParen, ")", 1),
-                Token::new(TLeftBrace, "{", 1),
-                Token::new(TRightBrace, "}", 1),
-                Token::new(TComma, ",", 1),
-                Token::new(TDot, ".", 1),
-                Token::new(TMinus, "-", 1),
-                Token::new(TPlus, "+", 1),
-                Token::new(TSemiColon, ";", 1),
-                Token::new(TStar, "*", 1),
-                Token::new(TEof, "", 1),
-            ]
+            shape(&tokens.unwrap().tokens),
+            shape(&vec![
+                Token::new(TLeftParen, "(", Literal::None, 1, 1),
+                Token::new(TRightParen, ")", Literal::None, 1, 2),
+                Token::new(TLeftBrace, "{", Literal::None, 1, 3),
+                Token::new(TRightBrace, "}", Literal::None, 1, 4),
+                Token::new(TComma, ",", Literal::None, 1, 5),
+                Token::new(TDot, ".", Literal::None, 1, 6),
+                Token::new(TMinus, "-", Literal::None, 1, 7),
+                Token::new(TPlus, "+", Literal::None, 1, 8),
+                Token::new(TSemicolon, ";", Literal::None, 1, 9),
+                Token::new(TStar, "*", Literal::None, 1, 10),
+                Token::new(TEof, "", Literal::None, 1, 11),
+            ])
         )
     }
 
@@ -313,18 +376,33 @@ mod tests {
     fn two_character() {
         let tokens = scan_tokens(String::from("! != < <= > >= == ="));
         assert_eq!(
-            tokens.unwrap().tokens,
-            vec![
-                Token::new(TBang, "!", 1),
-                Token::new(TBangEqual, "!=", 1),
-                Token::new(TLess, "<", 1),
-                Token::new(TLessEqual, "<=", 1),
-                Token::new(TGreater, ">", 1),
-                Token::new(TGreaterEqual, ">=", 1),
-                Token::new(TEqualEqual, "==", 1),
-                Token::new(TEqual, "=", 1),
-                Token::new(TEof, "", 1),
-            ]
+            shape(&tokens.unwrap().tokens),
+            shape(&vec![
+                Token::new(TBang, "!", Literal::None, 1, 1),
+                Token::new(TBangEqual, "!=", Literal::None, 1, 3),
+                Token::new(TLess, "<", Literal::None, 1, 6),
+                Token::new(TLessEqual, "<=", Literal::None, 1, 8),
+                Token::new(TGreater, ">", Literal::None, 1, 11),
+                Token::new(TGreaterEqual, ">=", Literal::None, 1, 13),
+                Token::new(TEqualEqual, "==", Literal::None, 1, 16),
+                Token::new(TEqual, "=", Literal::None, 1, 19),
+                Token::new(TEof, "", Literal::None, 1, 20),
+            ])
+        )
+    }
+
+    #[test]
+    fn crlf_lines() {
+        let tokens = scan_tokens(String::from("var\r\nx\r\n\"a\r\nb\" y")).unwrap().tokens;
+        assert_eq!(
+            shape(&tokens),
+            shape(&vec![
+                Token::new(TVar, "var", Literal::None, 1, 1),
+                Token::new(TIdentifier, "x", Literal::None, 2, 1),
+                Token::new(TString, "\"a\r\nb\"", Literal::Str("a\r\nb".to_string()), 3, 1),
+                Token::new(TIdentifier, "y", Literal::None, 4, 4),
+                Token::new(TEof, "", Literal::None, 4, 5),
+            ])
         )
     }
 
@@ -332,12 +410,12 @@ mod tests {
     fn strings() {
         let tokens = scan_tokens(String::from("\"hello\" \"world\""));
         assert_eq!(
-            tokens.unwrap().tokens,
-            vec![
-                Token::new(TString, "\"hello\"", 1),
-                Token::new(TString, "\"world\"", 1),
-                Token::new(TEof, "", 1),
-            ]
+            shape(&tokens.unwrap().tokens),
+            shape(&vec![
+                Token::new(TString, "\"hello\"", Literal::Str("hello".to_string()), 1, 1),
+                Token::new(TString, "\"world\"", Literal::Str("world".to_string()), 1, 9),
+                Token::new(TEof, "", Literal::None, 1, 16),
+            ])
         )
     }
 
@@ -345,25 +423,26 @@ mod tests {
     fn numbers() {
         let tokens = scan_tokens(String::from("12345 123.45"));
         assert_eq!(
-            tokens.unwrap().tokens,
-            vec![
-                Token::new(TNumber, "12345", 1),
-                Token::new(TNumber, "123.45", 1),
-                Token::new(TEof, "", 1),
-            ]
-        )
+            shape(&tokens.unwrap().tokens),
+            shape(&vec![
+                Token::new(TNumber, "12345", Literal::Num(12345.0), 1, 1),
+                Token::new(TNumber, "123.45", Literal::Num(123.45), 1, 7),
+                Token::new(TEof, "", Literal::None, 1, 13),
+            ])
+        );
+        assert_eq!(first_difference("1e21 1.5E-7 2e+3 3e e-1 4ex 5e+"), None);
     }
     #[test]
     fn identifiers() {
         let tokens = scan_tokens(String::from("abc def123 ab_cd"));
         assert_eq!(
-            tokens.unwrap().tokens,
-            vec![
-                Token::new(TIdentifier, "abc", 1),
-                Token::new(TIdentifier, "def123", 1),
-                Token::new(TIdentifier, "ab_cd", 1),
-                Token::new(TEof, "", 1),
-            ]
+            shape(&tokens.unwrap().tokens),
+            shape(&vec![
+                Token::new(TIdentifier, "abc", Literal::None, 1, 1),
+                Token::new(TIdentifier, "def123", Literal::None, 1, 5),
+                Token::new(TIdentifier, "ab_cd", Literal::None, 1, 12),
+                Token::new(TEof, "", Literal::None, 1, 17),
+            ])
         )
     }
     #[test]
@@ -372,26 +451,87 @@ mod tests {
             "and class else false for fun if nil or print return super this true var while",
         ));
         assert_eq!(
-            tokens.unwrap().tokens,
-            vec![
-                Token::new(TAnd, "and", 1),
-                Token::new(TClass, "class", 1),
-                Token::new(TElse, "else", 1),
-                Token::new(TFalse, "false", 1),
-                Token::new(TFor, "for", 1),
-                Token::new(TFun, "fun", 1),
-                Token::new(TIf, "if", 1),
-                Token::new(TNil, "nil", 1),
-                Token::new(TOr, "or", 1),
-                Token::new(TPrint, "print", 1),
-                Token::new(TReturn, "return", 1),
-                Token::new(TSuper, "super", 1),
-                Token::new(TThis, "this", 1),
-                Token::new(TTrue, "true", 1),
-                Token::new(TVar, "var", 1),
-                Token::new(TWhile, "while", 1),
-                Token::new(TEof, "", 1),
-            ]
+            shape(&tokens.unwrap().tokens),
+            shape(&vec![
+                Token::new(TAnd, "and", Literal::None, 1, 1),
+                Token::new(TClass, "class", Literal::None, 1, 5),
+                Token::new(TElse, "else", Literal::None, 1, 11),
+                Token::new(TFalse, "false", Literal::None, 1, 16),
+                Token::new(TFor, "for", Literal::None, 1, 22),
+                Token::new(TFun, "fun", Literal::None, 1, 26),
+                Token::new(TIf, "if", Literal::None, 1, 30),
+                Token::new(TNil, "nil", Literal::None, 1, 33),
+                Token::new(TOr, "or", Literal::None, 1, 37),
+                Token::new(TPrint, "print", Literal::None, 1, 40),
+                Token::new(TReturn, "return", Literal::None, 1, 46),
+                Token::new(TSuper, "super", Literal::None, 1, 53),
+                Token::new(TThis, "this", Literal::None, 1, 59),
+                Token::new(TTrue, "true", Literal::None, 1, 64),
+                Token::new(TVar, "var", Literal::None, 1, 69),
+                Token::new(TWhile, "while", Literal::None, 1, 73),
+                Token::new(TEof, "", Literal::None, 1, 78),
+            ])
         )
     }
+
+    #[test]
+    fn parses() {
+        // the same Tokens as tokenize, so the parser takes them
+        let tokens = scan_tokens(String::from("var x = 1 + 2;\nprint x * 3;")).unwrap();
+        let ast = crate::parser::parse(&tokens).unwrap();
+        assert_eq!(ast.top.len(), 2);
+    }
+
+    #[test]
+    fn agrees_with_tokenize() {
+        let corpus = [
+            "",
+            "var x = 1;\nprint x + 2.5;",
+            "switch case default try catch finally throw import in",
+            "fun f(a, ...rest) { return [a, {\"k\": rest}]; }",
+            "a % b // comment\n/ c // another",
+            "#!/usr/bin/env lox\nprint 1;",
+            "\"two\r\nlines\" x",
+            "print \"never closed\n",
+            "x $ y # z",
+            "café = \"naïve\"; π2",
+            "1. .5 1..2 a...b",
+            "\u{a0}x",
+            include_str!("../tests/snapshots/precedence.lox"),
+            include_str!("../tests/snapshots/var_declarations.lox"),
+            include_str!("../tests/snapshots/syntax_errors.lox"),
+        ];
+        for source in corpus {
+            if let Some(difference) = first_difference(source) {
+                panic!("the scanners disagree about {source:?}:\n{difference}");
+            }
+        }
+        // the known difference: only those identifiers are changed, and
+        // everything else is still compared, even if the source has errors
+        assert_eq!(first_difference("_x _ a_b"), None);
+        assert_eq!(first_difference("_x\n\"open"), None);
+        let source = "_x a_b \"_s\" // _c\n__y $";
+        assert_eq!(underscored_identifiers(source), [0, 18]);
+    }
+
+    #[test]
+    fn fuzz_against_tokenize() {
+        // random strings of bits of Lox, from a fixed seed
+        let pieces = [
+            "a", "_", "é", "1", ".", "/", "*", "\"", "\n", "\r", " ", "=", "!", "<", "[", "}", ";", "var", "//", "$",
+        ];
+        let mut seed: u64 = 0x2545f4914f6cdd1d;
+        for _ in 0..2000 {
+            let mut source = String::new();
+            for _ in 0..12 {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                source.push_str(pieces[(seed % pieces.len() as u64) as usize]);
+            }
+            if let Some(difference) = first_difference(&source) {
+                panic!("the scanners disagree about {source:?}:\n{difference}");
+            }
+        }
+    }
 }
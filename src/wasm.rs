@@ -0,0 +1,188 @@
+// Running Lox from a host that can't give it a terminal, like a browser
+// playground built for wasm32-unknown-unknown: the program's source goes in,
+// and what it printed plus anything that went wrong comes back out.
+//
+// run_lox and parse_lox are exported to JavaScript by wasm-bindgen; built
+// with `wasm-pack build --target web -- --no-default-features --features wasm`
+// they can be imported straight into a page.
+use serde_json::json;
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::evaluate::{self, Interpreter, SharedOutput};
+use crate::{parser, reader, tokenize};
+
+#[derive(Debug, PartialEq)]
+pub enum Stage {
+    Tokenize,
+    Parse,
+    Runtime,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Diagnostic {
+    pub stage: Stage,
+    // known for syntax errors; runtime errors carry it in the message if at all
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct RunResult {
+    pub stdout: String,
+    pub errors: Vec<Diagnostic>,
+}
+
+// The stack on wasm32 is 1MB unless the linker is told otherwise, which in a
+// release build has room for about this many nested Lox calls. Deeper than
+// that the instance would trap, rather than the program getting a Lox stack
+// overflow.
+const MAX_CALL_DEPTH: usize = 64;
+
+// Run a whole program in a fresh interpreter. Errors come back as
+// diagnostics rather than escaping. So do panics inside the interpreter
+// where panics unwind, but not on wasm32: there a panic aborts, and the
+// host has to start a new instance.
+pub fn run_to_string(src: &str) -> RunResult {
+    let stdout = SharedOutput::default();
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_max_call_depth(MAX_CALL_DEPTH);
+        interpreter.set_output(Box::new(stdout.clone()));
+        interpreter.exec(src)
+    }));
+    let errors = match result {
+        Ok(Ok(())) => Vec::new(),
+        Ok(Err(error)) => diagnostics(error),
+        Err(_) => vec![Diagnostic {
+            stage: Stage::Runtime,
+            line: None,
+            message: "Internal error in the interpreter".to_string(),
+        }],
+    };
+    RunResult { stdout: stdout.take(), errors }
+}
+
+// what the program printed, followed by a line for each error
+#[wasm_bindgen]
+pub fn run_lox(source: &str) -> String {
+    let result = run_to_string(source);
+    let mut output = result.stdout;
+    for error in result.errors {
+        output.push_str(&error.message);
+        output.push('\n');
+    }
+    output
+}
+
+// the program's syntax tree as JSON: {"statements": [...], "errors": [...]}.
+// If it doesn't parse, statements is null and each error has the "stage"
+// ("tokenize" or "parse"), "line" and "message".
+#[wasm_bindgen]
+pub fn parse_lox(source: &str) -> String {
+    let parsed = tokenize::tokenize(reader::Source::from(source))
+        .map_err(evaluate::Error::Tokenize)
+        .and_then(|tokens| parser::parse(&tokens).map_err(evaluate::Error::Parse));
+    let json = match parsed {
+        Ok(ast) => json!({"statements": ast.to_json(), "errors": []}),
+        Err(error) => {
+            let errors: Vec<_> = diagnostics(error)
+                .into_iter()
+                .map(|error| {
+                    let stage = if error.stage == Stage::Tokenize { "tokenize" } else { "parse" };
+                    json!({"stage": stage, "line": error.line, "message": error.message})
+                })
+                .collect();
+            json!({"statements": null, "errors": errors})
+        }
+    };
+    json.to_string()
+}
+
+// one diagnostic for each thing that went wrong
+fn diagnostics(error: evaluate::Error) -> Vec<Diagnostic> {
+    use crate::token::ScanError;
+    match error {
+        evaluate::Error::Tokenize(errors) => errors
+            .iter()
+            .map(|error| {
+                let (ScanError::UnexpectedCharacter { line, .. } | ScanError::UnterminatedString { line }) = error;
+                Diagnostic { stage: Stage::Tokenize, line: Some(*line), message: error.to_string() }
+            })
+            .collect(),
+        evaluate::Error::Parse(parser::Error::Multiple(errors)) => {
+            errors.into_iter().flat_map(|error| diagnostics(evaluate::Error::Parse(error))).collect()
+        }
+        evaluate::Error::Parse(error @ parser::Error::SyntaxError { line, .. }) => {
+            vec![Diagnostic { stage: Stage::Parse, line: Some(line), message: error.to_string() }]
+        }
+        error => vec![Diagnostic { stage: Stage::Runtime, line: None, message: error.to_string() }],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_success() {
+        let result = run_to_string("var a = 40;\nprint a + 2;\nprint nil;");
+        assert_eq!(result, RunResult { stdout: "42\nnil\n".to_string(), errors: vec![] });
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let result = run_to_string("print 1;\nvar = 2;\nprint 3 3;\n");
+        // nothing runs if the program doesn't parse
+        assert_eq!(result.stdout, "");
+        let lines: Vec<_> = result.errors.iter().map(|error| (&error.stage, error.line)).collect();
+        assert_eq!(lines, [(&Stage::Parse, Some(2)), (&Stage::Parse, Some(3))]);
+        assert!(result.errors[0].message.starts_with("Line 2: Syntax error"));
+
+        let result = run_to_string("print $;");
+        assert_eq!(result.errors[0].stage, Stage::Tokenize);
+    }
+
+    #[test]
+    fn test_runtime_error() {
+        let result = run_to_string("print \"before\";\nprint 1 / 0;\nprint \"after\";");
+        assert_eq!(result.stdout, "before\n");
+        assert_eq!(
+            result.errors,
+            [Diagnostic { stage: Stage::Runtime, line: None, message: "Line 2: Division by zero".to_string() }]
+        );
+
+        let result = run_to_string("print missing;");
+        assert_eq!(result.errors[0].message, "Undefined variable \"missing\"");
+    }
+
+    #[test]
+    fn test_call_depth() {
+        // on a thread with more stack than wasm32's, which a debug build needs
+        let run = |src: &'static str| {
+            let thread = std::thread::Builder::new().stack_size(64 * 1024 * 1024);
+            thread.spawn(move || run_to_string(src)).unwrap().join().unwrap()
+        };
+        let result = run("fun down(n) { if (n > 0) return 1 + down(n - 1); return 0; }\nprint down(60);");
+        assert_eq!(result, RunResult { stdout: "60\n".to_string(), errors: vec![] });
+        let result = run("fun down(n) { if (n > 0) return 1 + down(n - 1); return 0; }\nprint down(100);");
+        assert!(result.errors[0].message.starts_with("Line 1: Stack overflow"), "{:?}", result.errors);
+    }
+
+    #[test]
+    fn test_run_lox() {
+        assert_eq!(run_lox("print 1;\nprint 1 / 0;"), "1\nLine 2: Division by zero\n");
+    }
+
+    #[test]
+    fn test_parse_lox() {
+        let parsed: serde_json::Value = serde_json::from_str(&parse_lox("print nil;")).unwrap();
+        assert_eq!(parsed, json!({"statements": [{"type": "print", "expr": {"type": "nil"}}], "errors": []}));
+
+        let parsed: serde_json::Value = serde_json::from_str(&parse_lox("print 1;\nvar = 2;")).unwrap();
+        assert_eq!(parsed["statements"], serde_json::Value::Null);
+        assert_eq!(parsed["errors"][0]["stage"], "parse");
+        assert_eq!(parsed["errors"][0]["line"], 2);
+        let parsed: serde_json::Value = serde_json::from_str(&parse_lox("$")).unwrap();
+        assert_eq!(parsed["errors"][0]["stage"], "tokenize");
+    }
+}
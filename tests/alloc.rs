@@ -0,0 +1,77 @@
+// How much the front end allocates, counted by wrapping the system
+// allocator. This is its own test binary so nothing else is counted.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use lox::parser::parse;
+use lox::reader::Source;
+use lox::tokenize::tokenize;
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+// bytes in use now, and the most there have been since PEAK was last reset
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+fn grow(bytes: usize) {
+    let live = LIVE.fetch_add(bytes, Ordering::Relaxed) + bytes;
+    PEAK.fetch_max(live, Ordering::Relaxed);
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        grow(layout.size());
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+        grow(new_size);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+// the allocations made while running f, and the most bytes it had in use
+// at any one time
+fn counted<T>(f: impl FnOnce() -> T) -> (T, usize, usize) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let live = LIVE.load(Ordering::Relaxed);
+    PEAK.store(live, Ordering::Relaxed);
+    let result = f();
+    (result, ALLOCATIONS.load(Ordering::Relaxed) - allocations, PEAK.load(Ordering::Relaxed) - live)
+}
+
+#[test]
+fn large_input() {
+    // the same few names used over and over, like real code
+    let mut program = String::new();
+    for n in 0..20_000 {
+        let (name, other) = (["total", "count", "index", "value"][n % 4], ["left", "right"][n % 2]);
+        program.push_str(&format!("var {name} = {other} + {n}; print {name} * {other};\n"));
+    }
+
+    let (tokens, allocations, bytes) = counted(|| tokenize(Source::from(program.as_str())).unwrap());
+    let count = tokens.tokens.len();
+    assert_eq!(count, 20_000 * 12 + 1);
+    // a lexeme is allocated once however often it appears, so only the
+    // 20,000 different numbers cost an allocation each
+    assert!(allocations < 25_000, "{allocations} allocations for {count} tokens");
+    // mostly the tokens themselves (with their spans), plus the source as chars
+    assert!(bytes < 33 * program.len(), "{bytes} bytes in use for {} bytes of source", program.len());
+
+    // the parser shares names with the tokens rather than copying them, so
+    // each line costs its four boxed operands
+    let (_, allocations, _) = counted(|| parse(&tokens).unwrap());
+    assert!(allocations < 6 * 20_000, "{allocations} allocations to parse");
+}
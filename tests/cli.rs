@@ -0,0 +1,409 @@
+// Running the lox binary the way a shell would.
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn lox(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lox"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+// a script in a fresh temporary directory
+fn script(test: &str, name: &str, contents: &str) -> String {
+    let dir = std::env::temp_dir().join(format!("lox-cli-{test}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    std::fs::write(&path, contents).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn script_from_stdin() {
+    let output = lox(&["-"], "#!/usr/bin/env lox\nvar a = 40;\nprint a + 2;\n");
+    assert!(output.status.success());
+    assert!(stdout(&output).lines().any(|line| line == "42"));
+}
+
+#[test]
+fn piped_script() {
+    // run whole, not a line at a time: no prompts, and statements can span lines
+    let output = lox(&[], "var a = 40;\nprint a +\n  2;\n");
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "42\n");
+    // and a failure is the program's exit status
+    let output = lox(&["--no-color"], "print 1;\nprint 1 / 0;\nprint 2;\n");
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(stdout(&output), "1\n");
+}
+
+// print uses the Lox spelling, not Rust's Debug form
+#[test]
+fn print_nil() {
+    let output = lox(&["-e", "print nil; var unset; print unset;"], "");
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "nil\nnil\n");
+}
+
+#[test]
+fn eval() {
+    let output = lox(&["-e", "print 1 + 2;"], "");
+    assert!(output.status.success());
+    assert!(stdout(&output).lines().any(|line| line == "3"));
+
+    // a bare expression is echoed, as at the prompt
+    let output = lox(&["--eval", "\"a\" + \"b\""], "");
+    assert!(output.status.success());
+    assert!(stdout(&output).lines().any(|line| line == "\"ab\""));
+}
+
+#[test]
+fn exit_codes() {
+    assert_eq!(lox(&["-e", "print 1 +;"], "").status.code(), Some(65));
+    assert_eq!(lox(&["-e", "print 1 / 0;"], "").status.code(), Some(70));
+    // the resolver's errors are found before anything runs
+    let output = lox(&["-e", "print 1; print this;"], "");
+    assert_eq!(output.status.code(), Some(65));
+    assert_eq!(stdout(&output), "");
+    assert_eq!(lox(&["no-such-file.lox"], "").status.code(), Some(66));
+    assert_eq!(lox(&["-e", "print 1;", "prog.lox"], "").status.code(), Some(64));
+}
+
+#[test]
+fn several_files() {
+    let prelude = script("several", "prelude.lox", "var greeting = \"hi\";\n");
+    let main = script("several", "main.lox", "print greeting + \"!\";\n");
+    let output = lox(&[&prelude, &main], "");
+    assert!(output.status.success());
+    assert!(stdout(&output).lines().any(|line| line == "hi!"));
+
+    let broken = script("several", "broken.lox", "print greeting;\nprint 1 / 0;\n");
+    let output = lox(&[&prelude, &broken, &main], "");
+    assert_eq!(output.status.code(), Some(70));
+    assert!(stderr(&output).contains(&format!("{broken}: Line 2: Division by zero")));
+    assert!(!stdout(&output).contains("hi!"));
+}
+
+#[test]
+fn import_relative_to_script() {
+    script("import", "helpers.lox", "fun double(x) { return x * 2; }\n");
+    let main = script("import", "main.lox", "import \"helpers.lox\";\nprint double(21);\n");
+    // run from somewhere else, so only the script's own directory has helpers.lox
+    let output = Command::new(env!("CARGO_BIN_EXE_lox"))
+        .arg(&main)
+        .current_dir(std::env::temp_dir())
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).lines().any(|line| line == "42"));
+}
+
+#[test]
+fn search_path() {
+    let helpers = script("search-path", "helpers.lox", "fun double(x) { return x * 2; }\n");
+    let lib = std::path::Path::new(&helpers).parent().unwrap().to_str().unwrap();
+    let program = "import \"helpers.lox\"; print double(21);";
+    let output = lox(&["--search-path", "/no/such/dir", "--search-path", lib, "-e", program], "");
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "42\n");
+    assert!(!lox(&["-e", program], "").status.success());
+}
+
+#[test]
+fn compile_only() {
+    let good = script("check", "good.lox", "print 1 / 0;\n");
+    let output = lox(&["-c", &good], "");
+    assert_eq!(output.status.code(), Some(0));
+    // checked, not run
+    assert!(!stderr(&output).contains("Division by zero"));
+
+    let bad = script("check", "bad.lox", "var = 1;\nprint 2;\nprint 3 3;\n");
+    let output = lox(&["--compile-only", &good, &bad], "");
+    assert_eq!(output.status.code(), Some(1));
+    let errors = stderr(&output);
+    assert!(errors.contains("Line 1: Syntax error"));
+    assert!(errors.contains("Line 3: Syntax error"));
+}
+
+#[test]
+fn check_directory() {
+    let good = script("check-dir", "a.lox", "print 1 / 0;\n");
+    let dir = std::path::Path::new(&good).parent().unwrap();
+    script("check-dir", "b.lox", "var = 1;\nprint 2;\n");
+    script("check-dir", "notes.txt", "not lox");
+    std::fs::create_dir_all(dir.join("sub")).unwrap();
+    std::fs::write(dir.join("sub").join("c.lox"), "print 3 3;\n").unwrap();
+
+    let output = lox(&["--check", dir.to_str().unwrap()], "");
+    assert_eq!(output.status.code(), Some(1));
+    let errors = stderr(&output);
+    let lines: Vec<&str> = errors.lines().collect();
+    assert_eq!(lines.len(), 2, "{errors}");
+    assert!(lines[0].ends_with("b.lox: Line 1: Syntax error: Expected variable name at \"=\""));
+    assert!(lines[1].contains("c.lox: Line 1: Syntax error"));
+
+    let output = lox(&["--check", &good], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stderr(&output), "");
+}
+
+#[test]
+fn highlight() {
+    let file = script("highlight", "hl.lox", "var x; // hi\n");
+    let output = lox(&["--highlight", &file], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        stdout(&output),
+        "0\t3\tkeyword\n3\t4\twhitespace\n4\t5\tidentifier\n5\t6\toperator\n\
+         6\t7\twhitespace\n7\t12\tcomment\n12\t13\twhitespace\n"
+    );
+}
+
+#[test]
+fn trace_env() {
+    let output = lox(&["--no-warn", "--trace-env", "-e", "var a = 1; { var b = 2; }"], "");
+    assert!(output.status.success());
+    assert_eq!(stderr(&output), "a = 1\n--\nb = 2\n  a = 1\n--\na = 1\n--\n");
+}
+
+#[test]
+fn profile() {
+    let program = "fun f(n) { if (n > 0) { return f(n - 1); } return len([n]); } f(2); print 1 / 0;";
+    let output = lox(&["--profile", "-e", program], "");
+    assert_eq!(output.status.code(), Some(70));
+    let errors = stderr(&output);
+    // the summary comes out even though the program failed
+    assert!(errors.contains("calls\n     count  function\n         3  f\n         1  len\n"), "{errors}");
+    assert!(errors.contains("         3  if\n"), "{errors}");
+    assert!(errors.ends_with("Division by zero\n"));
+}
+
+#[test]
+fn stats() {
+    let output = lox(&["--stats", "-e", "print -1;"], "");
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "-1\n");
+    let errors = stderr(&output);
+    assert!(errors.starts_with("statements executed:   1\nexpressions evaluated: 2\n"), "{errors}");
+    assert!(errors.contains("  unary:               1\n"));
+    // each tail call is a call
+    let output = lox(&["--stats", "-e", "fun f(n) { if (n > 0) return f(n - 1); return 0; } f(3);"], "");
+    let errors = stderr(&output);
+    assert!(errors.starts_with("statements executed:   10\nexpressions evaluated: 31\n"), "{errors}");
+    assert!(errors.contains("  calls:               4\n"), "{errors}");
+}
+
+#[test]
+fn record_and_replay() {
+    let session = script("record", "session.lox", "");
+    let typed = lox(&["--record", &session], "var a = 3;\nprint a / 0;\na * 2;\n");
+    assert!(stderr(&typed).contains("Division by zero"));
+    let recorded = std::fs::read_to_string(&session).unwrap();
+    assert_eq!(recorded, "var a = 3;\n// error: Line 1: Division by zero\n// print a / 0;\na * 2;\n");
+
+    let replayed = lox(&["--replay", &session], "ignored;\n");
+    assert!(replayed.status.success());
+    assert_eq!(stderr(&replayed), "");
+    assert_eq!(stdout(&replayed), "> var a = 3;\n> // error: Line 1: Division by zero\n> // print a / 0;\n> a * 2;\n6\n> ");
+    assert_eq!(lox(&["--replay", "missing.lox"], "").status.code(), Some(66));
+}
+
+#[test]
+fn help_and_version() {
+    let help = lox(&["--help"], "");
+    assert!(help.status.success());
+    assert!(stdout(&help).starts_with("Usage: lox"));
+    assert!(stdout(&help).contains("  --tokens "));
+    assert_eq!(stdout(&lox(&["-V"], "")), format!("lox {}\n", env!("CARGO_PKG_VERSION")));
+}
+
+#[test]
+fn bad_arguments() {
+    let output = lox(&["--no-color", "--frobnicate"], "");
+    assert_eq!(output.status.code(), Some(64));
+    assert!(stderr(&output).starts_with("Unknown option --frobnicate\nUsage: lox"));
+    let output = lox(&["--no-color", "--backend", "jit", "-e", "print 1;"], "");
+    assert_eq!(output.status.code(), Some(64));
+    assert!(stderr(&output).starts_with("Unknown backend jit\n"));
+    assert!(lox(&["--backend", "tree-walk", "-e", "print 1;"], "").status.success());
+}
+
+#[test]
+fn max_call_depth() {
+    let program = "fun down(n) { if (n > 0) { down(n - 1); } } down(20); print \"done\";";
+    assert_eq!(stdout(&lox(&["--max-call-depth", "30", "-e", program], "")), "done\n");
+    let output = lox(&["--no-color", "--max-call-depth", "10", "-e", program], "");
+    assert_eq!(output.status.code(), Some(70));
+    assert!(stderr(&output).contains("Stack overflow"), "{}", stderr(&output));
+    let output = lox(&["--no-color", "--max-call-depth", "0", "-e", program], "");
+    assert_eq!(output.status.code(), Some(64));
+    assert!(stderr(&output).starts_with("--max-call-depth needs a positive whole number, not 0\n"));
+    // 200 without the flag
+    let program = "fun down(n) { if (n > 0) { down(n - 1); } }";
+    assert_eq!(stdout(&lox(&["-e", &format!("{program} down(199); print \"done\";")], "")), "done\n");
+    let output = lox(&["--no-color", "-e", &format!("{program} down(200);")], "");
+    assert!(stderr(&output).contains("Stack overflow"), "{}", stderr(&output));
+}
+
+#[test]
+fn max_call_depth_has_the_stack_for_it() {
+    // a stack overflow in Rust rather than Lox aborts
+    let program = "fun down(n) { while (true) { if (n > 0) { { var r = [down(n - 1)]; return r; } } return 0; } }";
+    let output = lox(&["--max-call-depth", "10000", "-e", &format!("{program} down(9990); print \"done\";")], "");
+    assert_eq!(stdout(&output), "done\n", "{}", stderr(&output));
+    let output = lox(&["--no-color", "--max-call-depth", "10000", "-e", &format!("{program} down(10000);")], "");
+    assert_eq!(output.status.code(), Some(70));
+    assert!(stderr(&output).contains("Stack overflow"), "{}", stderr(&output));
+    let output = lox(&["--no-color", "--max-call-depth", "100000", "-e", "print 1;"], "");
+    assert_eq!(output.status.code(), Some(64));
+}
+
+#[test]
+fn warnings() {
+    let program = script("warnings", "prog.lox", "fun f() {\n  var unused = 1;\n}\nprint 2;\n");
+    let output = lox(&["--no-color", &program], "");
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "2\n");
+    assert!(stderr(&output).ends_with("prog.lox: Line 2: Warning: Local variable 'unused' is never used\n"), "{}", stderr(&output));
+    let output = lox(&["--no-color", "--no-warn", &program], "");
+    assert_eq!((stdout(&output).as_str(), stderr(&output).as_str()), ("2\n", ""));
+    // nothing runs if warnings are errors
+    for flag in ["-Werror", "--warn-error"] {
+        let output = lox(&["--no-color", flag, &program], "");
+        assert_eq!(output.status.code(), Some(65));
+        assert_eq!(stdout(&output), "");
+        assert!(stderr(&output).ends_with("prog.lox: Line 2: Warning: Local variable 'unused' is never used\n"));
+    }
+    assert!(lox(&["-Werror", "-e", "fun f() { var used = 1; return used; } print f();"], "").status.success());
+}
+
+#[test]
+fn error_underlined() {
+    let output = lox(&["--no-color", "-e", "var a = 1;\nprint -(2 * (a + (nil)));"], "");
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(
+        stderr(&output),
+        "Line 2: Operands must be two numbers or two strings: 1 + nil (number + nil)\n\
+         2 | print -(2 * (a + (nil)));\n  |              ^^^^^^^^^\n"
+    );
+    // spans in eval() code and imported files aren't in the program's text,
+    // so there's nothing to underline
+    for program in ["var é = 1; eval(\"     1 + nil;\");", "print \"hello there\";\neval(\"1 + nil;\");"] {
+        let output = lox(&["--no-color", "-e", program], "");
+        assert_eq!(output.status.code(), Some(70));
+        assert_eq!(stderr(&output), "Line 1: Operands must be two numbers or two strings: 1 + nil (number + nil)\n");
+    }
+    script("underline", "module.lox", "fun add(a) {\n  return a + nil;\n}\n");
+    let program = script("underline", "prog.lox", "import \"module.lox\";\nprint add(1);\n");
+    let output = lox(&["--no-color", &program], "");
+    assert_eq!(output.status.code(), Some(70));
+    let errors = stderr(&output);
+    assert!(errors.starts_with(&format!("{program}: Line 2: Operands must be two numbers or two strings")), "{errors}");
+    assert!(!errors.contains(" | "), "{errors}");
+}
+
+#[test]
+fn strict() {
+    let fixture = "var total = 0;\nvar total = 1;\nfun f() {\n  var unused;\n  count = 1;\n}\nf();\nprint count;\n";
+    let program = script("strict", "prog.lox", fixture);
+    let output = lox(&["--no-color", &program], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "1\n");
+    assert_eq!(stderr(&output), format!("{program}: Line 4: Warning: Local variable 'unused' is never used\n"));
+
+    let output = lox(&["--no-color", "--strict", &program], "");
+    assert_eq!(output.status.code(), Some(65));
+    assert_eq!(stdout(&output), "");
+    assert_eq!(stderr(&output), format!("{program}: Line 2: Syntax error: 'total' is already declared in this scope\n"));
+    // without the redeclaration, the warning stops it
+    let program = script("strict", "warned.lox", &fixture.replace("var total = 1;", "total = 1;"));
+    let output = lox(&["--no-color", "--strict", &program], "");
+    assert_eq!(output.status.code(), Some(65));
+    assert_eq!(stderr(&output), format!("{program}: Line 4: Warning: Local variable 'unused' is never used\n"));
+    // and without that, the undeclared count
+    let program = script("strict", "undeclared.lox", &fixture.replace("var total = 1;", "total = 1;").replace("  var unused;\n", ""));
+    let output = lox(&["--no-color", "--strict", &program], "");
+    assert_eq!(output.status.code(), Some(70));
+    assert!(stderr(&output).starts_with(&format!("{program}: Undefined variable \"count\"")), "{}", stderr(&output));
+
+    // at the prompt too
+    let session = script("strict", "session.lox", "count = 1;\nprint count;\n");
+    assert_eq!(stdout(&lox(&["--replay", &session], "")), "> count = 1;\n1\n> print count;\n1\n> ");
+    let replayed = lox(&["--no-color", "--strict", "--replay", &session], "");
+    assert_eq!(stdout(&replayed), "> count = 1;\n> print count;\n> ");
+    assert!(stderr(&replayed).starts_with("Undefined variable \"count\"\n"), "{}", stderr(&replayed));
+    let session = script("strict", "redeclared.lox", "var a = 1; var a = 2;\nprint a;\n");
+    let replayed = lox(&["--no-color", "--strict", "--replay", &session], "");
+    assert_eq!(stdout(&replayed), "> var a = 1; var a = 2;\n> print a;\n> ");
+    assert!(stderr(&replayed).contains("'a' is already declared in this scope"), "{}", stderr(&replayed));
+    // and in what the program imports
+    script("strict", "module.lox", "var b = 1;\nvar b = 2;\n");
+    let program = script("strict", "imports.lox", "import \"module.lox\";\n");
+    let output = lox(&["--no-color", "--strict", &program], "");
+    assert_eq!(output.status.code(), Some(70));
+    assert!(stderr(&output).contains("'b' is already declared in this scope"), "{}", stderr(&output));
+}
+
+#[test]
+fn coverage() {
+    let program = script("coverage", "prog.lox", "var x = 2;\nif (x > 1) {\n  print \"big\";\n} else {\n  print \"small\";\n}\n");
+    let output = lox(&["--coverage", &program], "");
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "big\n");
+    assert_eq!(
+        stderr(&output),
+        "✓ 1 | var x = 2;\n✓ 2 | if (x > 1) {\n✓ 3 |   print \"big\";\n  4 | } else {\n  5 |   print \"small\";\n  6 | }\n\
+         3 of 5 lines run (60.0%)\n"
+    );
+    assert_eq!(lox(&["--coverage"], "").status.code(), Some(64));
+}
+
+#[test]
+fn lenient() {
+    assert_eq!(lox(&["-e", "print \"a\" + 1;"], "").status.code(), Some(70));
+    let output = lox(&["--lenient", "-e", "print \"a\" + 1;"], "");
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "a1\n");
+}
+
+#[test]
+fn no_color() {
+    let output = lox(&["--no-color", "-e", "print 1 / 0;"], "");
+    assert!(!stderr(&output).contains('\x1b'));
+    assert!(stderr(&output).ends_with("Division by zero\n"));
+}
+
+#[test]
+fn debug_tokens_and_ast() {
+    let output = lox(&["--tokens", "--ast", "-e", "print 1;"], "");
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "1\n");
+    let errors = stderr(&output);
+    assert!(errors.starts_with("1\tTPrint\tprint\n1\tTNumber\t1\n1\tTSemicolon\t;\n"), "{errors}");
+    assert!(errors.ends_with("SPrint { expr: ENumber { value: 1.0, id: #1, span: 6..7 }, id: #2, span: 0..8 }\n"), "{errors}");
+}
+
+#[test]
+fn missing_file() {
+    let output = lox(&["--no-color", "nosuchfile.lox"], "");
+    assert_eq!(output.status.code(), Some(66));
+    assert_eq!(stderr(&output), "could not read 'nosuchfile.lox': No such file or directory. Check the filename.\n");
+    // the files before it have run by the time it's found missing
+    let first = script("missing", "first.lox", "print 1;");
+    let output = lox(&["--no-color", &first, "nosuchfile.lox"], "");
+    assert_eq!(output.status.code(), Some(66));
+    assert_eq!(stdout(&output), "1\n");
+}
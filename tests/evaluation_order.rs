@@ -0,0 +1,78 @@
+// The order expressions are evaluated in, made visible by side effects.
+//
+// Each case calls t(name, value), which appends name to a log and gives
+// back value, so the log shows what ran and in what order. Operands and
+// arguments go strictly left to right; `and` and `or` stop as soon as the
+// left side decides.
+use lox::evaluate::{Interpreter, LoxValue};
+
+const PRELUDE: &str = "
+    var log = \"\";
+    fun t(name, value) { log = log + name; return value; }
+    class Box {}
+    var box = Box();
+    fun pair(a, b) { return [a, b]; }";
+
+// the log after evaluating source, and the value it gave
+fn run(source: &str) -> (String, String) {
+    let mut interp = Interpreter::new();
+    interp.exec(PRELUDE).unwrap();
+    let value = interp.eval_expr(source).unwrap_or_else(|e| panic!("{source}: {e}"));
+    (interp.get_global("log").unwrap().to_string(), value.repr())
+}
+
+fn order(source: &str) -> String {
+    run(source).0
+}
+
+#[test]
+fn binary_operands() {
+    assert_eq!(order("t(\"a\", 1) + t(\"b\", 2)"), "ab");
+    assert_eq!(order("t(\"a\", 1) < t(\"b\", 2)"), "ab");
+    assert_eq!(order("t(\"a\", 1) == t(\"b\", 2)"), "ab");
+    // precedence decides the grouping, not the order operands run in
+    assert_eq!(order("t(\"a\", 1) + t(\"b\", 2) * t(\"c\", 3)"), "abc");
+    assert_eq!(order("t(\"a\", 1) * t(\"b\", 2) + t(\"c\", 3)"), "abc");
+    assert_eq!(order("-t(\"a\", 1) - -t(\"b\", 2)"), "ab");
+}
+
+#[test]
+fn logical_short_circuit() {
+    assert_eq!(run("t(\"a\", false) and t(\"b\", true)"), ("a".to_string(), "false".to_string()));
+    assert_eq!(run("t(\"a\", 1) and t(\"b\", 2)"), ("ab".to_string(), "2".to_string()));
+    assert_eq!(run("t(\"a\", 1) or t(\"b\", 2)"), ("a".to_string(), "1".to_string()));
+    assert_eq!(run("t(\"a\", nil) or t(\"b\", 2)"), ("ab".to_string(), "2".to_string()));
+    assert_eq!(order("t(\"a\", nil) and t(\"b\", 1) or t(\"c\", 2)"), "ac");
+}
+
+#[test]
+fn calls() {
+    assert_eq!(run("pair(t(\"a\", 1), t(\"b\", 2))"), ("ab".to_string(), "[1, 2]".to_string()));
+    // the callee is found before any argument runs
+    assert_eq!(order("t(\"f\", pair)(t(\"a\", 1), t(\"b\", 2))"), "fab");
+    assert_eq!(order("len(pair(t(\"a\", 1), t(\"b\", t(\"c\", 2))))"), "acb");
+}
+
+#[test]
+fn literals() {
+    assert_eq!(order("[t(\"a\", 1), t(\"b\", 2), t(\"c\", 3)]"), "abc");
+    assert_eq!(order("{t(\"k\", 1): t(\"v\", 2), t(\"K\", 3): t(\"V\", 4)}"), "kvKV");
+}
+
+#[test]
+fn properties() {
+    assert_eq!(order("t(\"o\", box).x = t(\"v\", 1)"), "ov");
+    assert_eq!(order("t(\"o\", box).y = t(\"v\", 1) + t(\"w\", 2)"), "ovw");
+}
+
+#[test]
+fn assignments() {
+    // the left operand is read before the right one assigns
+    let mut interp = Interpreter::new();
+    interp.exec("var a = 0; var r = a + (a = 2);").unwrap();
+    assert_eq!(interp.get_global("r"), Some(LoxValue::LNumber(2.0)));
+    interp.exec("a = 1; a = 1 + (a = 2);").unwrap();
+    assert_eq!(interp.get_global("a"), Some(LoxValue::LNumber(3.0)));
+    interp.exec("var b = 1; var s = (b = b + 1) * (b = b * 10);").unwrap();
+    assert_eq!(interp.get_global("s"), Some(LoxValue::LNumber(40.0)));
+}
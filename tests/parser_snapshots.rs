@@ -0,0 +1,49 @@
+// The syntax tree for each program in tests/snapshots, compared with the
+// .snap file beside it: the AST's Debug form, one statement at a time, or
+// the syntax errors if it doesn't parse.
+//
+// When the grammar changes a tree on purpose, regenerate them with
+//     LOX_UPDATE_SNAPSHOTS=1 cargo test --test parser_snapshots
+// and check the diff.
+use std::path::{Path, PathBuf};
+
+use lox::parser::{parse, Error};
+use lox::reader::Source;
+use lox::tokenize::tokenize;
+
+fn render(program: &str) -> String {
+    let tokens = tokenize(Source::from(program)).expect("fixtures should tokenize");
+    match parse(&tokens) {
+        Ok(ast) => ast.top.iter().map(|stmt| format!("{stmt:#?}\n")).collect(),
+        Err(Error::Multiple(errors)) => errors.iter().map(|error| format!("{error:?}\n")).collect(),
+        Err(error) => format!("{error:?}\n"),
+    }
+}
+
+fn fixtures() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots");
+    let mut fixtures: Vec<PathBuf> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "lox"))
+        .collect();
+    fixtures.sort();
+    fixtures
+}
+
+#[test]
+fn parser_snapshots() {
+    let update = std::env::var_os("LOX_UPDATE_SNAPSHOTS").is_some();
+    let mut changed = Vec::new();
+    for fixture in fixtures() {
+        let rendered = render(&std::fs::read_to_string(&fixture).unwrap());
+        let snapshot = fixture.with_extension("snap");
+        if update {
+            std::fs::write(&snapshot, &rendered).unwrap();
+        } else if std::fs::read_to_string(&snapshot).ok().as_ref() != Some(&rendered) {
+            eprintln!("{} now parses as:\n{rendered}", fixture.display());
+            changed.push(snapshot);
+        }
+    }
+    assert!(changed.is_empty(), "snapshots differ (LOX_UPDATE_SNAPSHOTS=1 regenerates them): {changed:?}");
+}